@@ -7,7 +7,7 @@ use settings::SettingsBlock;
 use tabs::TabsBlock;
 use info::InfoBlock;
 
-use crate::component::block::{dialogs::DialogBlock, results::ResultsBlock, search::SearchBlock, waves::WavesBlock};
+use crate::component::block::{dialogs::DialogBlock, playing::PlayingBlock, results::ResultsBlock, search::SearchBlock, waves::WavesBlock};
 
 use super::{layer, popup::{help::HelpPopup, set_popup, PopupComponent}};
 
@@ -61,6 +61,7 @@ pub fn handle_key(block_id: u8, event: KeyEvent) -> bool {
 			DialogBlock::ID => DialogBlock::instance().handle_key(event),
 			SearchBlock::ID => SearchBlock::instance().handle_key(event),
 			ResultsBlock::ID => ResultsBlock::instance().handle_key(event),
+			PlayingBlock::ID => PlayingBlock::instance().handle_key(event),
 			_ => false,
 		}
 	}
@@ -76,6 +77,7 @@ pub fn navigate_block(block_id: u8, dx: i16, dy: i16) -> u8 {
 		DialogBlock::ID => DialogBlock::instance().navigate_block(dx, dy),
 		SearchBlock::ID => SearchBlock::instance().navigate_block(dx, dy),
 		ResultsBlock::ID => ResultsBlock::instance().navigate_block(dx, dy),
+		PlayingBlock::ID => PlayingBlock::instance().navigate_block(dx, dy),
 		_ => block_id
 	}
 }
@@ -6,7 +6,7 @@ use rand::Rng;
 use ratatui::{Frame, layout::Rect, style::{Color, Modifier, Style}, text::{Line, Span}, widgets::{Block, Borders, Padding, Paragraph}};
 use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
 
-use crate::{component::{block::{BlockHandleKey, BlockNavigation, BlockRenderArea, BlockSingleton, loop_index, settings::SettingsBlock, tabs::TabsBlock}, popup::{PopupComponent, confirm::ConfirmPopup, dialog::DialogPopup, input::{FLAG_NONE, InputPopup}, key_bind::{KeyBindFor, KeyBindPopup}, set_popup}}, state::acquire, util::dialog::Dialog};
+use crate::{component::{block::{BlockHandleKey, BlockNavigation, BlockRenderArea, BlockSingleton, loop_index, playing::PlayingBlock, settings::SettingsBlock, tabs::TabsBlock}, popup::{PopupComponent, confirm::ConfirmPopup, dialog::DialogPopup, effects::{EffectTarget, EffectsPopup}, input::{FLAG_NONE, InputPopup}, key_bind::{KeyBindFor, KeyBindPopup}, set_popup}}, state::acquire, util::dialog::Dialog};
 
 pub struct DialogBlock {
 	range: (i32, i32),
@@ -99,7 +99,9 @@ impl BlockHandleKey for DialogBlock {
 			KeyCode::Char('/') => self.play_dialog(true),
 			KeyCode::Char('a') => self.add_dialog(),
 			KeyCode::Char('e') => self.edit_dialog(),
+			KeyCode::Char('c') => self.edit_effects(),
 			KeyCode::Char('r') => self.rename_dialog(),
+			KeyCode::Char('g') => self.set_group(),
 			KeyCode::Char('d') => self.delete_dialog(),
 			KeyCode::Char('f') => self.duplicate_dialog(),
 			KeyCode::Char('x') => self.set_global_key_bind(),
@@ -122,6 +124,9 @@ impl BlockNavigation for DialogBlock {
 		if dy < 0 {
 			return TabsBlock::ID;
 		}
+		if dy > 0 && PlayingBlock::is_visible() {
+			return PlayingBlock::ID;
+		}
 		if dx > 0 && acquire().settings_opened {
 			return SettingsBlock::ID;
 		}
@@ -188,6 +193,14 @@ impl DialogBlock {
 		true
 	}
 
+	fn edit_effects(&self) -> bool {
+		if self.selected >= { acquire().dialogs.len() } {
+			return false;
+		}
+		set_popup(PopupComponent::Effects(EffectsPopup::new(EffectTarget::Dialog(self.selected))));
+		true
+	}
+
 	fn rename_dialog(&self) -> bool {
 		set_popup(PopupComponent::Input(InputPopup::new(acquire().dialogs[self.selected].label.clone(), "Dialog Label".to_string(), FLAG_NONE, |value| {
 			let name = value.to_string();
@@ -200,6 +213,19 @@ impl DialogBlock {
 		true
 	}
 
+	fn set_group(&self) -> bool {
+		let init = acquire().dialogs[self.selected].group.clone().unwrap_or_default();
+		set_popup(PopupComponent::Input(InputPopup::new(init, "Choke Group (empty for none)".to_string(), FLAG_NONE, |value| {
+			let group = Some(value.trim().to_string()).filter(|group| !group.is_empty());
+			let mut app = acquire();
+			let selected = { DialogBlock::instance().selected };
+			app.dialogs[selected].group = group.clone();
+			app.config.dialogs[selected].group = group;
+			true
+		})));
+		true
+	}
+
 	fn delete_dialog(&self) -> bool {
 		set_popup(PopupComponent::Confirm(ConfirmPopup::new("Delete dialog?", "delete", || {
 			let mut app = acquire();
@@ -1,6 +1,6 @@
 use std::{cmp::{max, min}, collections::HashSet, i32, path::Path, sync::{Arc, Mutex, MutexGuard, OnceLock}};
 
-use crate::{component::{block::{BlockNavigation, BlockSingleton, settings::SettingsBlock, tabs::TabsBlock}, popup::{PopupComponent, input::{FLAG_INT, InputPopup}, key_bind::{KeyBindFor, KeyBindPopup}, set_popup}}, config::FileEntry, state::{Scanning, acquire}, util::{file::play_file_auto_volume, tab::{scan, selected_file_path}}};
+use crate::{component::{block::{BlockNavigation, BlockSingleton, playing::PlayingBlock, settings::SettingsBlock, tabs::TabsBlock}, popup::{PopupComponent, effects::{EffectTarget, EffectsPopup}, input::{FLAG_INT, FLAG_NONE, InputPopup}, key_bind::{KeyBindFor, KeyBindPopup}, set_popup, trim::TrimPopup}}, config::FileEntry, state::{Scanning, acquire}, util::{file::{PlaybackMode, Resampling, clear_cached_files, play_file_auto_volume}, tab::{scan, selected_file_path}}};
 
 use super::{loop_index, BlockHandleKey, BlockRenderArea};
 
@@ -80,8 +80,13 @@ impl BlockRenderArea for FilesBlock {
 						} else {
 							spans.push(Span::from("K").style(Style::default().fg(Color::LightGreen).add_modifier(Modifier::REVERSED)));
 						}
+						spans.push(match entry.mode {
+							PlaybackMode::OneShot => Span::from(" "),
+							PlaybackMode::Hold => Span::from("H").style(Style::default().fg(Color::LightMagenta).add_modifier(Modifier::REVERSED)),
+							PlaybackMode::Toggle => Span::from("T").style(Style::default().fg(Color::LightMagenta).add_modifier(Modifier::REVERSED)),
+						});
 					} else {
-						spans.push(Span::from("  "));
+						spans.push(Span::from("   "));
 					}
 					spans.push(Span::from(" "));
 					let style = if duration.is_empty() {
@@ -136,6 +141,15 @@ impl BlockHandleKey for FilesBlock {
 			KeyCode::Char('z') => self.unset_global_key_bind(),
 			KeyCode::Char('v') => self.set_file_id(),
 			KeyCode::Char('b') => self.unset_file_id(),
+			KeyCode::Char('t') => self.trim_file(),
+			KeyCode::Char('m') => self.cycle_playback_mode(),
+			KeyCode::Char('u') => self.cycle_resampling(),
+			KeyCode::Char('c') => self.edit_effects(),
+			KeyCode::Char('p') => self.toggle_ducker(),
+			KeyCode::Char('o') => self.cycle_retrigger(),
+			KeyCode::Char('n') => self.set_max_voices(),
+			KeyCode::Char('k') => self.set_cooldown(),
+			KeyCode::Char('g') => self.set_group(),
 			KeyCode::PageUp => self.navigate_file(-(self.range.1 - self.range.0 + 1)),
 			KeyCode::PageDown => self.navigate_file(self.range.1 - self.range.0 + 1),
 			KeyCode::Home => self.navigate_file(-i32::MAX),
@@ -152,6 +166,9 @@ impl BlockNavigation for FilesBlock {
 		if dy < 0 {
 			return TabsBlock::ID;
 		}
+		if dy > 0 && PlayingBlock::is_visible() {
+			return PlayingBlock::ID;
+		}
 		if dx > 0 && acquire().settings_opened {
 			return SettingsBlock::ID;
 		}
@@ -183,7 +200,7 @@ impl FilesBlock {
 			} else {
 				Arc::new(Mutex::new(()))
 			};
-			play_file_auto_volume(&Path::new(&tab).join(&files[index].0).into_os_string().into_string().unwrap(), lock);
+			play_file_auto_volume(&Path::new(&tab).join(&files[index].0).into_os_string().into_string().unwrap(), false, lock);
 			true
 		});
 	}
@@ -304,5 +321,125 @@ impl FilesBlock {
 		app.file_ids.remove(&id);
 		true
 	}
+
+	fn trim_file(&self) -> bool {
+		let app = acquire();
+		let path = selected_file_path(&app.config.tabs, &app.files, Some(self.selected));
+		if path.is_empty() {
+			return false;
+		}
+		let tab = &app.config.tabs[TabsBlock::instance().selected];
+		let duration = app.files.get(tab).map_or(String::new(), |files| files[self.selected].1.clone());
+		drop(app);
+		set_popup(PopupComponent::Trim(TrimPopup::new(path, duration)));
+		true
+	}
+
+	fn cycle_playback_mode(&self) -> bool {
+		update_selected_entry(Some(self.selected), |entry| entry.mode = entry.mode.next())
+	}
+
+	fn cycle_retrigger(&self) -> bool {
+		update_selected_entry(Some(self.selected), |entry| entry.retrigger = entry.retrigger.next())
+	}
+
+	fn set_max_voices(&self) -> bool {
+		let init = {
+			let app = acquire();
+			let path = selected_file_path(&app.config.tabs, &app.files, Some(self.selected));
+			if path.is_empty() {
+				return false;
+			}
+			app.config.get_file_entry(&path).map_or(0, |entry| entry.max_voices).to_string()
+		};
+		set_popup(PopupComponent::Input(InputPopup::new(init, "Max Voices (0 for no limit)".to_string(), FLAG_INT, |value| {
+			let Ok(voices) = value.parse::<u32>() else { return false; };
+			update_selected_entry(None, |entry| entry.max_voices = voices)
+		})));
+		true
+	}
+
+	fn set_cooldown(&self) -> bool {
+		let init = {
+			let app = acquire();
+			let path = selected_file_path(&app.config.tabs, &app.files, Some(self.selected));
+			if path.is_empty() {
+				return false;
+			}
+			app.config.get_file_entry(&path).map_or(0, |entry| entry.cooldown).to_string()
+		};
+		set_popup(PopupComponent::Input(InputPopup::new(init, "Retrigger Cooldown (ms)".to_string(), FLAG_INT, |value| {
+			let Ok(cooldown) = value.parse::<u32>() else { return false; };
+			update_selected_entry(None, |entry| entry.cooldown = cooldown)
+		})));
+		true
+	}
+
+	fn set_group(&self) -> bool {
+		let init = {
+			let app = acquire();
+			let path = selected_file_path(&app.config.tabs, &app.files, Some(self.selected));
+			if path.is_empty() {
+				return false;
+			}
+			app.config.get_file_entry(&path).and_then(|entry| entry.group.clone()).unwrap_or_default()
+		};
+		set_popup(PopupComponent::Input(InputPopup::new(init, "Choke Group (empty for none)".to_string(), FLAG_NONE, |value| {
+			let group = Some(value.trim().to_string()).filter(|group| !group.is_empty());
+			update_selected_entry(None, |entry| entry.group = group)
+		})));
+		true
+	}
+
+	// Duckers lower every other sound while they play
+	fn toggle_ducker(&self) -> bool {
+		update_selected_entry(Some(self.selected), |entry| entry.duck = !entry.duck)
+	}
+
+	fn edit_effects(&self) -> bool {
+		let path = {
+			let app = acquire();
+			selected_file_path(&app.config.tabs, &app.files, Some(self.selected))
+		};
+		if path.is_empty() {
+			return false;
+		}
+		set_popup(PopupComponent::Effects(EffectsPopup::new(EffectTarget::File(path))));
+		true
+	}
+
+	// Cycles between the global quality and the file's own
+	fn cycle_resampling(&self) -> bool {
+		let mut app = acquire();
+		let path = selected_file_path(&app.config.tabs, &app.files, Some(self.selected));
+		if path.is_empty() {
+			return false;
+		}
+		let next = |resample: Option<Resampling>| match resample {
+			None => Some(Resampling::Low),
+			Some(Resampling::Low) => Some(Resampling::High),
+			Some(Resampling::High) => None,
+		};
+		app.config.update_file_entry(path.clone(), |entry| {
+			entry.resample = next(entry.resample);
+			true
+		});
+		drop(app);
+		clear_cached_files(|cached| cached == path);
+		true
+	}
 }
 
+// Changes the entry of the selected file. Pass the selection when the block is already locked.
+fn update_selected_entry(selected: Option<usize>, apply: impl FnOnce(&mut FileEntry)) -> bool {
+	let mut app = acquire();
+	let path = selected_file_path(&app.config.tabs, &app.files, selected);
+	if path.is_empty() {
+		return false;
+	}
+	app.config.update_file_entry(path, |entry| {
+		apply(entry);
+		true
+	});
+	true
+}
@@ -4,7 +4,7 @@ use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use ratatui::{layout::Rect, style::{Color, Modifier, Style}, text::{Line, Span, Text}, widgets::{Block, Borders, Padding, Paragraph}, Frame};
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 
-use crate::{component::block::{BlockNavigation, BlockSingleton, dialogs::DialogBlock, results::{ResultsBlock, SearchResult}, search::SearchBlock, tabs::TabsBlock, waves::WavesBlock}, config::FileEntry, state::{MainOpened, acquire}, util::{keyboard::{keyboard_to_string, sort_keys}, tab::selected_file_path}};
+use crate::{component::block::{BlockNavigation, BlockSingleton, dialogs::DialogBlock, results::{ResultsBlock, SearchResult}, search::SearchBlock, tabs::TabsBlock, waves::WavesBlock}, config::FileEntry, state::{MainOpened, acquire}, util::{audio::set_mix_settings, file::Retrigger, keyboard::{keyboard_to_string, sort_keys}, pan::pan_label, tab::selected_file_path}};
 
 use super::{loop_index, BlockHandleKey, BlockRenderArea};
 
@@ -17,7 +17,7 @@ impl BlockSingleton for InfoBlock {
 	fn instance() -> MutexGuard<'static, Self> {
 		static BLOCK: LazyLock<Mutex<InfoBlock>> = LazyLock::new(|| { Mutex::new(InfoBlock {
 			selected: 0,
-			options: 2
+			options: 8
 		}) });
 		BLOCK.lock().unwrap()
 	}
@@ -45,21 +45,40 @@ impl BlockRenderArea for InfoBlock {
 						Span::from("Selected "),
 						Span::from(path.clone()).style(Style::default().fg(Color::LightGreen))
 					]));
-					let (volume, hotkey, file_id) = match app.config.get_file_entry(&path) {
+					let (volume, hotkey, file_id, fade_in, fade_out, speed, pitch, stretch, pan) = match app.config.get_file_entry(&path) {
 						Some(entry) => (entry.volume, if entry.keys.is_empty() { None } else {
 							let mut keys = entry.keys.clone().into_iter().collect::<Vec<String>>();
 							let keys = sort_keys(&mut keys);
 							Some(format!("{{{}}}", keys.join(" ")))
-						}, entry.id),
-						None => (100, None, None)
+						}, entry.id, entry.fade_in, entry.fade_out, entry.speed, entry.pitch, entry.stretch, entry.pan),
+						None => (100, None, None, 0, 0, 100, 0, false, 0)
 					};
 					lines.push(volume_line("File Volume".to_string(), volume, area.width, self.selected == 1));
+					// In the order Up and Down select them
+					lines.push(fade_line(fade_in, fade_out, self.selected));
+					lines.push(shift_line(speed, pitch, stretch, self.selected));
+					lines.push(pan_line(pan, None, self.selected, 7));
 					let mut spans = vec![];
 					spans.push(Span::from("ID "));
 					spans.push(file_id.map_or( Span::from("None").style(Style::default().fg(Color::Red)), |id| { Span::from(format!(" {} ", id)).style(Style::default().fg(Color::LightYellow).add_modifier(Modifier::REVERSED)) }));
 					spans.push(Span::from(" | Keys "));
 					spans.push(hotkey.map_or(Span::from("None").style(Style::default().fg(Color::Red)), |keys| { Span::from(format!(" {} ", keys)).style(Style::default().fg(Color::LightGreen).add_modifier(Modifier::REVERSED)) }));
 					lines.push(Line::from(spans));
+					let resampling = match app.config.get_file_entry(&path).and_then(|entry| entry.resample) {
+						Some(resample) => resample.label().to_string(),
+						None => format!("{} (global)", app.config.resample_quality.label())
+					};
+					let duck = app.config.get_file_entry(&path).is_some_and(|entry| entry.duck);
+					lines.push(Line::from(format!("Resampling {} | Ducks Others {}", resampling, if duck { "On" } else { "Off" })));
+					let (retrigger, max_voices, cooldown) = app.config.get_file_entry(&path).map_or((Retrigger::Overlap, 0, 0), |entry| (entry.retrigger, entry.max_voices, entry.cooldown));
+					let retrigger = if retrigger == Retrigger::Overlap && max_voices > 0 {
+						format!("{} (max {})", retrigger.label(), max_voices)
+					} else {
+						retrigger.label().to_string()
+					};
+					lines.push(Line::from(format!("Retrigger {} | Cooldown {} ms", retrigger, cooldown)));
+					let group = app.config.get_file_entry(&path).and_then(|entry| entry.group.clone());
+					lines.push(Line::from(format!("Choke Group {}", group.as_deref().unwrap_or("None"))));
 				}
 			},
 			MainOpened::Wave => {
@@ -72,6 +91,8 @@ impl BlockRenderArea for InfoBlock {
 						Span::from(format!("{} ({})", wave.label, wave.details())).style(Style::default().fg(Color::LightBlue))
 					]));
 					lines.push(volume_line("Wave Volume".to_string(), wave.volume, area.width, self.selected == 1));
+					lines.push(fade_line(wave.fade_in, wave.fade_out, self.selected));
+					lines.push(pan_line(wave.pan, None, self.selected, 4));
 					let mut spans = vec![];
 					spans.push(Span::from("ID "));
 					spans.push(wave.id.map_or( Span::from("None").style(Style::default().fg(Color::Red)), |id| { Span::from(format!(" {} ", id)).style(Style::default().fg(Color::LightYellow).add_modifier(Modifier::REVERSED)) }));
@@ -84,6 +105,7 @@ impl BlockRenderArea for InfoBlock {
 						spans.push(Span::from(format!(" {{{}}} ", keys.join(" "))).style(Style::default().fg(Color::LightGreen).add_modifier(Modifier::REVERSED)));
 					}
 					lines.push(Line::from(spans));
+					lines.push(Line::from(format!("Choke Group {}", wave.group.as_deref().unwrap_or("None"))));
 				}
 			},
 			MainOpened::Dialog => {
@@ -96,6 +118,8 @@ impl BlockRenderArea for InfoBlock {
 						Span::from(dialog.label.clone()).style(Style::default().fg(Color::LightYellow))
 					]));
 					lines.push(volume_line("Dialog Volume".to_string(), dialog.volume, area.width, self.selected == 1));
+					lines.push(fade_line(dialog.fade_in, dialog.fade_out, self.selected));
+					lines.push(pan_line(dialog.pan, Some(dialog.pan_random), self.selected, 4));
 					let mut spans = vec![];
 					spans.push(Span::from("ID "));
 					spans.push(dialog.id.map_or( Span::from("None").style(Style::default().fg(Color::Red)), |id| { Span::from(format!(" {} ", id)).style(Style::default().fg(Color::LightYellow).add_modifier(Modifier::REVERSED)) }));
@@ -108,6 +132,7 @@ impl BlockRenderArea for InfoBlock {
 						spans.push(Span::from(format!(" {{{}}} ", keys.join(" "))).style(Style::default().fg(Color::LightGreen).add_modifier(Modifier::REVERSED)));
 					}
 					lines.push(Line::from(spans));
+					lines.push(Line::from(format!("Choke Group {}", dialog.group.as_deref().unwrap_or("None"))));
 				}
 			},
 			MainOpened::Search => {
@@ -195,9 +220,17 @@ impl BlockNavigation for InfoBlock {
 
 impl InfoBlock {
 	fn navigate_volume(&mut self, dy: i32) -> bool {
-		let new_selected = loop_index(self.selected, dy, self.options as usize);
+		// Search results have no fade lines, only files can change speed and pitch, and only dialogs pan at random
+		let opened = acquire().main_opened;
+		let options = match opened {
+			MainOpened::Search => 2,
+			MainOpened::File => self.options as usize,
+			MainOpened::Dialog => 6,
+			_ => 5
+		};
+		let new_selected = loop_index(self.selected, dy, options);
 		if new_selected != self.selected {
-			if new_selected == 1 {
+			if new_selected >= 1 {
 				let app = acquire();
 				let selected_file = selected_file_path(&app.config.tabs, &app.files, None);
 				if selected_file.is_empty() {
@@ -211,6 +244,18 @@ impl InfoBlock {
 	}
 
 	fn change_volume(&self, delta: i64) -> bool {
+		if self.selected >= 4 {
+			let opened = acquire().main_opened;
+			return match (opened, self.selected) {
+				(MainOpened::File, 7)|(MainOpened::Wave|MainOpened::Dialog, 4) => change_pan(false, delta),
+				(MainOpened::Dialog, 5) => change_pan(true, delta),
+				_ => change_shift(self.selected, delta)
+			};
+		}
+		if self.selected >= 2 {
+			// Fades move in steps of 10 ms
+			return change_fade(self.selected == 2, delta * 10);
+		}
 		if self.selected == 1 {
 			return match { acquire().main_opened } {
 				MainOpened::File => change_file_volume(delta),
@@ -224,6 +269,7 @@ impl InfoBlock {
 		let new_volume = max(0, old_volume + delta);
 		if new_volume != old_volume {
 			app.config.volume = new_volume as u32;
+			set_mix_settings(&app.config);
 			return true
 		}
 		false
@@ -259,32 +305,193 @@ fn volume_line(title: String, volume: u32, width: u16, highlight: bool) -> Line<
 	Line::from(spans)
 }
 
-fn change_file_volume(delta: i64) -> bool {
+fn fade_line(fade_in: u32, fade_out: u32, selected: usize) -> Line<'static> {
+	let highlight = Style::default().fg(Color::LightCyan).add_modifier(Modifier::REVERSED);
+	Line::from(vec![
+		Span::from("Fade In").style(if selected == 2 { highlight } else { Style::default() }),
+		Span::from(format!(" {} ms | ", fade_in)),
+		Span::from("Fade Out").style(if selected == 3 { highlight } else { Style::default() }),
+		Span::from(format!(" {} ms", fade_out)),
+	])
+}
+
+fn shift_line(speed: u32, pitch: i32, stretch: bool, selected: usize) -> Line<'static> {
+	let highlight = Style::default().fg(Color::LightCyan).add_modifier(Modifier::REVERSED);
+	Line::from(vec![
+		Span::from("Speed").style(if selected == 4 { highlight } else { Style::default() }),
+		Span::from(format!(" {}% | ", speed)),
+		Span::from("Pitch").style(if selected == 5 { highlight } else { Style::default() }),
+		Span::from(format!(" {:+} st | ", pitch)),
+		Span::from("Stretch").style(if selected == 6 { highlight } else { Style::default() }),
+		Span::from(if stretch { " On" } else { " Off" }),
+	])
+}
+
+// `index` is where the pan is in the selection, followed by the random range if there is one
+fn pan_line(pan: i32, random: Option<u32>, selected: usize, index: usize) -> Line<'static> {
+	let highlight = Style::default().fg(Color::LightCyan).add_modifier(Modifier::REVERSED);
+	let mut spans = vec![
+		Span::from("Pan").style(if selected == index { highlight } else { Style::default() }),
+		Span::from(format!(" {}", pan_label(pan))),
+	];
+	if let Some(random) = random {
+		spans.push(Span::from(" | "));
+		spans.push(Span::from("Random Pan").style(if selected == index + 1 { highlight } else { Style::default() }));
+		spans.push(Span::from(format!(" ±{}", random)));
+	}
+	Line::from(spans)
+}
+
+// Speed moves in steps of 5%, pitch in semitones, and any change toggles stretching
+fn change_shift(selected: usize, delta: i64) -> bool {
 	let mut app = acquire();
+	if app.main_opened != MainOpened::File {
+		return false;
+	}
 	let path = selected_file_path(&app.config.tabs, &app.files, None);
 	if path.is_empty() {
 		return false;
 	}
-	match app.config.get_file_entry_mut(path.clone()) {
-		Some(entry) => {
-			let old_volume = entry.volume;
-			let new_volume = max(0, old_volume as i64 + delta) as u32;
-			if new_volume != old_volume {
-				entry.volume = new_volume;
-				if entry.is_default() {
-					app.config.remove_file_entry(path);
+	let apply = |entry: &mut FileEntry| {
+		match selected {
+			4 => {
+				let new_speed = (entry.speed as i64 + delta * 5).clamp(25, 400) as u32;
+				if new_speed == entry.speed {
+					return false;
+				}
+				entry.speed = new_speed;
+			},
+			5 => {
+				let new_pitch = (entry.pitch as i64 + delta).clamp(-24, 24) as i32;
+				if new_pitch == entry.pitch {
+					return false;
 				}
-				return true;
+				entry.pitch = new_pitch;
+			},
+			_ => entry.stretch = !entry.stretch
+		}
+		true
+	};
+	app.config.update_file_entry(path, apply)
+}
+
+// Pans move in steps of 5, from -100 (left) to 100 (right)
+fn change_pan(random: bool, delta: i64) -> bool {
+	let mut app = acquire();
+	let apply = |pan: &mut i32, min: i64| {
+		let new_pan = (*pan as i64 + delta * 5).clamp(min, 100) as i32;
+		if new_pan != *pan {
+			*pan = new_pan;
+			return true;
+		}
+		false
+	};
+	match app.main_opened {
+		MainOpened::File => {
+			let path = selected_file_path(&app.config.tabs, &app.files, None);
+			if path.is_empty() {
+				return false;
+			}
+			app.config.update_file_entry(path, |entry| apply(&mut entry.pan, -100))
+		},
+		MainOpened::Wave => {
+			let index = { WavesBlock::instance().selected };
+			if index >= app.waves.len() || !apply(&mut app.waves[index].pan, -100) {
+				return false;
 			}
+			app.config.waves[index].pan = app.waves[index].pan;
+			true
 		},
-		None => {
-			let mut entry = FileEntry::default();
-			entry.volume = (100 + delta) as u32;
-			app.config.insert_file_entry(path, entry);
+		MainOpened::Dialog => {
+			let index = { DialogBlock::instance().selected };
+			if index >= app.dialogs.len() {
+				return false;
+			}
+			let dialog = &mut app.dialogs[index];
+			if random {
+				let mut spread = dialog.pan_random as i32;
+				if !apply(&mut spread, 0) {
+					return false;
+				}
+				dialog.pan_random = spread as u32;
+				app.config.dialogs[index].pan_random = spread as u32;
+			} else {
+				if !apply(&mut dialog.pan, -100) {
+					return false;
+				}
+				let pan = dialog.pan;
+				app.config.dialogs[index].pan = pan;
+			}
+			true
+		},
+		_ => false
+	}
+}
+
+fn change_fade(fade_in: bool, delta: i64) -> bool {
+	let mut app = acquire();
+	let apply = |fade: &mut u32| {
+		let new_fade = max(0, *fade as i64 + delta) as u32;
+		if new_fade != *fade {
+			*fade = new_fade;
 			return true;
 		}
+		false
 	};
-	false
+	match app.main_opened {
+		MainOpened::File => {
+			let path = selected_file_path(&app.config.tabs, &app.files, None);
+			if path.is_empty() {
+				return false;
+			}
+			app.config.update_file_entry(path, |entry| apply(if fade_in { &mut entry.fade_in } else { &mut entry.fade_out }))
+		},
+		MainOpened::Wave => {
+			let index = { WavesBlock::instance().selected };
+			if index >= app.waves.len() {
+				return false;
+			}
+			let wave = &mut app.waves[index];
+			if !apply(if fade_in { &mut wave.fade_in } else { &mut wave.fade_out }) {
+				return false;
+			}
+			let (new_in, new_out) = (wave.fade_in, wave.fade_out);
+			app.config.waves[index].fade_in = new_in;
+			app.config.waves[index].fade_out = new_out;
+			true
+		},
+		MainOpened::Dialog => {
+			let index = { DialogBlock::instance().selected };
+			if index >= app.dialogs.len() {
+				return false;
+			}
+			let dialog = &mut app.dialogs[index];
+			if !apply(if fade_in { &mut dialog.fade_in } else { &mut dialog.fade_out }) {
+				return false;
+			}
+			let (new_in, new_out) = (dialog.fade_in, dialog.fade_out);
+			app.config.dialogs[index].fade_in = new_in;
+			app.config.dialogs[index].fade_out = new_out;
+			true
+		},
+		_ => false
+	}
+}
+
+fn change_file_volume(delta: i64) -> bool {
+	let mut app = acquire();
+	let path = selected_file_path(&app.config.tabs, &app.files, None);
+	if path.is_empty() {
+		return false;
+	}
+	app.config.update_file_entry(path, |entry| {
+		let new_volume = max(0, entry.volume as i64 + delta) as u32;
+		if new_volume == entry.volume {
+			return false;
+		}
+		entry.volume = new_volume;
+		true
+	})
 }
 
 fn change_wave_volume(delta: i64) -> bool {
@@ -1,16 +1,21 @@
 use std::{cmp::min, sync::{LazyLock, Mutex}};
 
-use ratatui::{layout::Rect, style::{Color, Style}, text::{Line, Text}, widgets::{Block, BorderType, Clear, Padding, Paragraph, Widget}, Frame};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::{layout::Rect, style::{Color, Modifier, Style}, text::{Line, Span, Text}, widgets::{Block, Clear, Padding, Paragraph, Widget}, Frame};
 
-use crate::{component::block::BlockSingleton, state::acquire};
+use crate::{component::block::{BlockHandleKey, BlockNavigation, BlockSingleton, loop_index, settings::SettingsBlock, tabs::TabsBlock}, state::acquire, util::{file::{seek, set_paused}, record::recording_path}};
 
 use super::BlockRender;
 
-pub struct PlayingBlock { }
+const MAX_LINES: u16 = 5;
+
+pub struct PlayingBlock {
+	pub selected: usize,
+}
 
 impl BlockSingleton for PlayingBlock {
 	fn instance() -> std::sync::MutexGuard<'static, Self> {
-		static BLOCK: LazyLock<Mutex<PlayingBlock>> = LazyLock::new(|| { Mutex::new(PlayingBlock {}) });
+		static BLOCK: LazyLock<Mutex<PlayingBlock>> = LazyLock::new(|| { Mutex::new(PlayingBlock { selected: 0 }) });
 		BLOCK.lock().unwrap()
 	}
 }
@@ -20,12 +25,21 @@ impl BlockRender for PlayingBlock {
 		let app = acquire();
 		let playing = &app.playing_file;
 		let waves = &app.playing_wave;
+		let focused = app.block_selected == Self::ID;
 
 		let mut lines: Vec<Line> = vec![];
 
 		if playing.len() > 0 {
-			lines.extend(playing.values().map(|file| {
-				Line::from(file.as_str()).style(Style::default().fg(Color::LightGreen))
+			lines.extend(playing.iter().map(|(uuid, file)| {
+				if app.paused_file.contains(uuid) {
+					Line::from(vec![
+						Span::from("Paused ").style(Style::default().fg(Color::Yellow).add_modifier(Modifier::REVERSED)),
+						Span::from(" "),
+						Span::from(file.as_str()),
+					]).style(Style::default().fg(Color::Yellow))
+				} else {
+					Line::from(file.as_str()).style(Style::default().fg(Color::LightGreen))
+				}
 			}));
 		}
 
@@ -35,21 +49,111 @@ impl BlockRender for PlayingBlock {
 			}));
 		}
 
+		let recording = recording_path().is_some();
 		if lines.len() == 0 {
-			return;
+			if !focused && !recording {
+				return;
+			}
+			lines.push(Line::from("Nothing is playing").style(Style::default().fg(Color::DarkGray)));
+		} else if focused {
+			let selected = min(self.selected, lines.len() - 1);
+			lines[selected] = lines[selected].clone().patch_style(Style::default().add_modifier(Modifier::REVERSED));
 		}
 
-		let len = lines.len();
+		let len = playing.len() + waves.len();
+		let mut title = vec![Span::from(format!("Playing ({len})"))];
+		if recording {
+			title.push(Span::from(" "));
+			title.push(Span::from(" ● REC ").style(Style::default().fg(Color::White).bg(Color::Red).add_modifier(Modifier::BOLD)));
+		}
 		let area = f.area();
-		let inner_height = min(5, len as u16);
+		let inner_height = min(MAX_LINES, lines.len() as u16);
 		let block_area = Rect {
 			x: 1,
 			y: area.height - (4 + inner_height),
 			width: area.width - 2,
 			height: 2 + inner_height
 		};
+		// Keep the selected line in view
+		let scroll = if focused { self.selected.saturating_sub(inner_height as usize - 1) as u16 } else { 0 };
+		let (border_type, border_style) = app.borders(Self::ID);
 		Clear.render(block_area, f.buffer_mut());
-		let paragraph = Paragraph::new(Text::from(lines)).block(Block::bordered().border_type(BorderType::Rounded).title(format!("Playing ({len})")).padding(Padding::horizontal(1)));
+		let paragraph = Paragraph::new(Text::from(lines))
+			.scroll((scroll, 0))
+			.block(Block::bordered().border_type(border_type).border_style(border_style).title(Line::from(title)).padding(Padding::horizontal(1)));
 		f.render_widget(paragraph, block_area);
 	}
-}
\ No newline at end of file
+}
+
+impl BlockHandleKey for PlayingBlock {
+	fn handle_key(&mut self, event: KeyEvent) -> bool {
+		match event.code {
+			KeyCode::Up => self.navigate(-1),
+			KeyCode::Down => self.navigate(1),
+			KeyCode::Char(' ') => self.toggle_pause(),
+			KeyCode::Char('p') => self.toggle_pause_all(),
+			KeyCode::Left => self.seek(if event.modifiers.contains(KeyModifiers::CONTROL) { -30000 } else { -5000 }),
+			KeyCode::Right => self.seek(if event.modifiers.contains(KeyModifiers::CONTROL) { 30000 } else { 5000 }),
+			_ => false,
+		}
+	}
+}
+
+impl BlockNavigation for PlayingBlock {
+	const ID: u8 = 10;
+
+	fn navigate_block(&self, dx: i16, dy: i16) -> u8 {
+		let app = acquire();
+		if dy < 0 {
+			return app.main_opened.id(TabsBlock::ID);
+		}
+		if dx > 0 && app.settings_opened {
+			return SettingsBlock::ID;
+		}
+		Self::ID
+	}
+}
+
+impl PlayingBlock {
+	// Main blocks move down into the overlay only while something is playing
+	pub fn is_visible() -> bool {
+		let app = acquire();
+		!app.playing_file.is_empty() || !app.playing_wave.is_empty()
+	}
+
+	fn navigate(&mut self, dy: i32) -> bool {
+		let len = {
+			let app = acquire();
+			app.playing_file.len() + app.playing_wave.len()
+		};
+		if len == 0 {
+			return false;
+		}
+		self.selected = loop_index(min(self.selected, len - 1), dy, len);
+		true
+	}
+
+	// Only files can be paused or seeked. Waves play for as long as they are held.
+	fn selected_file(&self) -> Option<(uuid::Uuid, bool)> {
+		let app = acquire();
+		app.playing_file.keys().nth(self.selected).map(|uuid| (*uuid, app.paused_file.contains(uuid)))
+	}
+
+	fn toggle_pause(&self) -> bool {
+		let Some((uuid, paused)) = self.selected_file() else { return false };
+		set_paused(Some(uuid), !paused)
+	}
+
+	fn toggle_pause_all(&self) -> bool {
+		let all_paused = {
+			let app = acquire();
+			app.playing_file.keys().all(|uuid| app.paused_file.contains(uuid))
+		};
+		set_paused(None, !all_paused)
+	}
+
+	fn seek(&self, offset: i32) -> bool {
+		let Some((uuid, _)) = self.selected_file() else { return false };
+		seek(Some(uuid), offset)
+	}
+}
@@ -8,7 +8,7 @@ use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIter
 use substring::Substring;
 use uuid::Uuid;
 
-use crate::{component::block::{BlockHandleKey, BlockNavigation, BlockRenderArea, BlockSingleton, log, loop_index, search::SearchBlock, settings::SettingsBlock}, state::{acquire, notify_redraw}, util::file::play_file_auto_volume};
+use crate::{component::block::{BlockHandleKey, BlockNavigation, BlockRenderArea, BlockSingleton, log, loop_index, playing::PlayingBlock, search::SearchBlock, settings::SettingsBlock}, state::{acquire, notify_redraw}, util::file::play_file_auto_volume};
 
 enum State {
 	Initial,
@@ -181,6 +181,9 @@ impl BlockNavigation for ResultsBlock {
 		if dy < 0 {
 			return SearchBlock::ID;
 		}
+		if dy > 0 && PlayingBlock::is_visible() {
+			return PlayingBlock::ID;
+		}
 		if dx > 0 && acquire().settings_opened {
 			return SettingsBlock::ID;
 		}
@@ -267,7 +270,7 @@ impl ResultsBlock {
 				} else {
 					Arc::new(Mutex::new(()))
 				};
-				play_file_auto_volume(&Path::new(&result.parent).join(&result.name).into_os_string().into_string().unwrap(), lock);
+				play_file_auto_volume(&Path::new(&result.parent).join(&result.name).into_os_string().into_string().unwrap(), false, lock);
 				true
 			},
 			SearchResult::Wave(result) => {
@@ -1,9 +1,9 @@
-use std::{cmp::max, collections::HashSet, sync::{Mutex, MutexGuard, OnceLock}};
+use std::{cmp::max, collections::HashSet, path::Path, sync::{Mutex, MutexGuard, OnceLock}};
 use crossterm::event::{KeyCode, KeyEvent};
 use ratatui::{layout::Rect, style::{Color, Modifier, Style}, text::{Line, Span}, widgets::{Block, Padding, Paragraph}, Frame};
 use substring::Substring;
 
-use crate::{component::{block::{BlockNavigation, BlockSingleton}, popup::{PopupComponent, input::{FLAG_NONE, InputPopup}, key_bind::{KeyBindFor, KeyBindPopup}, set_popup}}, state::acquire, util::pulseaudio::{loopback, unload_module}};
+use crate::{component::{block::{BlockNavigation, BlockSingleton}, popup::{PopupComponent, effects::{EffectTarget, EffectsPopup}, input::{FLAG_INT, FLAG_NONE, FLAG_NUM, InputPopup}, key_bind::{KeyBindFor, KeyBindPopup}, set_popup}}, config::SoundboardConfig, state::{Scanning, acquire}, util::{audio::{reset_output, set_mix_settings}, channels::OutputChannels, file::{Resampling, clear_cached_files, set_cache_limits}, limiter::LimiterMode, pulseaudio::{loopback, unload_module}, replay::set_replay_length, tab::scan}};
 
 use super::{loop_index, BlockHandleKey, BlockRenderArea};
 
@@ -18,7 +18,7 @@ impl BlockSingleton for SettingsBlock {
 		BLOCK.get_or_init(|| {
 			Mutex::new(SettingsBlock {
 				selected: 0,
-				options: 6
+				options: 30
 			})
 		}).lock().unwrap()
 	}
@@ -54,6 +54,32 @@ impl BlockRenderArea for SettingsBlock {
 		self.left_right_line("Loopback 2".to_string(), app.config.loopback_2.clone(), width as usize, &mut lines);
 		self.left_right_line("Playlist Mode".to_string(), app.config.playlist_mode.to_string(), width as usize, &mut lines);
 		self.left_right_line("Fast Scan".to_string(), app.config.fast_scan.to_string(), width as usize, &mut lines);
+		self.left_right_line("Stop Fade".to_string(), format!("{} ms", app.config.stop_fade), width as usize, &mut lines);
+		self.left_right_line("Normalize".to_string(), app.config.normalize.to_string(), width as usize, &mut lines);
+		self.left_right_line("Target Loudness".to_string(), format!("{} LUFS", app.config.normalize_target), width as usize, &mut lines);
+		self.left_right_line("Master Limiter".to_string(), app.config.limiter.label().to_string(), width as usize, &mut lines);
+		self.left_right_line("Limiter Ceiling".to_string(), format!("{} dBFS", app.config.limiter_ceiling), width as usize, &mut lines);
+		self.left_right_line("Output Rate".to_string(), format!("{} Hz", app.config.output_rate), width as usize, &mut lines);
+		self.left_right_line("Output Channels".to_string(), app.config.output_channels.label().to_string(), width as usize, &mut lines);
+		self.left_right_line("Resample Quality".to_string(), app.config.resample_quality.label().to_string(), width as usize, &mut lines);
+		self.left_right_line("Monitor Device".to_string(), if app.config.monitor.is_empty() { "Off".to_string() } else { app.config.monitor.clone() }, width as usize, &mut lines);
+		self.left_right_line("Monitor Volume".to_string(), format!("{}%", app.config.monitor_volume), width as usize, &mut lines);
+		self.left_right_line("Mic Input".to_string(), if app.config.mic.is_empty() { "Off".to_string() } else { app.config.mic.clone() }, width as usize, &mut lines);
+		self.left_right_line("Mic Gain".to_string(), format!("{}%", app.config.mic_gain), width as usize, &mut lines);
+		self.left_right_line("Mic Muted".to_string(), app.config.mic_muted.to_string(), width as usize, &mut lines);
+		self.left_right_line("Cache Size".to_string(), format!("{} MB", app.config.cache_size), width as usize, &mut lines);
+		self.left_right_line("Disk Cache Size".to_string(), format!("{} MB", app.config.disk_cache_size), width as usize, &mut lines);
+		self.left_right_line("Master Effects".to_string(), if app.config.master_effects.is_empty() { "None".to_string() } else { app.config.master_effects.iter().map(|effect| effect.label()).collect::<Vec<_>>().join(" > ") }, width as usize, &mut lines);
+		self.left_right_line("Duck Amount".to_string(), format!("{} dB", app.config.duck_amount), width as usize, &mut lines);
+		self.left_right_line("Duck Attack".to_string(), format!("{} ms", app.config.duck_attack), width as usize, &mut lines);
+		self.left_right_line("Duck Release".to_string(), format!("{} ms", app.config.duck_release), width as usize, &mut lines);
+		self.left_right_line("Duck Waves".to_string(), app.config.duck_waves.to_string(), width as usize, &mut lines);
+		self.left_right_line("Replay Length".to_string(), if app.config.replay_length == 0 { "Off".to_string() } else { format!("{} s", app.config.replay_length) }, width as usize, &mut lines);
+		let mut replay_key = app.config.replay_key.iter().cloned().collect::<Vec<_>>();
+		replay_key.sort();
+		self.left_right_line("Replay Key".to_string(), replay_key.join(" + "), width as usize, &mut lines);
+		self.left_right_line("Replay Tab".to_string(), if app.config.replay_tab.is_empty() { "None".to_string() } else { Path::new(&app.config.replay_tab).file_name().map_or(app.config.replay_tab.clone(), |name| name.to_string_lossy().to_string()) }, width as usize, &mut lines);
+		self.left_right_line("Latency".to_string(), format!("{} ms", app.config.latency), width as usize, &mut lines);
 		f.render_widget(Paragraph::new(lines).block(block), area);
 	}
 }
@@ -180,13 +206,263 @@ impl SettingsBlock {
 				app.config.fast_scan = !app.config.fast_scan;
 				return true;
 			},
+			// Stop fade duration
+			6 => {
+				let init = { acquire().config.stop_fade.to_string() };
+				set_popup(PopupComponent::Input(InputPopup::new(init, "Stop Fade (ms)".to_string(), FLAG_INT, |value| {
+					let Ok(fade) = value.parse::<u32>() else { return false; };
+					acquire().config.stop_fade = fade;
+					true
+				})));
+				true
+			},
+			// Loudness normalization toggle
+			7 => {
+				let mut app = acquire();
+				app.config.normalize = !app.config.normalize;
+				set_mix_settings(&app.config);
+				if app.config.normalize {
+					// Measure files that were scanned while normalization was off
+					drop(app);
+					scan(Scanning::All);
+				}
+				true
+			},
+			// Normalization target
+			8 => {
+				let init = { acquire().config.normalize_target.to_string() };
+				set_popup(PopupComponent::Input(InputPopup::new(init, "Target Loudness (LUFS)".to_string(), FLAG_NUM, |value| {
+					let Ok(target) = value.parse::<f32>() else { return false; };
+					let mut app = acquire();
+					app.config.normalize_target = target.min(0.0);
+					set_mix_settings(&app.config);
+					true
+				})));
+				true
+			},
+			// Master limiter mode
+			9 => {
+				let mut app = acquire();
+				app.config.limiter = app.config.limiter.next();
+				set_mix_settings(&app.config);
+				true
+			},
+			// Limiter ceiling
+			10 => {
+				let init = { acquire().config.limiter_ceiling.to_string() };
+				set_popup(PopupComponent::Input(InputPopup::new(init, "Limiter Ceiling (dBFS)".to_string(), FLAG_NUM, |value| {
+					let Ok(ceiling) = value.parse::<f32>() else { return false; };
+					let mut app = acquire();
+					app.config.limiter_ceiling = ceiling.min(0.0);
+					set_mix_settings(&app.config);
+					true
+				})));
+				true
+			},
+			// Output sample rate
+			11 => {
+				let init = { acquire().config.output_rate.to_string() };
+				set_popup(PopupComponent::Input(InputPopup::new(init, "Output Rate (Hz)".to_string(), FLAG_INT, |value| {
+					let Ok(rate) = value.parse::<u32>() else { return false; };
+					if !(8000..=192000).contains(&rate) {
+						acquire().error = "Output rate must be between 8000 and 192000 Hz".to_string();
+						return false;
+					}
+					let mut app = acquire();
+					if app.config.output_rate != rate {
+						app.config.output_rate = rate;
+						reset_output();
+					}
+					true
+				})));
+				true
+			},
+			// Output channel layout
+			12 => {
+				let mut app = acquire();
+				app.config.output_channels = app.config.output_channels.next();
+				reset_output();
+				true
+			},
+			// Global resample quality
+			13 => {
+				let mut app = acquire();
+				app.config.resample_quality = app.config.resample_quality.next();
+				clear_global_resampled(&app.config);
+				true
+			},
+			// Monitor output. The monitor thread picks up the change.
+			14 => {
+				let init = { acquire().config.monitor.clone() };
+				set_popup(PopupComponent::Input(InputPopup::new(init, "Monitor Device (sink, cpal ID or default)".to_string(), FLAG_NONE, |value| {
+					acquire().config.monitor = value.trim().to_string();
+					true
+				})));
+				true
+			},
+			// Monitor volume
+			15 => {
+				let init = { acquire().config.monitor_volume.to_string() };
+				set_popup(PopupComponent::Input(InputPopup::new(init, "Monitor Volume (%)".to_string(), FLAG_INT, |value| {
+					let Ok(volume) = value.parse::<u32>() else { return false; };
+					let mut app = acquire();
+					app.config.monitor_volume = volume;
+					set_mix_settings(&app.config);
+					true
+				})));
+				true
+			},
+			// Mic input. The input thread picks up the change.
+			16 => {
+				let init = { acquire().config.mic.clone() };
+				set_popup(PopupComponent::Input(InputPopup::new(init, "Mic Input (source, cpal ID, default or WAV file)".to_string(), FLAG_NONE, |value| {
+					acquire().config.mic = value.trim().to_string();
+					true
+				})));
+				true
+			},
+			// Mic gain
+			17 => {
+				let init = { acquire().config.mic_gain.to_string() };
+				set_popup(PopupComponent::Input(InputPopup::new(init, "Mic Gain (%)".to_string(), FLAG_INT, |value| {
+					let Ok(gain) = value.parse::<u32>() else { return false; };
+					let mut app = acquire();
+					app.config.mic_gain = gain;
+					set_mix_settings(&app.config);
+					true
+				})));
+				true
+			},
+			// Mic mute toggle
+			18 => {
+				let mut app = acquire();
+				app.config.mic_muted = !app.config.mic_muted;
+				set_mix_settings(&app.config);
+				true
+			},
+			// Memory for decoded audio
+			19 => {
+				let init = { acquire().config.cache_size.to_string() };
+				set_popup(PopupComponent::Input(InputPopup::new(init, "Cache Size (MB)".to_string(), FLAG_INT, |value| {
+					let Ok(size) = value.parse::<u32>() else { return false; };
+					let mut app = acquire();
+					app.config.cache_size = size;
+					set_cache_limits(&app.config);
+					true
+				})));
+				true
+			},
+			// Decoded audio saved on disk
+			20 => {
+				let init = { acquire().config.disk_cache_size.to_string() };
+				set_popup(PopupComponent::Input(InputPopup::new(init, "Disk Cache Size (MB, 0 to disable)".to_string(), FLAG_INT, |value| {
+					let Ok(size) = value.parse::<u32>() else { return false; };
+					let mut app = acquire();
+					app.config.disk_cache_size = size;
+					set_cache_limits(&app.config);
+					true
+				})));
+				true
+			},
+			// Effects on the whole mix
+			21 => {
+				set_popup(PopupComponent::Effects(EffectsPopup::new(EffectTarget::Master)));
+				true
+			},
+			// How far a ducker lowers other sounds
+			22 => {
+				let init = { acquire().config.duck_amount.to_string() };
+				set_popup(PopupComponent::Input(InputPopup::new(init, "Duck Amount (dB)".to_string(), FLAG_NUM, |value| {
+					let Ok(amount) = value.parse::<f32>() else { return false; };
+					let mut app = acquire();
+					app.config.duck_amount = amount.max(0.0);
+					set_mix_settings(&app.config);
+					true
+				})));
+				true
+			},
+			// Time for other sounds to go down
+			23 => {
+				let init = { acquire().config.duck_attack.to_string() };
+				set_popup(PopupComponent::Input(InputPopup::new(init, "Duck Attack (ms)".to_string(), FLAG_INT, |value| {
+					let Ok(attack) = value.parse::<u32>() else { return false; };
+					let mut app = acquire();
+					app.config.duck_attack = attack;
+					set_mix_settings(&app.config);
+					true
+				})));
+				true
+			},
+			// Time for other sounds to come back
+			24 => {
+				let init = { acquire().config.duck_release.to_string() };
+				set_popup(PopupComponent::Input(InputPopup::new(init, "Duck Release (ms)".to_string(), FLAG_INT, |value| {
+					let Ok(release) = value.parse::<u32>() else { return false; };
+					let mut app = acquire();
+					app.config.duck_release = release;
+					set_mix_settings(&app.config);
+					true
+				})));
+				true
+			},
+			// Duck waves along with files
+			25 => {
+				let mut app = acquire();
+				app.config.duck_waves = !app.config.duck_waves;
+				set_mix_settings(&app.config);
+				true
+			},
+			// Seconds of output kept for replays
+			26 => {
+				let init = { acquire().config.replay_length.to_string() };
+				set_popup(PopupComponent::Input(InputPopup::new(init, "Replay Length (s, 0 to disable)".to_string(), FLAG_INT, |value| {
+					let Ok(length) = value.parse::<u32>() else { return false; };
+					acquire().config.replay_length = length;
+					set_replay_length(length);
+					true
+				})));
+				true
+			},
+			// Replay key
+			27 => {
+				set_popup(PopupComponent::KeyBind(KeyBindPopup::new(KeyBindFor::Replay, HashSet::new())));
+				true
+			},
+			// Cycle through the tabs replays are saved into
+			28 => {
+				let mut app = acquire();
+				let next = match app.config.tabs.iter().position(|tab| *tab == app.config.replay_tab) {
+					Some(index) => app.config.tabs.get(index + 1).cloned().unwrap_or_default(),
+					None => app.config.tabs.first().cloned().unwrap_or_default(),
+				};
+				app.config.replay_tab = next;
+				true
+			},
+			// Output latency, which restarts the output
+			29 => {
+				let init = { acquire().config.latency.to_string() };
+				set_popup(PopupComponent::Input(InputPopup::new(init, "Latency (ms)".to_string(), FLAG_INT, |value| {
+					let Ok(latency) = value.parse::<u32>() else { return false; };
+					if !(2..=1000).contains(&latency) {
+						acquire().error = "Latency must be between 2 and 1000 ms".to_string();
+						return false;
+					}
+					let mut app = acquire();
+					if app.config.latency != latency {
+						app.config.latency = latency;
+						reset_output();
+					}
+					true
+				})));
+				true
+			},
 			_ => false
 		}
 	}
 
 	fn handle_delete(&mut self) -> bool {
 		let mut app = acquire();
-		match self.selected {
+		let handled = match self.selected {
 			0 => {
 				app.config.stop_key.clear();
 				app.stopkey.clear();
@@ -219,7 +495,127 @@ impl SettingsBlock {
 				app.config.playlist_mode = false;
 				return true;
 			},
+			6 => {
+				app.config.stop_fade = 0;
+				true
+			},
+			7 => {
+				app.config.normalize = false;
+				true
+			},
+			8 => {
+				app.config.normalize_target = -16.0;
+				true
+			},
+			9 => {
+				app.config.limiter = LimiterMode::default();
+				true
+			},
+			10 => {
+				app.config.limiter_ceiling = -1.0;
+				true
+			},
+			11 => {
+				if app.config.output_rate != 48000 {
+					app.config.output_rate = 48000;
+					reset_output();
+				}
+				true
+			},
+			12 => {
+				if app.config.output_channels != OutputChannels::Stereo {
+					app.config.output_channels = OutputChannels::Stereo;
+					reset_output();
+				}
+				true
+			},
+			13 => {
+				if app.config.resample_quality != Resampling::Low {
+					app.config.resample_quality = Resampling::Low;
+					clear_global_resampled(&app.config);
+				}
+				true
+			},
+			14 => {
+				app.config.monitor = String::new();
+				true
+			},
+			15 => {
+				app.config.monitor_volume = 100;
+				true
+			},
+			16 => {
+				app.config.mic = String::new();
+				true
+			},
+			17 => {
+				app.config.mic_gain = 100;
+				true
+			},
+			18 => {
+				app.config.mic_muted = false;
+				true
+			},
+			19 => {
+				app.config.cache_size = 512;
+				set_cache_limits(&app.config);
+				true
+			},
+			20 => {
+				app.config.disk_cache_size = 2048;
+				set_cache_limits(&app.config);
+				true
+			},
+			21 => {
+				app.config.master_effects.clear();
+				true
+			},
+			22 => {
+				app.config.duck_amount = 12.0;
+				true
+			},
+			23 => {
+				app.config.duck_attack = 50;
+				true
+			},
+			24 => {
+				app.config.duck_release = 500;
+				true
+			},
+			25 => {
+				app.config.duck_waves = false;
+				true
+			},
+			26 => {
+				app.config.replay_length = 0;
+				set_replay_length(0);
+				true
+			},
+			27 => {
+				app.config.replay_key.clear();
+				app.replaykey.clear();
+				true
+			},
+			28 => {
+				app.config.replay_tab = String::new();
+				true
+			},
+			29 => {
+				if app.config.latency != 20 {
+					app.config.latency = 20;
+					reset_output();
+				}
+				true
+			},
 			_ => false
-		}
+		};
+		// Most rows are read by the mixer
+		set_mix_settings(&app.config);
+		handled
 	}
-}
\ No newline at end of file
+}
+
+// Files with their own resample quality keep their decoded audio
+pub fn clear_global_resampled(config: &SoundboardConfig) {
+	clear_cached_files(|path| config.get_file_entry(path).is_none_or(|entry| entry.resample.is_none()));
+}
@@ -10,7 +10,7 @@ use substring::Substring;
 use crate::component::{block::{BlockSingleton, loop_index}, popup::{confirm::ConfirmPopup, input::{FLAG_NONE, InputPopup}, key_bind::{KeyBindFor, KeyBindPopup}}};
 use crate::component::popup::wave::WavePopup;
 use crate::component::popup::{set_popup, PopupComponent};
-use crate::{component::block::{settings::SettingsBlock, tabs::TabsBlock, BlockHandleKey, BlockNavigation, BlockRenderArea}, state::acquire, util::wave::Waveform};
+use crate::{component::block::{playing::PlayingBlock, settings::SettingsBlock, tabs::TabsBlock, BlockHandleKey, BlockNavigation, BlockRenderArea}, state::acquire, util::wave::Waveform};
 
 pub struct WavesBlock {
 	range: (i32, i32),
@@ -112,6 +112,7 @@ impl BlockHandleKey for WavesBlock {
 			KeyCode::Char('a') => self.add_wave(),
 			KeyCode::Char('e') => self.edit_wave(),
 			KeyCode::Char('r') => self.rename_wave(),
+			KeyCode::Char('g') => self.set_group(),
 			KeyCode::Char('d') => self.delete_wave(),
 			KeyCode::Char('f') => self.duplicate_wave(),
 			KeyCode::Char('x') => self.set_global_key_bind(),
@@ -134,6 +135,9 @@ impl BlockNavigation for WavesBlock {
 		if dy < 0 {
 			return TabsBlock::ID;
 		}
+		if dy > 0 && PlayingBlock::is_visible() {
+			return PlayingBlock::ID;
+		}
 		if dx > 0 && acquire().settings_opened {
 			return SettingsBlock::ID;
 		}
@@ -212,6 +216,19 @@ impl WavesBlock {
 		true
 	}
 
+	fn set_group(&self) -> bool {
+		let init = acquire().waves[self.selected].group.clone().unwrap_or_default();
+		set_popup(PopupComponent::Input(InputPopup::new(init, "Choke Group (empty for none)".to_string(), FLAG_NONE, |value| {
+			let group = Some(value.trim().to_string()).filter(|group| !group.is_empty());
+			let mut app = acquire();
+			let selected = { WavesBlock::instance().selected };
+			app.waves[selected].group = group.clone();
+			app.config.waves[selected].group = group;
+			true
+		})));
+		true
+	}
+
 	fn delete_wave(&self) -> bool {
 		set_popup(PopupComponent::Confirm(ConfirmPopup::new("Delete wave?", "delete", || {
 			let mut app = acquire();
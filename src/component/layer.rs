@@ -1,8 +1,8 @@
-use std::thread;
+use std::{thread, time::{SystemTime, UNIX_EPOCH}};
 
 use crossterm::event::{KeyCode, KeyEvent};
 
-use crate::{component::{block::{self, BlockNavigation, dialogs::DialogBlock, files::FilesBlock, results::ResultsBlock, search::SearchBlock, settings::SettingsBlock, tabs::TabsBlock, waves::WavesBlock}, popup::{confirm::ConfirmPopup, exit_popup, save::SavePopup}}, config, state::{MainOpened, SelectionLayer, acquire, notify_redraw, stop_running}};
+use crate::{component::{block::{self, BlockNavigation, dialogs::DialogBlock, files::FilesBlock, log, results::ResultsBlock, search::SearchBlock, settings::SettingsBlock, tabs::TabsBlock, waves::WavesBlock}, popup::{confirm::ConfirmPopup, exit_popup, input::{FLAG_NONE, InputPopup}, save::SavePopup}}, config, constant::APP_NAME, state::{MainOpened, SelectionLayer, acquire, notify_redraw, stop_running}, util::record::{start_recording, stop_recording}};
 
 use super::{popup::{help::HelpPopup, set_popup, PopupComponent}};
 
@@ -22,6 +22,10 @@ pub fn handle_key(event: KeyEvent) -> bool {
 			save();
 			return true;
 		},
+		KeyCode::Char('r') => {
+			toggle_recording();
+			true
+		},
 		KeyCode::Char('c') => {
 			let mut app = acquire();
 			app.settings_opened = !app.settings_opened;
@@ -118,6 +122,23 @@ fn save() {
 	});
 }
 
+// Stops the recording, or asks where to start one
+fn toggle_recording() {
+	if let Some(path) = stop_recording() {
+		log::info(format!("Saved recording to {}", path).as_str());
+		return;
+	}
+	let dir = dirs::audio_dir().or_else(dirs::home_dir).unwrap_or_default();
+	let time = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+	let path = dir.join(format!("{}-{}.wav", APP_NAME, time)).to_string_lossy().to_string();
+	set_popup(PopupComponent::Input(InputPopup::new(path, "Record To (.wav, or .flac with ffmpeg)".to_string(), FLAG_NONE, |value| {
+		if let Err(err) = start_recording(value.trim()) {
+			log::error(format!("Failed to record to {}: {:?}", value, err).as_str());
+		}
+		true
+	})));
+}
+
 fn toggle_main_opened(main_opened: MainOpened) {
 	let mut app = acquire();
 	if app.main_opened == main_opened {
@@ -8,14 +8,16 @@ use mki::Keyboard;
 use ratatui::{layout::Rect, Frame};
 use save::SavePopup;
 
-use crate::{component::popup::{confirm::ConfirmPopup, dialog::DialogPopup, wave::WavePopup}, state::notify_redraw};
+use crate::{component::popup::{confirm::ConfirmPopup, dialog::DialogPopup, effects::EffectsPopup, trim::TrimPopup, wave::WavePopup}, state::notify_redraw};
 
 pub mod confirm;
 pub mod dialog;
+pub mod effects;
 pub mod help;
 pub mod input;
 pub mod key_bind;
 pub mod save;
+pub mod trim;
 pub mod wave;
 
 static POPUPS: LazyLock<Mutex<Vec<PopupComponent>>> = LazyLock::new(|| { Mutex::new(vec![]) });
@@ -28,6 +30,8 @@ pub enum PopupComponent {
 	Save(SavePopup),
 	Wave(WavePopup),
 	Dialog(DialogPopup),
+	Trim(TrimPopup),
+	Effects(EffectsPopup),
 }
 
 pub trait PopupRender {
@@ -57,6 +61,8 @@ impl PopupRender for PopupComponent {
 			Save(popup) => popup.render(f),
 			Wave(popup) => popup.render(f),
 			Dialog(popup) => popup.render(f),
+			Trim(popup) => popup.render(f),
+			Effects(popup) => popup.render(f),
 		}
 	}
 }
@@ -72,6 +78,8 @@ impl PopupHandleKey for PopupComponent {
 			Save(popup) => popup.handle_key(event),
 			Wave(popup) => popup.handle_key(event),
 			Dialog(popup) => popup.handle_key(event),
+			Trim(popup) => popup.handle_key(event),
+			Effects(popup) => popup.handle_key(event),
 		}
 	}
 }
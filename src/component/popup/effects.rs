@@ -0,0 +1,221 @@
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::{style::{Color, Modifier, Style}, text::Line, widgets::{Block, BorderType, Clear, Padding, Paragraph, Widget}, Frame};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use std::thread;
+
+use crate::{component::popup::{PopupComponent, PopupHandleKey, PopupRender, confirm::ConfirmPopup, defer_exit_popup, defer_set_popup, input::{FLAG_NUM, InputPopup}, popups, safe_centered_rect}, state::acquire, util::{audio::set_mix_settings, effects::Effect}};
+
+// What the edited chain belongs to
+pub enum EffectTarget {
+	File(String),
+	Dialog(usize),
+	Master,
+}
+
+pub struct EffectsPopup {
+	target: EffectTarget,
+	pub(super) effects: Vec<Effect>,
+	pub(super) selected: usize,
+	// Parameter being edited
+	pub(super) param: usize,
+	pub(super) changed: bool
+}
+
+impl EffectsPopup {
+	pub fn new(target: EffectTarget) -> Self {
+		let effects = {
+			let app = acquire();
+			match &target {
+				EffectTarget::File(path) => app.config.get_file_entry(path).map_or(vec![], |entry| entry.effects.clone()),
+				EffectTarget::Dialog(index) => app.dialogs[*index].effects.clone(),
+				EffectTarget::Master => app.config.master_effects.clone(),
+			}
+		};
+		Self {
+			target,
+			effects,
+			selected: 0,
+			param: 0,
+			changed: false
+		}
+	}
+}
+
+impl PopupRender for EffectsPopup {
+	fn render(&self, f: &mut Frame) {
+		let mut lines = vec![
+			Line::from("Controls").style(Style::default().add_modifier(Modifier::BOLD)).centered(),
+			Line::from("a - add, d - delete"),
+			Line::from("up / down - select"),
+			Line::from("[ / ] - move up / down"),
+			Line::from("left / right - change type"),
+			Line::from("1 / 2 / 3 - change parameter"),
+			Line::from("enter / esc - save / discard changes"),
+			Line::from(""),
+		];
+
+		let page_size = (f.area().height as usize).saturating_sub(3 + lines.len()).max(1);
+		let page = self.selected / page_size;
+
+		lines.push(Line::from(if self.effects.len() > page_size {
+			format!("Effect Chain (Page {} / {})", page + 1, self.effects.len().div_ceil(page_size))
+		} else {
+			"Effect Chain".to_string()
+		}).style(Style::default().add_modifier(Modifier::BOLD)).centered());
+
+		if self.effects.is_empty() {
+			lines.push(Line::from("No effects").style(Style::default().fg(Color::Red)));
+		}
+		let start = page * page_size;
+		lines.extend(self.effects[start..((page + 1) * page_size).min(self.effects.len())].iter().enumerate().map(|(ii, effect)| {
+			let params = effect.params().into_iter().enumerate()
+				.map(|(jj, (name, value, unit))| format!("{}. {} {}{}", jj + 1, name, value, unit))
+				.collect::<Vec<_>>();
+			Line::from(format!("{} | {}", effect.label(), params.join(", "))).style(if self.selected == start + ii {
+				Style::default().fg(Color::LightGreen).add_modifier(Modifier::REVERSED)
+			} else {
+				Style::default().fg(Color::Green)
+			})
+		}));
+
+		let width = lines.par_iter().map(|line| line.width() as u16).max().unwrap_or(0) + 4;
+		let height = lines.len() as u16 + 2;
+		let popup_area = safe_centered_rect(width, height, f.area());
+
+		let block = Block::bordered()
+			.padding(Padding::horizontal(1))
+			.border_type(BorderType::Rounded)
+			.title(match &self.target {
+				EffectTarget::Master => "Master Effects",
+				_ => "Effects",
+			});
+
+		Clear.render(popup_area, f.buffer_mut());
+		f.render_widget(Paragraph::new(lines).block(block), popup_area);
+	}
+}
+
+impl PopupHandleKey for EffectsPopup {
+	fn handle_key(&mut self, event: KeyEvent) -> bool {
+		use KeyCode::*;
+		match event.code {
+			Up => self.navigate_effect(-1),
+			Down => self.navigate_effect(1),
+			Left => self.change_type(-1),
+			Right => self.change_type(1),
+			Char('[') => self.move_effect(-1),
+			Char(']') => self.move_effect(1),
+			Char('a') => self.add_effect(),
+			Char('d') => self.delete_effect(),
+			Char(c @ '1'..='3') => self.popup_param(c as usize - '1' as usize),
+			Enter => self.commit_changes(),
+			Esc|Char('q') => self.discard_changes(),
+			_ => false
+		}
+	}
+}
+
+impl EffectsPopup {
+	fn navigate_effect(&mut self, dy: i16) -> bool {
+		if self.effects.is_empty() {
+			return false;
+		}
+		let new_selected = (self.selected as i16 + dy).rem_euclid(self.effects.len() as i16) as usize;
+		if new_selected != self.selected {
+			self.selected = new_selected;
+			return true;
+		}
+		false
+	}
+
+	fn change_type(&mut self, dx: i32) -> bool {
+		let Some(effect) = self.effects.get_mut(self.selected) else { return false; };
+		*effect = effect.cycle(dx);
+		self.changed = true;
+		true
+	}
+
+	fn move_effect(&mut self, dy: i16) -> bool {
+		let target = self.selected as i16 + dy;
+		if target < 0 || target as usize >= self.effects.len() {
+			return false;
+		}
+		self.effects.swap(self.selected, target as usize);
+		self.selected = target as usize;
+		self.changed = true;
+		true
+	}
+
+	fn add_effect(&mut self) -> bool {
+		self.effects.push(Effect::default());
+		self.selected = self.effects.len() - 1;
+		self.changed = true;
+		true
+	}
+
+	fn delete_effect(&mut self) -> bool {
+		if self.selected >= self.effects.len() {
+			return false;
+		}
+		self.effects.remove(self.selected);
+		self.selected = self.selected.min(self.effects.len().saturating_sub(1));
+		self.changed = true;
+		true
+	}
+
+	fn popup_param(&mut self, index: usize) -> bool {
+		let Some(effect) = self.effects.get(self.selected) else { return false; };
+		let Some((name, value, unit)) = effect.params().get(index).copied() else { return false; };
+		self.param = index;
+		let title = if unit.is_empty() { name.to_string() } else { format!("{} ({})", name, unit.trim()) };
+		defer_set_popup(PopupComponent::Input(InputPopup::new(value.to_string(), title, FLAG_NUM, |value| {
+			let Ok(value) = value.parse::<f32>() else { return false; };
+			thread::spawn(move || {
+				if let Some(popup) = popups().last_mut() && let PopupComponent::Effects(popup) = popup {
+					let effect = &mut popup.effects[popup.selected];
+					let old = effect.clone();
+					effect.set_param(popup.param, value.round() as i64);
+					if *effect != old {
+						popup.changed = true;
+					}
+				}
+			});
+			false
+		})));
+		true
+	}
+
+	fn commit_changes(&self) -> bool {
+		let mut app = acquire();
+		match &self.target {
+			EffectTarget::File(path) => {
+				app.config.update_file_entry(path.clone(), |entry| {
+					entry.effects = self.effects.clone();
+					true
+				});
+			},
+			EffectTarget::Dialog(index) => {
+				app.dialogs[*index].effects = self.effects.clone();
+				app.config.dialogs[*index].effects = self.effects.clone();
+			},
+			EffectTarget::Master => {
+				app.config.master_effects = self.effects.clone();
+				set_mix_settings(&app.config);
+			},
+		}
+		defer_exit_popup();
+		true
+	}
+
+	fn discard_changes(&self) -> bool {
+		if self.changed {
+			defer_set_popup(PopupComponent::Confirm(ConfirmPopup::new("Discard changes?", "discard", || {
+				defer_exit_popup();
+				false
+			})));
+		} else {
+			defer_exit_popup();
+		}
+		true
+	}
+}
@@ -44,6 +44,7 @@ impl PopupRender for HelpPopup {
 					Line::from("' - Toggle search"),
 					Line::from("\\ - Toggle logs"),
 					Line::from("s - Save configuration"),
+					Line::from("r - Start / stop recording the output"),
 
 					Line::from(""),
 					Line::from("Help Key Binds").style(Style::default().add_modifier(Modifier::BOLD)).centered(),
@@ -57,6 +58,9 @@ impl PopupRender for HelpPopup {
 					Line::from("right - Increase volume by 1%"),
 					Line::from("ctrl + left - Decrease volume by 5%"),
 					Line::from("ctrl + right - Increase volume by 5%"),
+					Line::from("up / down - Select volume, fade or pan"),
+					Line::from("left / right on fade - Change by 10 ms (ctrl: 50 ms)"),
+					Line::from("left / right on pan - Move by 5 (ctrl: 25)"),
 
 					Line::from(""),
 					Line::from("Tabs Key Binds").style(Style::default().add_modifier(Modifier::BOLD)).centered(),
@@ -73,11 +77,26 @@ impl PopupRender for HelpPopup {
 					Line::from("z - Remove global hotkey"),
 					Line::from("v - Set file ID"),
 					Line::from("b - Remove file ID"),
+					Line::from("t - Trim file"),
+					Line::from("m - Cycle playback mode (one-shot / hold / toggle)"),
+					Line::from("u - Cycle resample quality (global / low / high)"),
+					Line::from("c - Edit effects"),
+					Line::from("p - Toggle ducking other sounds while playing"),
+					Line::from("o - Cycle retrigger policy (overlap / restart / ignore / stop)"),
+					Line::from("n - Set max overlapping voices"),
+					Line::from("k - Set retrigger cooldown"),
+					Line::from("g - Set choke group"),
 			
 					Line::from(""),
 					Line::from("Settings Key Binds").style(Style::default().add_modifier(Modifier::BOLD)).centered(),
 					Line::from("enter - Set an option"),
 					Line::from("delete - Reset an option"),
+
+					Line::from(""),
+					Line::from("Playing Key Binds").style(Style::default().add_modifier(Modifier::BOLD)).centered(),
+					Line::from("space - Pause / resume file"),
+					Line::from("p - Pause / resume all files"),
+					Line::from("left / right - Seek by 5 s (ctrl: 30 s)"),
 				]);
 			}
 			3 => {
@@ -94,6 +113,8 @@ impl PopupRender for HelpPopup {
 					Line::from("z - Remove global hotkey"),
 					Line::from("v - Set waveform ID"),
 					Line::from("b - Remove waveform ID"),
+					Line::from("g - Set choke group"),
+					Line::from("c - Edit effects (dialogs only)"),
 				]);
 			}
 			_ => {}
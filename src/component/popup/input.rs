@@ -68,7 +68,8 @@ impl PopupHandleKey for InputPopup {
 					return false;
 				} else if self.flags & FLAG_NUM != 0 {
 					let new = format!("{}{}", self.input.value(), c);
-					if let Err(_) = new.parse::<f32>() {
+					// Allow starting a negative number
+					if new != "-" && let Err(_) = new.parse::<f32>() {
 						return false;
 					}
 				}
@@ -12,6 +12,7 @@ use super::{safe_centered_rect, PopupHandleGlobalKey, PopupHandleKey, PopupRende
 pub enum KeyBindFor {
 	File,
 	Stop,
+	Replay,
 	Wave,
 	Dialog,
 }
@@ -60,6 +61,7 @@ impl PopupHandleKey for KeyBindPopup {
 					match self.this_is_a {
 						KeyBindFor::File => self.set_file_key_bind(),
 						KeyBindFor::Stop => self.set_stop_key_bind(),
+						KeyBindFor::Replay => self.set_replay_key_bind(),
 						KeyBindFor::Wave => self.set_wave_key_bind(),
 						KeyBindFor::Dialog => self.set_dialog_key_bind(),
 					}
@@ -128,6 +130,13 @@ impl KeyBindPopup {
 		notify_redraw();
 	}
 
+	fn set_replay_key_bind(&self) {
+		let mut app = acquire();
+		app.config.replay_key = self.recorded.iter().map(|key| { keyboard_to_string(*key) }).collect::<HashSet<String>>();
+		app.replaykey = self.recorded.clone().into_iter().collect::<Vec<Keyboard>>();
+		notify_redraw();
+	}
+
 	fn set_wave_key_bind(&self) {
 		let mut app = acquire();
 		let selected = { WavesBlock::instance().selected };
@@ -0,0 +1,193 @@
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::{style::{Color, Modifier, Style}, text::{Line, Span}, widgets::{Block, BorderType, Clear, Padding, Paragraph, Widget}, Frame};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use std::thread;
+
+use crate::{component::popup::{PopupComponent, PopupHandleKey, PopupRender, confirm::ConfirmPopup, defer_exit_popup, defer_set_popup, input::{FLAG_NONE, InputPopup}, popups, safe_centered_rect}, state::acquire};
+
+pub struct TrimPopup {
+	path: String,
+	duration: String,
+	pub(super) start: Option<u32>,
+	pub(super) end: Option<u32>,
+	pub(super) selected: usize,
+	pub(super) changed: bool,
+}
+
+impl TrimPopup {
+	pub fn new(path: String, duration: String) -> Self {
+		let (start, end) = acquire().config.get_file_entry(&path).map_or((None, None), |entry| (entry.start, entry.end));
+		Self {
+			path,
+			duration,
+			start,
+			end,
+			selected: 0,
+			changed: false,
+		}
+	}
+}
+
+impl PopupRender for TrimPopup {
+	fn render(&self, f: &mut Frame) {
+		let highlight = Style::default().fg(Color::LightGreen).add_modifier(Modifier::REVERSED);
+		let lines = vec![
+			Line::from("Controls").style(Style::default().add_modifier(Modifier::BOLD)).centered(),
+			Line::from("up / down - select"),
+			Line::from("e - edit (e.g. 1500ms, 1.5s)"),
+			Line::from("delete - clear"),
+			Line::from("enter / esc - save / discard changes"),
+			Line::from(""),
+			Line::from(vec![
+				Span::from("Duration "),
+				Span::from(if self.duration.is_empty() { "Unknown".to_string() } else { self.duration.clone() }).style(Style::default().fg(Color::Cyan)),
+			]),
+			Line::from(vec![
+				Span::from("Start").style(if self.selected == 0 { highlight } else { Style::default() }),
+				Span::from(format!(" {}", self.start.map_or("Beginning".to_string(), format_time))),
+			]),
+			Line::from(vec![
+				Span::from("End").style(if self.selected == 1 { highlight } else { Style::default() }),
+				Span::from(format!(" {}", self.end.map_or("End of file".to_string(), format_time))),
+			]),
+		];
+
+		let width = lines.par_iter().map(|line| line.width() as u16).max().unwrap_or(0) + 4;
+		let height = lines.len() as u16 + 2;
+		let popup_area = safe_centered_rect(width, height, f.area());
+
+		let block = Block::bordered()
+			.padding(Padding::horizontal(1))
+			.border_type(BorderType::Rounded)
+			.title("Trim");
+
+		Clear.render(popup_area, f.buffer_mut());
+		f.render_widget(Paragraph::new(lines).block(block), popup_area);
+	}
+}
+
+impl PopupHandleKey for TrimPopup {
+	fn handle_key(&mut self, event: KeyEvent) -> bool {
+		use KeyCode::*;
+		match event.code {
+			Up|Down => {
+				self.selected = 1 - self.selected;
+				true
+			},
+			Char('e') => self.popup_time(),
+			Delete => self.clear_time(),
+			Enter => self.commit_changes(),
+			Esc|Char('q') => self.discard_changes(),
+			_ => false
+		}
+	}
+}
+
+impl TrimPopup {
+	fn popup_time(&self) -> bool {
+		let (init, title) = if self.selected == 0 {
+			(self.start, "Trim Start")
+		} else {
+			(self.end, "Trim End")
+		};
+		defer_set_popup(PopupComponent::Input(InputPopup::new(init.map_or(String::new(), |ms| format!("{}ms", ms)), title.to_string(), FLAG_NONE, |value| {
+			let Some(ms) = parse_time(value) else { return false; };
+			thread::spawn(move || {
+				if let Some(popup) = popups().last_mut() && let PopupComponent::Trim(popup) = popup {
+					let time = if popup.selected == 0 { &mut popup.start } else { &mut popup.end };
+					if *time != Some(ms) {
+						popup.changed = true;
+					}
+					*time = Some(ms);
+				}
+			});
+			false
+		})));
+		true
+	}
+
+	fn clear_time(&mut self) -> bool {
+		let time = if self.selected == 0 { &mut self.start } else { &mut self.end };
+		if time.is_none() {
+			return false;
+		}
+		*time = None;
+		self.changed = true;
+		true
+	}
+
+	fn commit_changes(&self) -> bool {
+		let mut app = acquire();
+		if let (Some(start), Some(end)) = (self.start, self.end) && start >= end {
+			app.error = "Trim start must be before the end".to_string();
+			return true;
+		}
+		app.config.update_file_entry(self.path.clone(), |entry| {
+			entry.start = self.start;
+			entry.end = self.end;
+			true
+		});
+		defer_exit_popup();
+		true
+	}
+
+	fn discard_changes(&self) -> bool {
+		if self.changed {
+			defer_set_popup(PopupComponent::Confirm(ConfirmPopup::new("Discard changes?", "discard", || {
+				defer_exit_popup();
+				false
+			})));
+		} else {
+			defer_exit_popup();
+		}
+		true
+	}
+}
+
+// Accepts milliseconds ("1500", "1500ms") or seconds ("1.5s")
+fn parse_time(value: &str) -> Option<u32> {
+	let value = value.trim();
+	if let Some(ms) = value.strip_suffix("ms") {
+		ms.trim().parse::<u32>().ok()
+	} else if let Some(secs) = value.strip_suffix('s') {
+		let secs = secs.trim().parse::<f32>().ok()?;
+		if !secs.is_finite() || secs < 0.0 {
+			return None;
+		}
+		Some((secs * 1000.0).round() as u32)
+	} else {
+		value.parse::<u32>().ok()
+	}
+}
+
+fn format_time(ms: u32) -> String {
+	format!("{}.{:0>3} s", ms / 1000, ms % 1000)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parses_milliseconds_and_seconds() {
+		assert_eq!(parse_time("1500"), Some(1500));
+		assert_eq!(parse_time("1500ms"), Some(1500));
+		assert_eq!(parse_time(" 1500 ms "), Some(1500));
+		assert_eq!(parse_time("1.5s"), Some(1500));
+		assert_eq!(parse_time("1.5 s"), Some(1500));
+		assert_eq!(parse_time("0"), Some(0));
+	}
+
+	#[test]
+	fn rejects_bad_input() {
+		for value in ["", "abc", "-5", "-1.5s", "1.5ms", "1.5", "s", "ms", "1,5s", "nans", "infs"] {
+			assert_eq!(parse_time(value), None, "{:?} was accepted", value);
+		}
+	}
+
+	#[test]
+	fn formats_back_to_seconds() {
+		assert_eq!(format_time(1500), "1.500 s");
+		assert_eq!(parse_time(format_time(61005).as_str()), Some(61005));
+	}
+}
@@ -1,20 +1,22 @@
 use config::Config;
 use migrate0::ConfigV0;
 use migrate1::ConfigV1;
+use migrate2::ConfigV2;
 use serde::Deserialize;
 
 use crate::constant::CONFIG_VERSION;
 
 use super::get_config_path;
 
-pub use migrate1::FileEntry;
-pub use migrate1::WaveformEntry;
-pub use migrate1::DialogEntry;
+pub use migrate2::FileEntry;
+pub use migrate2::WaveformEntry;
+pub use migrate2::DialogEntry;
 
 mod migrate0;
 mod migrate1;
+mod migrate2;
 
-pub type SoundboardConfig = migrate1::ConfigV1;
+pub type SoundboardConfig = migrate2::ConfigV2;
 
 #[derive(Deserialize, Default)]
 #[serde(default)]
@@ -28,6 +30,7 @@ pub(super) fn migrate_config() -> SoundboardConfig {
 		let version = read_version();
 		match version {
 			0 => migrate_v0(), // should not be possible, but i'm putting it here anyway
+			1 => migrate_v1(),
 			CONFIG_VERSION => SoundboardConfig::read(),
 			_ => SoundboardConfig::default()
 		}
@@ -53,6 +56,10 @@ fn read_version() -> u32 {
 	settings.try_deserialize::<VersoinCheckConfig>().expect("Failed to parse config").version
 }
 
-fn migrate_v0() -> ConfigV1 {
-	ConfigV1::from_v0(ConfigV0::read())
+fn migrate_v0() -> ConfigV2 {
+	ConfigV2::from_v1(ConfigV1::from_v0(ConfigV0::read()))
+}
+
+fn migrate_v1() -> ConfigV2 {
+	ConfigV2::from_v1(ConfigV1::read())
 }
\ No newline at end of file
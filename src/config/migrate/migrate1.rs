@@ -154,58 +154,4 @@ impl ConfigV1 {
 
 		cfg
 	}
-
-	pub fn get_file_entry(&self, path: &str) -> Option<&FileEntry> {
-		let (parent, name) = parent_file(path);
-		match self.files.get(&parent) {
-			Some(map) => match map.get(&name) {
-				Some(entry) => Some(entry),
-				None => None
-			},
-			None => None
-		}
-	}
-
-	pub fn get_file_entry_mut(&mut self, path: String) -> Option<&mut FileEntry> {
-		let (parent, name) = parent_file(&path);
-		match self.files.get_mut(&parent) {
-			Some(map) => match map.get_mut(&name) {
-				Some(entry) => Some(entry),
-				None => None
-			},
-			None => None
-		}
-	}
-
-	pub fn insert_file_entry(&mut self, path: String, entry: FileEntry) {
-		let (parent, name) = parent_file(&path);
-		match self.files.get_mut(&parent) {
-			Some(map) => {
-				map.insert(name, entry);
-			},
-			None => {
-				let mut map = HashMap::new();
-				map.insert(name, entry);
-				self.files.insert(parent, map);
-			}
-		}
-	}
-
-	pub fn remove_file_entry(&mut self, path: String) -> bool {
-		let (parent, name) = parent_file(&path);
-		match self.files.get_mut(&parent) {
-			Some(map) => {
-				let removed = map.remove(&name);
-				return removed.is_some();
-			},
-			None => false
-		}
-	}
 }
-
-impl FileEntry {
-	pub fn is_default(&self) -> bool {
-		let def = Self::default();
-		return self == &def;
-	}
-}
\ No newline at end of file
@@ -0,0 +1,383 @@
+use std::collections::{HashMap, HashSet};
+
+use config::Config;
+use serde::{Deserialize, Serialize};
+
+use crate::util::{channels::OutputChannels, effects::Effect, file::{PlaybackMode, Resampling, Retrigger, parent_file}, limiter::LimiterMode, wave::Wave};
+
+use super::{get_config_path, migrate1::ConfigV1};
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct FileEntry {
+	pub volume: u32,
+	pub keys: HashSet<String>,
+	pub id: Option<u32>,
+	#[serde(default)]
+	pub fade_in: u32,
+	#[serde(default)]
+	pub fade_out: u32,
+	// Trim points in milliseconds
+	#[serde(default)]
+	pub start: Option<u32>,
+	#[serde(default)]
+	pub end: Option<u32>,
+	#[serde(default)]
+	pub mode: PlaybackMode,
+	// Overrides the global resample quality
+	#[serde(default)]
+	pub resample: Option<Resampling>,
+	// Playback rate in percent
+	#[serde(default = "default_speed")]
+	pub speed: u32,
+	// Pitch shift in semitones
+	#[serde(default)]
+	pub pitch: i32,
+	// Keep the pitch when the speed changes
+	#[serde(default)]
+	pub stretch: bool,
+	// Applied in order
+	#[serde(default)]
+	pub effects: Vec<Effect>,
+	// Lowers every other sound while this one plays
+	#[serde(default)]
+	pub duck: bool,
+	#[serde(default)]
+	pub retrigger: Retrigger,
+	// Most copies playing at once when overlapping. 0 for no limit.
+	#[serde(default)]
+	pub max_voices: u32,
+	// Triggers within this many milliseconds of the last are ignored
+	#[serde(default)]
+	pub cooldown: u32,
+	// Choke group. Starting a sound in a group stops the others playing in it.
+	#[serde(default)]
+	pub group: Option<String>,
+	// -100 (left) to 100 (right)
+	#[serde(default)]
+	pub pan: i32,
+}
+
+fn default_speed() -> u32 {
+	100
+}
+
+impl Default for FileEntry {
+	fn default() -> Self {
+		Self {
+			volume: 100,
+			keys: HashSet::new(),
+			id: Option::None,
+			fade_in: 0,
+			fade_out: 0,
+			start: Option::None,
+			end: Option::None,
+			mode: PlaybackMode::OneShot,
+			resample: Option::None,
+			speed: 100,
+			pitch: 0,
+			stretch: false,
+			effects: vec![],
+			duck: false,
+			retrigger: Retrigger::Overlap,
+			max_voices: 0,
+			cooldown: 0,
+			group: Option::None,
+			pan: 0,
+		}
+	}
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct WaveformEntry {
+	pub label: String,
+	pub id: Option<u32>,
+	pub keys: HashSet<String>,
+	pub waves: Vec<Wave>,
+	pub volume: u32,
+	#[serde(default)]
+	pub fade_in: u32,
+	#[serde(default)]
+	pub fade_out: u32,
+	// Choke group shared with files and dialogs
+	#[serde(default)]
+	pub group: Option<String>,
+	// -100 (left) to 100 (right)
+	#[serde(default)]
+	pub pan: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct DialogEntry {
+	pub label: String,
+	pub id: Option<u32>,
+	pub keys: HashSet<String>,
+	pub files: Vec<String>,
+	pub delay: f32,
+	pub random: bool,
+	pub sequential: bool,
+	pub volume: u32,
+	#[serde(default)]
+	pub fade_in: u32,
+	#[serde(default)]
+	pub fade_out: u32,
+	// Applied in order to every file the dialog plays
+	#[serde(default)]
+	pub effects: Vec<Effect>,
+	// Choke group of every file the dialog plays, instead of the files' own
+	#[serde(default)]
+	pub group: Option<String>,
+	// Added to the pan of every file the dialog plays
+	#[serde(default)]
+	pub pan: i32,
+	// Each file is panned up to this far either side at random
+	#[serde(default)]
+	pub pan_random: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct ConfigV2 {
+	pub version: u32,
+	pub tabs: Vec<String>,
+	pub volume: u32,
+	pub stop_key: HashSet<String>,
+	pub loopback_default: bool,
+	pub loopback_1: String,
+	pub loopback_2: String,
+	pub playlist_mode: bool,
+	pub fast_scan: bool,
+	pub stop_fade: u32,
+	pub normalize: bool,
+	pub normalize_target: f32,
+	pub limiter: LimiterMode,
+	// Output ceiling in dBFS
+	pub limiter_ceiling: f32,
+	// Applied in order to the whole mix, before the limiter
+	pub master_effects: Vec<Effect>,
+	// How far sounds under a ducker are lowered, in dB
+	pub duck_amount: f32,
+	// Time to duck and to recover, in milliseconds
+	pub duck_attack: u32,
+	pub duck_release: u32,
+	pub duck_waves: bool,
+	// Seconds of output kept for saving a replay. 0 turns it off.
+	pub replay_length: u32,
+	pub replay_key: HashSet<String>,
+	// Tab that replays are saved into. Empty to save them in the audio directory.
+	pub replay_tab: String,
+	pub output_rate: u32,
+	pub output_channels: OutputChannels,
+	// Output latency in milliseconds, which sets the mixer chunk and the device buffer
+	pub latency: u32,
+	pub resample_quality: Resampling,
+	// Decoded audio kept in memory, in megabytes
+	pub cache_size: u32,
+	// Decoded audio saved between runs, in megabytes. 0 turns it off.
+	pub disk_cache_size: u32,
+	// Second output for hearing sounds locally. Empty when off.
+	pub monitor: String,
+	pub monitor_volume: u32,
+	// Input mixed into the output. Empty when off.
+	pub mic: String,
+	pub mic_gain: u32,
+	pub mic_muted: bool,
+	pub files: HashMap<String, HashMap<String, FileEntry>>,
+	pub waves: Vec<WaveformEntry>,
+	pub dialogs: Vec<DialogEntry>,
+}
+
+impl Default for ConfigV2 {
+	fn default() -> Self {
+		Self {
+			version: 2,
+			tabs: vec![],
+			volume: 100,
+			stop_key: HashSet::new(),
+			loopback_default: true,
+			loopback_1: String::new(),
+			loopback_2: String::new(),
+			playlist_mode: false,
+			fast_scan: true,
+			stop_fade: 0,
+			normalize: false,
+			normalize_target: -16.0,
+			limiter: LimiterMode::Limiter,
+			limiter_ceiling: -1.0,
+			master_effects: vec![],
+			duck_amount: 12.0,
+			duck_attack: 50,
+			duck_release: 500,
+			duck_waves: false,
+			replay_length: 0,
+			replay_key: HashSet::new(),
+			replay_tab: String::new(),
+			output_rate: 48000,
+			output_channels: OutputChannels::Stereo,
+			latency: 20,
+			resample_quality: Resampling::Low,
+			cache_size: 512,
+			disk_cache_size: 2048,
+			monitor: String::new(),
+			monitor_volume: 100,
+			mic: String::new(),
+			mic_gain: 100,
+			mic_muted: false,
+			files: HashMap::new(),
+			waves: vec![],
+			dialogs: vec![],
+		}
+	}
+}
+
+impl ConfigV2 {
+	pub(super) fn read() -> ConfigV2 {
+		let settings = Config::builder()
+			.add_source(config::File::new(get_config_path(false).to_str().unwrap(), config::FileFormat::Json))
+			.build()
+			.expect("Failed to build config");
+
+		settings.try_deserialize::<ConfigV2>().expect("Failed to parse config")
+	}
+
+	pub(super) fn from_v1(config: ConfigV1) -> ConfigV2 {
+		let files = config.files.into_iter().map(|(parent, map)| {
+			(parent, map.into_iter().map(|(name, entry)| {
+				(name, FileEntry {
+					volume: entry.volume,
+					keys: entry.keys,
+					id: entry.id,
+					fade_in: 0,
+					fade_out: 0,
+					start: Option::None,
+					end: Option::None,
+					mode: PlaybackMode::OneShot,
+					resample: Option::None,
+					speed: 100,
+					pitch: 0,
+					stretch: false,
+					effects: vec![],
+					duck: false,
+					retrigger: Retrigger::Overlap,
+					max_voices: 0,
+					cooldown: 0,
+					group: Option::None,
+					pan: 0,
+				})
+			}).collect())
+		}).collect();
+		let waves = config.waves.into_iter().map(|entry| WaveformEntry {
+			label: entry.label,
+			id: entry.id,
+			keys: entry.keys,
+			waves: entry.waves,
+			volume: entry.volume,
+			..WaveformEntry::default()
+		}).collect();
+		let dialogs = config.dialogs.into_iter().map(|entry| DialogEntry {
+			label: entry.label,
+			id: entry.id,
+			keys: entry.keys,
+			files: entry.files,
+			delay: entry.delay,
+			random: entry.random,
+			sequential: entry.sequential,
+			volume: entry.volume,
+			..DialogEntry::default()
+		}).collect();
+
+		ConfigV2 {
+			tabs: config.tabs,
+			volume: config.volume,
+			stop_key: config.stop_key,
+			loopback_default: config.loopback_default,
+			loopback_1: config.loopback_1,
+			loopback_2: config.loopback_2,
+			playlist_mode: config.playlist_mode,
+			fast_scan: config.fast_scan,
+			files,
+			waves,
+			dialogs,
+			..ConfigV2::default()
+		}
+	}
+
+	pub fn get_file_entry(&self, path: &str) -> Option<&FileEntry> {
+		let (parent, name) = parent_file(path);
+		match self.files.get(&parent) {
+			Some(map) => match map.get(&name) {
+				Some(entry) => Some(entry),
+				None => None
+			},
+			None => None
+		}
+	}
+
+	// Resample quality of a file, falling back to the global setting
+	pub fn file_resampling(&self, path: &str) -> Resampling {
+		self.get_file_entry(path).and_then(|entry| entry.resample).unwrap_or(self.resample_quality)
+	}
+
+	pub fn get_file_entry_mut(&mut self, path: String) -> Option<&mut FileEntry> {
+		let (parent, name) = parent_file(&path);
+		match self.files.get_mut(&parent) {
+			Some(map) => match map.get_mut(&name) {
+				Some(entry) => Some(entry),
+				None => None
+			},
+			None => None
+		}
+	}
+
+	pub fn insert_file_entry(&mut self, path: String, entry: FileEntry) {
+		let (parent, name) = parent_file(&path);
+		match self.files.get_mut(&parent) {
+			Some(map) => {
+				map.insert(name, entry);
+			},
+			None => {
+				let mut map = HashMap::new();
+				map.insert(name, entry);
+				self.files.insert(parent, map);
+			}
+		}
+	}
+
+	pub fn remove_file_entry(&mut self, path: String) -> bool {
+		let (parent, name) = parent_file(&path);
+		match self.files.get_mut(&parent) {
+			Some(map) => map.remove(&name).is_some(),
+			None => false
+		}
+	}
+
+	// Changes the entry of a file, adding or removing it so only non-default entries are kept.
+	// Returns false, leaving the entry alone, if `apply` made no change.
+	pub fn update_file_entry(&mut self, path: String, apply: impl FnOnce(&mut FileEntry) -> bool) -> bool {
+		match self.get_file_entry_mut(path.clone()) {
+			Some(entry) => {
+				if !apply(entry) {
+					return false;
+				}
+				if entry.is_default() {
+					self.remove_file_entry(path);
+				}
+			},
+			None => {
+				let mut entry = FileEntry::default();
+				if !apply(&mut entry) {
+					return false;
+				}
+				if !entry.is_default() {
+					self.insert_file_entry(path, entry);
+				}
+			}
+		}
+		true
+	}
+}
+
+impl FileEntry {
+	pub fn is_default(&self) -> bool {
+		self == &Self::default()
+	}
+}
@@ -1,9 +1,9 @@
-use std::{io, sync::{Arc, Mutex}, time::Duration};
+use std::{io, sync::{Arc, Mutex}, thread, time::Duration};
 use crossterm::event::{Event, KeyEvent, KeyEventKind, poll, read};
 use mki::Action;
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 
-use crate::{component::{block::{self, log}, layer, popup::{PopupHandleGlobalKey, PopupHandleKey, PopupHandlePaste, popups}}, constant::{MIN_HEIGHT, MIN_WIDTH}, state::{SelectionLayer, acquire, is_running, notify_redraw, stop_running}, util::file::{play_file_auto_volume, stop_all}};
+use crate::{component::{block::{self, log}, layer, popup::{PopupHandleGlobalKey, PopupHandleKey, PopupHandlePaste, popups}}, constant::{MIN_HEIGHT, MIN_WIDTH}, state::{SelectionLayer, acquire, is_running, notify_redraw, stop_running}, util::{file::{play_file_auto_volume, stop_all}, replay::save_replay}};
 
 pub fn program_loop() -> io::Result<()> {
 	// Global key listener
@@ -23,14 +23,23 @@ pub fn program_loop() -> io::Result<()> {
 				} else {
 					Arc::new(Mutex::new(()))
 				};
-				play_file_auto_volume(path, lock);
+				play_file_auto_volume(path, true, lock);
 			}
 		});
 		if !app.stopkey.is_empty() && !app.edit {
 			if app.stopkey.par_iter().all(|key| { key.is_pressed() }) {
-				stop_all();
+				stop_all(app.config.stop_fade);
 			}
 		}
+		if !app.replaykey.is_empty() && !app.edit && app.replaykey.par_iter().all(|key| { key.is_pressed() }) {
+			// Defer to avoid deadlock
+			thread::spawn(|| {
+				match save_replay(None) {
+					Ok((path, _)) => log::info(format!("Saved replay to {}", path).as_str()),
+					Err(err) => log::error(format!("Failed to save replay: {}", err).as_str()),
+				}
+			});
+		}
 
 		// Waveform hotkey
 		app.waves.par_iter().for_each(|wave| {
@@ -3,9 +3,9 @@ use std::panic;
 use socket::{send_exit, send_socket};
 use util::pulseaudio::{load_null_sink, loopback};
 use state::Scanning;
-use clap::{command, Arg, ArgAction, Command};
+use clap::{command, Arg, ArgAction, ArgMatches, Command};
 
-use crate::{component::block::{BlockSingleton, log}, listener::{listen_signals, program_loop}, renderer::draw_loop, socket::start_socket, state::{acquire, stop_running}, util::{audio::{PlayerType, create_audio_player, list_audio_devices}, file::audio_cache_invalidator, tab::scan}};
+use crate::{component::block::{BlockSingleton, log}, listener::{listen_signals, program_loop}, renderer::draw_loop, socket::start_socket, state::{acquire, stop_running}, constant::APP_NAME, util::{audio::{create_audio_player, list_audio_devices, set_mix_settings}, backend::Backend, disk_cache::clear_disk_cache, file::set_cache_limits, input::create_input, latency::latency_test, monitor::create_monitor, record::stop_recording, render::{RenderLength, render_dialog, render_wave}, replay::set_replay_length, tab::scan}};
 mod component;
 mod config;
 mod constant;
@@ -29,8 +29,29 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 		.arg(Arg::new("fast-scan").long("fast-scan").help("scan files by extensions instead of header").action(ArgAction::SetTrue))
 		.arg(Arg::new("no-pacat").long("no-pacat").help("avoid using pacat for playback").action(ArgAction::SetTrue))
 		.arg(Arg::new("audio-device").long("audio-device").help("output audio device to use (ignored with pacat)").action(ArgAction::Set))
+		.args(backend_args())
 		.subcommand(Command::new("exit").about("exit another instance"))
 		.subcommand(Command::new("audio-devices").about("list available audio devices"))
+		.subcommand(Command::new("clear-cache").about("delete decoded audio saved on disk"))
+		.subcommand(Command::new("latency-test").about("measure how steadily the output is fed, to tune the latency setting")
+			.args(backend_args())
+			.args([
+				Arg::new("latency").long("latency").help("latency to test in milliseconds, defaults to the latency setting"),
+				Arg::new("duration").long("duration").help("seconds to test for (default 5)")
+			]))
+		.subcommand(Command::new("render").about("render a waveform or dialog to a WAV file without playing it").subcommand_required(true)
+			.subcommand(Command::new("wave").about("render a waveform by user-defined ID").args([
+				Arg::new("id").required(true),
+				Arg::new("file").required(true),
+				Arg::new("duration").long("duration").help("seconds to hold the waveform for, before its fade out (default 1)")
+			]))
+			.subcommand(Command::new("dialog").about("render a dialog by user-defined ID").args([
+				Arg::new("id").required(true),
+				Arg::new("file").required(true),
+				Arg::new("duration").long("duration").conflicts_with("clips").help("seconds to keep starting clips for (default 1)"),
+				Arg::new("clips").long("clips").help("number of clips to play instead"),
+				Arg::new("seed").long("seed").help("seed for picking and panning clips, for the same render every time")
+			])))
 		.subcommand(Command::new("reload-config").about("reload config for another instance"))
 		.subcommand(Command::new("add-tab").about("add a directory tab").arg(Arg::new("dir").required(true)))
 		.subcommand(Command::new("delete-tab").about("delete a tab, defaults to the selected one")
@@ -49,9 +70,21 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 		.subcommand(Command::new("play-wave").about("play a waveform by user-defined ID").arg(Arg::new("id").required(true)))
 		.subcommand(Command::new("play-dialog").about("play a dialog by user-defined ID").arg(Arg::new("id").required(true)))
 		.subcommand(Command::new("play-search").about("play a searched audio file").arg(Arg::new("query").required(true)))
-		.subcommand(Command::new("stop").about("stop all playing files"))
+		.subcommand(Command::new("stop").about("stop all playing files").arg(Arg::new("fade").long("fade").help("fade out over this many milliseconds, defaults to the stop fade setting")))
 		.subcommand(Command::new("stop-wave").about("stop a waveform by user-defined ID").arg(Arg::new("id").required(true)))
 		.subcommand(Command::new("stop-dialog").about("stop a dialog by user-defined ID").arg(Arg::new("id").required(true)))
+		.subcommand(Command::new("stop-group").about("stop everything playing in a choke group").arg(Arg::new("name").required(true)))
+		.subcommand(Command::new("pause").about("pause a playing file, or all of them").arg(Arg::new("uuid").help("UUID of the playback to pause")))
+		.subcommand(Command::new("resume").about("resume a paused file, or all of them").arg(Arg::new("uuid").help("UUID of the playback to resume")))
+		.subcommand(Command::new("seek").about("seek playing files forward or back").args([
+			Arg::new("offset").required(true).allow_hyphen_values(true).help("milliseconds to seek by, negative to go back"),
+			Arg::new("uuid").long("uuid").help("UUID of the playback to seek, defaults to all of them")
+		]))
+		.subcommand(Command::new("playing").about("list playing files with the UUIDs pause, resume and seek take"))
+		.subcommand(Command::new("record").about("record the output to a file").subcommand_required(true)
+			.subcommand(Command::new("start").about("start recording, as FLAC if the file ends with .flac (needs ffmpeg) and WAV otherwise").arg(Arg::new("file").required(true)))
+			.subcommand(Command::new("stop").about("stop recording and finish the file")))
+		.subcommand(Command::new("save-replay").about("save the last seconds of output to a WAV file").arg(Arg::new("path").help("file to save to, defaults to the replay tab or the audio directory")))
 		.subcommand(Command::new("set-volume").about("set volume of the sink or a file").args([
 			Arg::new("volume").help("new volume or volume increment (-200 - +200)"),
 			Arg::new("increment").long("increment").help("increment volume instead of setting it").action(ArgAction::SetTrue),
@@ -75,6 +108,43 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 				list_audio_devices()?;
 				return Ok(())
 			},
+			"clear-cache" => {
+				let size = clear_disk_cache()?;
+				println!("Cleared {} MB of cached audio", size / 1024 / 1024);
+				return Ok(())
+			},
+			"latency-test" => {
+				let latency = matches.get_one::<String>("latency").map(|latency| latency.parse::<u32>()).transpose()?;
+				let duration = matches.get_one::<String>("duration").map_or(Ok(5.0), |duration| duration.parse::<f32>())?;
+				let backend = backend_from(matches);
+				println!("Testing the {} output for {} seconds...", backend.name(), duration);
+				match latency_test(&backend, latency, duration) {
+					Ok(report) => println!("{}", report),
+					Err(err) => panic!("Failed\n{}", err),
+				}
+				return Ok(())
+			},
+			"render" => {
+				let (target, matches) = matches.subcommand().unwrap();
+				let id = matches.get_one::<String>("id").unwrap().parse::<u32>()?;
+				let file = matches.get_one::<String>("file").unwrap();
+				let duration = matches.get_one::<String>("duration").map_or(Ok(1.0), |duration| duration.parse::<f32>())?;
+				let result = if target == "wave" {
+					render_wave(id, file, duration)
+				} else {
+					let length = match matches.get_one::<String>("clips") {
+						Some(clips) => RenderLength::Clips(clips.parse::<usize>()?),
+						None => RenderLength::Seconds(duration),
+					};
+					let seed = matches.get_one::<String>("seed").map(|seed| seed.parse::<u64>()).transpose()?;
+					render_dialog(id, file, length, seed)
+				};
+				match result {
+					Ok(seconds) => println!("Rendered {:.2} seconds to {}", seconds, file),
+					Err(err) => panic!("Failed\n{}", err),
+				}
+				return Ok(())
+			},
 			_ => {
 				let response = send_socket((subcommand, matches))?;
 				if response.starts_with("Success") {
@@ -90,6 +160,10 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 	let mut app = acquire();
 	(app.hidden, app.edit, app.no_pacat) = (matches.get_flag("hidden"), matches.get_flag("edit"), matches.get_flag("no-pacat"));
 	app.cpal_device = matches.get_one::<String>("audio-device").unwrap_or(&String::new()).clone();
+	app.backend = backend_from(&matches);
+	set_cache_limits(&app.config);
+	set_replay_length(app.config.replay_length);
+	set_mix_settings(&app.config);
 
 	if app.hidden && app.edit {
 		// Mutually exclusive options
@@ -98,9 +172,9 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 		return Ok(());
 	}
 
-	// PulseAudio setup
-	if !app.edit {
-		app.module_null_sink = load_null_sink();
+	// PulseAudio setup, unless nothing plays into the sink
+	if !app.edit && app.backend.uses_sink() {
+		app.module_null_sink = load_null_sink(app.config.output_rate, app.config.output_channels);
 		if app.config.loopback_default {
 			app.module_loopback_default = loopback("@DEFAULT_SINK@".to_string());
 		}
@@ -127,11 +201,14 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 		scan(Scanning::All);
 		let socket_thread = start_socket();
 		// Audio players
-		if !is_edit {
-			create_audio_player(PlayerType::File);
-			create_audio_player(PlayerType::Wave);
-			audio_cache_invalidator();
-		}
+		let audio_thread = if !is_edit {
+			let thread = create_audio_player();
+			create_monitor();
+			create_input();
+			Some(thread)
+		} else {
+			None
+		};
 		let draw_thread = if !is_hidden {
 			Some(draw_loop())
 		} else {
@@ -140,6 +217,8 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 		// Keep the program running
 		program_loop().ok();
 		draw_thread.map(|thread| thread.join());
+		// Let the output finish, such as the file of the wav-file backend
+		audio_thread.map(|thread| thread.join());
 		// Wait for all threads to end before closing
 		socket_thread.map(|thread| {
 			send_exit().ok();
@@ -150,6 +229,8 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 	if let Err(err) = result {
 		log::error(&format!("{:?}", err));
 	}
+	// Finish the file if still recording
+	stop_recording();
 	// Finish up PulseAudio
 	{ acquire().unload_modules(); }
 	if !is_hidden && !matches.get_flag("no-save") {
@@ -158,4 +239,26 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 	}
 	log::LogBlock::instance().flush_console();
 	Ok(())
+}
+
+// Options for picking the output, shared by running and testing it
+fn backend_args() -> [Arg; 3] {
+	[
+		Arg::new("backend").long("backend").help("where to play the output: auto, pacat, cpal, null or wav-file").value_parser(["auto", "pacat", "cpal", "null", "wav-file"]).action(ArgAction::Set),
+		Arg::new("output-file").long("output-file").help("file for the wav-file backend (default cls-output.wav)").action(ArgAction::Set),
+		Arg::new("fast").long("fast").help("let the wav-file backend mix faster than real time while something plays").action(ArgAction::SetTrue),
+	]
+}
+
+fn backend_from(matches: &ArgMatches) -> Backend {
+	match matches.get_one::<String>("backend").map(String::as_str) {
+		Some("pacat") => Backend::Pacat,
+		Some("cpal") => Backend::Cpal,
+		Some("null") => Backend::Null,
+		Some("wav-file") => Backend::WavFile {
+			path: matches.get_one::<String>("output-file").cloned().unwrap_or(format!("{}-output.wav", APP_NAME)),
+			fast: matches.get_flag("fast"),
+		},
+		_ => Backend::Auto,
+	}
 }
\ No newline at end of file
@@ -60,7 +60,7 @@ pub fn ui(f: &mut Frame) {
 		.margin(1)
 		.constraints(
 			[
-				Constraint::Length(7),
+				Constraint::Length(8),
 				Constraint::Length(3),
 				Constraint::Fill(1),
 				Constraint::Length(1)
@@ -5,8 +5,9 @@ use code::SocketCode;
 use interprocess::local_socket::{traits::{ListenerExt, Stream as _}, GenericFilePath, GenericNamespaced, Listener, ListenerOptions, Name, NameType, Stream, ToFsName, ToNsName};
 use normpath::PathExt;
 use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
+use uuid::Uuid;
 
-use crate::{component::block::{BlockSingleton, log, results::{ResultsBlock, SearchResult}, search::SearchBlock, tabs::TabsBlock}, config::FileEntry, constant::APP_NAME, state::{Scanning, acquire, is_running, load_app_config, notify_redraw, stop_running}, util::{file::{parent_file, play_file_auto_volume, stop_all}, tab::scan, wave::stop_all_waves}};
+use crate::{component::block::{BlockSingleton, log, results::{ResultsBlock, SearchResult}, search::SearchBlock, tabs::TabsBlock}, config::FileEntry, constant::APP_NAME, state::{Scanning, acquire, is_running, load_app_config, notify_redraw, stop_running}, util::{audio::{reset_output, set_mix_settings}, choke::stop_group, file::{acquire_playing_files, clear_cached_files, parent_file, play_file_auto_volume, preload_audio, seek, set_cache_limits, set_paused, stop_all}, record::{start_recording, stop_recording}, replay::{save_replay, set_replay_length}, tab::scan, wave::stop_all_waves}};
 
 pub mod code;
 
@@ -86,13 +87,22 @@ fn handle_stream(mut reader: BufReader<Stream>) -> std::io::Result<bool> {
 			return send_response(reader.get_mut(), &[0], true);
 		},
 		ReloadConfig => {
-			let (config, stopkey, hotkey, rev_file_id, waves, dialogs) = load_app_config();
+			let (config, stopkey, replaykey, hotkey, rev_file_id, waves, dialogs) = load_app_config();
+			if config.output_rate != app.config.output_rate || config.output_channels != app.config.output_channels || config.latency != app.config.latency {
+				reset_output();
+			}
+			clear_cached_files(|path| config.file_resampling(path) != app.config.file_resampling(path));
 			app.config = config;
 			app.stopkey = stopkey;
+			app.replaykey = replaykey;
 			app.hotkey = hotkey;
 			app.file_ids = rev_file_id;
 			app.waves = waves;
 			app.dialogs = dialogs;
+			set_cache_limits(&app.config);
+			set_replay_length(app.config.replay_length);
+			set_mix_settings(&app.config);
+			preload_audio();
 			notify_redraw();
 			return send_response(reader.get_mut(), &[0], true);
 		},
@@ -185,7 +195,7 @@ fn handle_stream(mut reader: BufReader<Stream>) -> std::io::Result<bool> {
 				} else {
 					Arc::new(Mutex::new(()))
 				};
-				play_file_auto_volume(&path.to_string(), lock);
+				play_file_auto_volume(&path.to_string(), false, lock);
 				notify_redraw();
 				let mut bytes = path.as_bytes().to_vec();
 				bytes.insert(0, 0);
@@ -206,7 +216,7 @@ fn handle_stream(mut reader: BufReader<Stream>) -> std::io::Result<bool> {
 					} else {
 						Arc::new(Mutex::new(()))
 					};
-					play_file_auto_volume(&path, lock);
+					play_file_auto_volume(&path, false, lock);
 					notify_redraw();
 					let mut bytes = path.as_bytes().to_vec();
 					bytes.insert(0, 0);
@@ -308,12 +318,121 @@ fn handle_stream(mut reader: BufReader<Stream>) -> std::io::Result<bool> {
 			bytes.insert(0, 10);
 			return send_response(reader.get_mut(), &bytes, true);
 		},
+		Record => {
+			let mut start = [0];
+			reader.read_exact(&mut start)?;
+			if start[0] == 0 {
+				let Some(path) = stop_recording() else { return send_response(reader.get_mut(), &[1], false); };
+				let mut bytes = path.into_bytes();
+				bytes.insert(0, 10);
+				return send_response(reader.get_mut(), &bytes, true);
+			}
+			let mut chars = vec![];
+			reader.read_until(0, &mut chars)?;
+			chars.pop();
+			let path = str::from_utf8(&chars).unwrap_or("");
+			match start_recording(path) {
+				Ok(()) => {
+					let mut bytes = path.as_bytes().to_vec();
+					bytes.insert(0, 0);
+					send_response(reader.get_mut(), &bytes, true)
+				},
+				Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => send_response(reader.get_mut(), &[3], false),
+				Err(err) => {
+					log::error(format!("Failed to record to {}: {:?}", path, err).as_str());
+					send_response(reader.get_mut(), &[2], false)
+				}
+			}
+		},
+		SaveReplay => {
+			let mut chars = vec![];
+			reader.read_until(0, &mut chars)?;
+			chars.pop();
+			let path = str::from_utf8(&chars).unwrap_or("");
+			// Saving into a tab needs the app
+			drop(app);
+			match save_replay((!path.is_empty()).then(|| path.to_string())) {
+				Ok((path, id)) => {
+					let mut bytes = path.into_bytes();
+					bytes.insert(0, 0);
+					if let Some(id) = id {
+						bytes.splice(1..1, id.to_le_bytes());
+						bytes[0] = 10;
+					}
+					send_response(reader.get_mut(), &bytes, true)
+				},
+				Err(err) if err.kind() == std::io::ErrorKind::NotFound => send_response(reader.get_mut(), &[1], false),
+				Err(err) => {
+					log::error(format!("Failed to save replay: {:?}", err).as_str());
+					send_response(reader.get_mut(), &[2], false)
+				}
+			}
+		},
+		StopGroup => {
+			let mut chars = vec![];
+			reader.read_until(0, &mut chars)?;
+			chars.pop();
+			let name = str::from_utf8(&chars).unwrap_or("");
+			if name.is_empty() {
+				return send_response(reader.get_mut(), &[1], false);
+			}
+			stop_group(name.to_string());
+			notify_redraw();
+			send_response(reader.get_mut(), &[10], true)
+		},
 		Stop => {
-			stop_all();
-			stop_all_waves();
+			let mut has_fade = [0];
+			reader.read_exact(&mut has_fade)?;
+			let fade = if has_fade[0] == 1 {
+				let mut bytes = [0; 4];
+				reader.read_exact(&mut bytes)?;
+				u32::from_le_bytes(bytes)
+			} else {
+				app.config.stop_fade
+			};
+			stop_all(fade);
+			stop_all_waves(fade);
 			notify_redraw();
 			return send_response(reader.get_mut(), &[0], true);
 		},
+		Pause|Resume|Seek => {
+			let offset = if code == Seek {
+				let mut bytes = [0; 4];
+				reader.read_exact(&mut bytes)?;
+				i32::from_le_bytes(bytes)
+			} else {
+				0
+			};
+			let mut has_uuid = [0];
+			reader.read_exact(&mut has_uuid)?;
+			let uuid = if has_uuid[0] == 1 {
+				let mut bytes = [0; 16];
+				reader.read_exact(&mut bytes)?;
+				Some(Uuid::from_bytes(bytes))
+			} else {
+				None
+			};
+			// Playing files are locked before the app by the mixer
+			drop(app);
+			let success = match code {
+				Pause => set_paused(uuid, true),
+				Resume => set_paused(uuid, false),
+				_ => seek(uuid, offset),
+			};
+			notify_redraw();
+			send_response(reader.get_mut(), &[if success { 10 } else { 1 }], success)
+		},
+		Playing => {
+			// Playing files are locked before the app by the mixer
+			drop(app);
+			let mut voices = acquire_playing_files().iter()
+				.map(|(uuid, playable)| (playable.started, format!("{} {}{}", uuid, playable.path, if playable.paused { " (paused)" } else { "" })))
+				.collect::<Vec<_>>();
+			voices.sort();
+			let mut bytes = voices.into_iter().map(|(_, line)| line).collect::<Vec<_>>().join("\n").into_bytes();
+			bytes.insert(0, 0);
+			send_response(reader.get_mut(), &bytes, true)
+		},
 		SetVolume => {
 			let mut args = [0; 4];
 			reader.read_exact(&mut args)?;
@@ -328,6 +447,7 @@ fn handle_stream(mut reader: BufReader<Stream>) -> std::io::Result<bool> {
 				let new_volume = min(200, max(0, if increment { old_volume + volume } else { volume }));
 				if new_volume != old_volume {
 					app.config.volume = new_volume as u32;
+					set_mix_settings(&app.config);
 				}
 				notify_redraw();
 				let [a, b, c, d] = (new_volume as u32).to_le_bytes();
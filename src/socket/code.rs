@@ -2,6 +2,7 @@ use std::{io::{Read, Write}, path::Path};
 
 use clap::ArgMatches;
 use interprocess::local_socket::Stream;
+use uuid::Uuid;
 
 #[derive(PartialEq, Eq)]
 pub enum SocketCode {
@@ -19,6 +20,13 @@ pub enum SocketCode {
 	Stop,
 	StopWaveId,
 	StopDialogId,
+	StopGroup,
+	Pause,
+	Resume,
+	Seek,
+	Playing,
+	Record,
+	SaveReplay,
 
 	SetVolume,
 }
@@ -41,6 +49,13 @@ impl SocketCode {
 			"stop" => Some(Stop),
 			"stop-wave" => Some(StopWaveId),
 			"stop-dialog" => Some(StopDialogId),
+			"stop-group" => Some(StopGroup),
+			"pause" => Some(Pause),
+			"resume" => Some(Resume),
+			"seek" => Some(Seek),
+			"playing" => Some(Playing),
+			"record" => Some(Record),
+			"save-replay" => Some(SaveReplay),
 			"set-volume" => Some(SetVolume),
 			_ => None,
 		}
@@ -63,6 +78,13 @@ impl SocketCode {
 			7 => Some(Stop),
 			11 => Some(StopWaveId),
 			13 => Some(StopDialogId),
+			18 => Some(StopGroup),
+			15 => Some(Pause),
+			16 => Some(Resume),
+			17 => Some(Seek),
+			21 => Some(Playing),
+			19 => Some(Record),
+			20 => Some(SaveReplay),
 			8 => Some(SetVolume),
 			_ => None,
 		}
@@ -84,6 +106,13 @@ impl SocketCode {
 			Stop => 7,
 			StopWaveId => 11,
 			StopDialogId => 13,
+			StopGroup => 18,
+			Pause => 15,
+			Resume => 16,
+			Seek => 17,
+			Playing => 21,
+			Record => 19,
+			SaveReplay => 20,
 			SetVolume => 8,
 		}
 	}
@@ -91,6 +120,28 @@ impl SocketCode {
 	pub fn write_to_stream(&self, mut stream: Stream, matches: &ArgMatches) -> std::io::Result<String> {
 		use SocketCode::*;
 		let mut buf = vec![self.to_u8()];
+		if *self == Stop {
+			match matches.get_one::<String>("fade") {
+				Some(fade) => {
+					buf.push(1);
+					buf.extend(fade.parse::<u32>().expect("Failed to parse fade").to_le_bytes());
+				},
+				None => buf.push(0)
+			}
+		}
+		if *self == Seek {
+			let offset = matches.get_one::<String>("offset").expect("Missing `offset` argument").parse::<i32>().expect("Failed to parse offset");
+			buf.extend(offset.to_le_bytes());
+		}
+		if *self == Pause || *self == Resume || *self == Seek {
+			match matches.get_one::<String>("uuid") {
+				Some(uuid) => {
+					buf.push(1);
+					buf.extend(Uuid::parse_str(uuid).expect("Failed to parse UUID").as_bytes());
+				},
+				None => buf.push(0)
+			}
+		}
 		match self {
 			AddTab => {
 				let path = matches.get_one::<String>("dir");
@@ -188,6 +239,63 @@ impl SocketCode {
 					_ => Ok("Failed\nResponse code is unknown".to_string())
 				}
 			},
+			Record => {
+				match matches.subcommand() {
+					Some(("start", matches)) => {
+						// The instance may run from another directory
+						let path = std::path::absolute(matches.get_one::<String>("file").expect("Missing `file` argument"))?;
+						buf.push(1);
+						buf.extend(path.as_os_str().as_encoded_bytes());
+						buf.push(0);
+					},
+					_ => buf.push(0)
+				}
+				stream.write_all(&buf)?;
+				let mut res = vec![];
+				stream.read_to_end(&mut res)?;
+				let path = String::from_utf8_lossy(res.get(1..).unwrap_or_default());
+				match res.first() {
+					Some(0) => Ok(format!("Success\nRecording to {}", path)),
+					Some(10) => Ok(format!("Success\nSaved recording to {}", path)),
+					Some(1) => Ok("Failed\nNot recording".to_string()),
+					Some(2) => Ok("Failed\nCould not create the file".to_string()),
+					Some(3) => Ok("Failed\nAlready recording".to_string()),
+					_ => Ok("Failed\nResponse code is unknown".to_string())
+				}
+			},
+			SaveReplay => {
+				// The instance may run from another directory
+				if let Some(path) = matches.get_one::<String>("path") {
+					buf.extend(std::path::absolute(path)?.as_os_str().as_encoded_bytes());
+				}
+				buf.push(0);
+				stream.write_all(&buf)?;
+				let mut res = vec![];
+				stream.read_to_end(&mut res)?;
+				match res.first() {
+					Some(0) => Ok(format!("Success\nSaved replay to {}", String::from_utf8_lossy(&res[1..]))),
+					Some(10) if res.len() >= 5 => {
+						let id = u32::from_le_bytes(res[1..5].try_into().unwrap());
+						Ok(format!("Success\nSaved replay to {} with ID {}", String::from_utf8_lossy(&res[5..]), id))
+					},
+					Some(1) => Ok("Failed\nNothing to replay. Is the replay length set?".to_string()),
+					Some(2) => Ok("Failed\nCould not write the file".to_string()),
+					_ => Ok("Failed\nResponse code is unknown".to_string())
+				}
+			},
+			StopGroup => {
+				let name = matches.get_one::<String>("name").expect("Missing `name` argument");
+				buf.extend(name.as_bytes());
+				buf.push(0);
+				stream.write_all(&buf)?;
+				let mut res = [0u8; 1];
+				stream.read_exact(&mut res)?;
+				match res[0] {
+					10 => Ok(format!("Success\nStopping group {}", name)),
+					1 => Ok("Failed\nGroup name is empty".to_string()),
+					_ => Ok("Failed\nResponse code is unknown".to_string())
+				}
+			},
 			PlaySearch => {
 				let query = matches.get_one::<String>("query").expect("Missing `query` argument");
 				buf.extend(query.as_bytes());
@@ -206,6 +314,34 @@ impl SocketCode {
 					_ => Ok("Failed\nResponse code is unknown".to_string())
 				}
 			},
+			Pause|Resume|Seek => {
+				stream.write_all(&buf)?;
+				let mut res = [0u8; 1];
+				stream.read_exact(&mut res)?;
+				match res[0] {
+					10 => Ok(match self {
+						Pause => "Success\nPaused",
+						Resume => "Success\nResumed",
+						_ => "Success\nSeeked",
+					}.to_string()),
+					1 => Ok(match self {
+						Pause => "Failed\nNo playing file to pause",
+						Resume => "Failed\nNo paused file to resume",
+						_ => "Failed\nNo playing file could be seeked",
+					}.to_string()),
+					_ => Ok("Failed\nResponse code is unknown".to_string())
+				}
+			},
+			Playing => {
+				stream.write_all(&buf)?;
+				let mut res = vec![];
+				stream.read_to_end(&mut res)?;
+				match res.first() {
+					Some(0) if res.len() > 1 => Ok(format!("Success\n{}", String::from_utf8_lossy(&res[1..]))),
+					Some(0) => Ok("Success\nNothing is playing".to_string()),
+					_ => Ok("Failed\nResponse code is unknown".to_string())
+				}
+			},
 			SetVolume => {
 				let volume = matches.get_one::<String>("volume")
 					.expect("Missing `volume` argument").parse::<i16>()
@@ -253,6 +389,6 @@ impl SocketCode {
 					_ => Ok("Failed\nResponse code is unknown".to_string())
 				}
 			},
-		};
+		}
 	}
 }
\ No newline at end of file
@@ -6,7 +6,7 @@ use ratatui::{style::{Color, Style}, widgets::BorderType};
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 use uuid::Uuid;
 
-use crate::{component::block::{BlockNavigation, dialogs::DialogBlock, files::FilesBlock, results::ResultsBlock, waves::WavesBlock}, config::{SoundboardConfig, load}, util::{dialog::Dialog, keyboard::string_to_keyboard, pulseaudio::unload_module, wave::Waveform}};
+use crate::{component::block::{BlockNavigation, dialogs::DialogBlock, files::FilesBlock, results::ResultsBlock, waves::WavesBlock}, config::{SoundboardConfig, load}, util::{backend::Backend, dialog::Dialog, keyboard::string_to_keyboard, pulseaudio::unload_module, wave::Waveform}};
 
 #[derive(Copy, Clone, PartialEq, Eq)]
 pub enum SelectionLayer {
@@ -47,6 +47,7 @@ pub struct App {
 	pub config: SoundboardConfig,
 	pub hotkey: HashMap<String, Vec<Keyboard>>,
 	pub stopkey: Vec<Keyboard>,
+	pub replaykey: Vec<Keyboard>,
 	// states
 	pub error: String,
 	pub error_important: bool,
@@ -55,6 +56,7 @@ pub struct App {
 	pub edit: bool,
 	pub no_pacat: bool,
 	pub cpal_device: String,
+	pub backend: Backend,
 	pub playlist_lock: Arc<Mutex<()>>,
 	pub sample_rate: u32,
 	// render states: root
@@ -73,6 +75,7 @@ pub struct App {
 	pub file_ids: HashMap<u32, String>,
 	// render states: playing
 	pub playing_file: LinkedHashMap<Uuid, String>,
+	pub paused_file: HashSet<Uuid>,
 	pub playing_wave: LinkedHashMap<Uuid, String>,
 	// waves
 	pub waves: Vec<Waveform>,
@@ -115,9 +118,10 @@ fn key_strings_to_keyboards(keys: &HashSet<String>) -> Vec<Keyboard> {
 	}).collect::<Vec<_>>()
 }
 
-pub fn load_app_config() -> (SoundboardConfig, Vec<Keyboard>, HashMap<String, Vec<Keyboard>>, HashMap<u32, String>, Vec<Waveform>, Vec<Dialog>) {
+pub fn load_app_config() -> (SoundboardConfig, Vec<Keyboard>, Vec<Keyboard>, HashMap<String, Vec<Keyboard>>, HashMap<u32, String>, Vec<Waveform>, Vec<Dialog>) {
 	let config = load();
 	let stopkey = key_strings_to_keyboards(&config.stop_key);
+	let replaykey = key_strings_to_keyboards(&config.replay_key);
 	let mut hotkey = HashMap::new();
 	let mut file_ids = HashMap::new();
 	for (parent, map) in &config.files {
@@ -142,6 +146,10 @@ pub fn load_app_config() -> (SoundboardConfig, Vec<Keyboard>, HashMap<String, Ve
 			keys: keyboard,
 			waves: wave.waves.clone(),
 			volume: wave.volume,
+			fade_in: wave.fade_in,
+			fade_out: wave.fade_out,
+			group: wave.group.clone(),
+			pan: wave.pan,
 			playing: Arc::new(Mutex::new((false, false)))
 		}
 	}).collect::<Vec<_>>();
@@ -157,23 +165,31 @@ pub fn load_app_config() -> (SoundboardConfig, Vec<Keyboard>, HashMap<String, Ve
 			random: dialog.random,
 			sequential: dialog.sequential,
 			volume: dialog.volume,
+			fade_in: dialog.fade_in,
+			fade_out: dialog.fade_out,
+			effects: dialog.effects.clone(),
+			group: dialog.group.clone(),
+			pan: dialog.pan,
+			pan_random: dialog.pan_random,
 			play_lock: Arc::new(Mutex::new(())),
 			play_next: 0,
 			playing: Arc::new(Mutex::new((false, false)))
 		}
 	}).collect::<Vec<_>>();
 
-	(config, stopkey, hotkey, file_ids, waves, dialogs)
+	(config, stopkey, replaykey, hotkey, file_ids, waves, dialogs)
 }
 
 pub fn acquire() -> MutexGuard<'static, App> {
 	static APP: LazyLock<Mutex<App>> = LazyLock::new(|| {
-		let (config, stopkey, hotkey, file_ids, waves, dialogs) = load_app_config();
+		let (config, stopkey, replaykey, hotkey, file_ids, waves, dialogs) = load_app_config();
+		let sample_rate = config.output_rate;
 		let app = App {
 			// config
 			config,
 			hotkey,
 			stopkey,
+			replaykey,
 			// states
 			error: String::new(),
 			error_important: false,
@@ -182,8 +198,9 @@ pub fn acquire() -> MutexGuard<'static, App> {
 			edit: true,
 			no_pacat: false,
 			cpal_device: String::new(),
+			backend: Backend::Auto,
 			playlist_lock: Arc::new(Mutex::new(())),
-			sample_rate: 48000,
+			sample_rate,
 			// render states: root
 			block_selected: 0,
 			selection_layer: SelectionLayer::Block,
@@ -200,6 +217,7 @@ pub fn acquire() -> MutexGuard<'static, App> {
 			file_ids,
 			// render states: playing
 			playing_file: LinkedHashMap::new(),
+			paused_file: HashSet::new(),
 			playing_wave: LinkedHashMap::new(),
 			// waves
 			waves,
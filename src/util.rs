@@ -1,7 +1,26 @@
 pub mod audio;
+pub mod backend;
+pub mod cache;
+pub mod channels;
+pub mod choke;
 pub mod dialog;
+pub mod disk_cache;
+pub mod duck;
+pub mod effects;
+pub mod fade;
+pub mod input;
 pub mod file;
 pub mod keyboard;
+pub mod latency;
+pub mod limiter;
+pub mod loudness;
+pub mod monitor;
+pub mod pan;
 pub mod pulseaudio;
+pub mod record;
+pub mod render;
+pub mod replay;
+pub mod shift;
+pub mod stream;
 pub mod tab;
 pub mod wave;
\ No newline at end of file
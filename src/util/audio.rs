@@ -1,253 +1,324 @@
-use std::{f32::consts::PI, io::{self, BufWriter, Write}, process::{Child, ChildStdin, Command, Stdio}, str::FromStr, sync::{Arc, Condvar, LazyLock, Mutex, MutexGuard}, thread, time::{Duration, SystemTime}};
+use std::{io::{self, BufWriter}, process::{Child, ChildStdin, Command, Stdio}, sync::{Arc, LazyLock, Mutex, atomic::{AtomicBool, Ordering}}, thread::{self, JoinHandle}, time::SystemTime};
 
-use cmd_exists::cmd_exists;
-use cpal::{DeviceId, FromSample, Sample, SampleFormat, traits::{DeviceTrait, HostTrait, StreamTrait}};
-use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use cpal::{Device, SampleFormat, SupportedStreamConfigRange, traits::{DeviceTrait, HostTrait}};
 
-use crate::{component::block::log, constant::{APP_NAME, ENDIANESS}, state::{acquire, is_running, notify_redraw}, util::{file::acquire_playing_files, wave::{WaveType, acquire_playing_waves}}};
+use crate::{component::block::log, config::SoundboardConfig, constant::ENDIANESS, state::{acquire, is_running}, util::{backend::{NullOutput, OutputBackend}, channels::{OutputChannels, to_output}, duck::Ducker, effects::{Effect, MasterEffects}, fade::ms_to_frames, file::{acquire_playing_files, clear_audio_cache, clear_playing_files, preload_audio}, input::acquire_input, latency::probe_chunk, limiter::{Limiter, LimiterMode}, monitor::feed_monitor, record::set_output_format, wave::acquire_playing_waves}};
 
-const CHUNK_SIZE: usize = 1024;
+pub struct Pacat {
+	pub last_used: SystemTime,
+	pub child: Child,
+	pub writer: BufWriter<ChildStdin>,
+}
+
+// Smallest chunk mixed, however low the latency is
+const MIN_CHUNK_FRAMES: usize = 32;
+
+// Set when the output format changes, so the mixer restarts its output
+static OUTPUT_RESET: AtomicBool = AtomicBool::new(false);
+static MIX_SETTINGS: LazyLock<Mutex<Arc<MixSettings>>> = LazyLock::new(|| { Mutex::new(Arc::new(MixSettings::new(&SoundboardConfig::default()))) });
 
-struct Pacat {
-	last_used: SystemTime,
-	child: Child,
-	writer: BufWriter<ChildStdin>,
-	discarded: bool,
+// Config the mixer reads, copied out so the audio thread does not lock the app
+struct MixSettings {
+	volume: f32,
+	// Loudness target, if normalization is on
+	normalize_target: Option<f32>,
+	duck_amount: f32,
+	duck_attack: u32,
+	duck_release: u32,
+	duck_waves: bool,
+	master_effects: Vec<Effect>,
+	limiter: LimiterMode,
+	limiter_ceiling: f32,
+	monitor_volume: f32,
+	mic_gain: f32,
+	mic_muted: bool,
+}
+
+impl MixSettings {
+	fn new(config: &SoundboardConfig) -> Self {
+		Self {
+			volume: config.volume as f32 / 100.0,
+			normalize_target: config.normalize.then_some(config.normalize_target),
+			duck_amount: config.duck_amount,
+			duck_attack: config.duck_attack,
+			duck_release: config.duck_release,
+			duck_waves: config.duck_waves,
+			master_effects: config.master_effects.clone(),
+			limiter: config.limiter,
+			limiter_ceiling: config.limiter_ceiling,
+			monitor_volume: config.monitor_volume as f32 / 100.0,
+			mic_gain: config.mic_gain as f32 / 100.0,
+			mic_muted: config.mic_muted,
+		}
+	}
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
-pub enum PlayerType {
-	File,
-	Wave
+// Hands the mixer the volume, normalization, ducking, master bus, monitor and mic settings.
+// Called whenever any of them changes.
+pub fn set_mix_settings(config: &SoundboardConfig) {
+	*MIX_SETTINGS.lock().unwrap() = Arc::new(MixSettings::new(config));
 }
 
-fn spawn_pacat(player_type: PlayerType, sample_rate: u32) -> Pacat {
-	use PlayerType::*;
-	let channels: u8 = match player_type {
-		File => 2,
-		Wave => 2,
-	};
+// Plays into `device`, which is the cls sink for the main output
+pub fn spawn_pacat(device: &str, sample_rate: u32, channels: OutputChannels, latency: u32) -> io::Result<Pacat> {
 	let mut child = Command::new("pacat").args([
 		"-d",
-		APP_NAME,
-		format!("--channels={}", channels).as_str(),
+		device,
+		format!("--channels={}", channels.count()).as_str(),
+		format!("--channel-map={}", channels.channel_map()).as_str(),
 		format!("--rate={}", sample_rate).as_str(),
 		format!("--format=float32{}", ENDIANESS).as_str(),
-		format!("--latency={}", CHUNK_SIZE).as_str()
+		format!("--latency-msec={}", latency).as_str()
 	])
 		.stdin(Stdio::piped())
-		.stdout(Stdio::piped()).spawn().expect("Failed to spawn pacat process");
+		.stdout(Stdio::piped()).spawn()?;
 	let stdin = child.stdin.take().unwrap();
-	Pacat {
+	Ok(Pacat {
 		last_used: SystemTime::now(),
 		child: child,
-		writer: BufWriter::with_capacity(CHUNK_SIZE * 4, stdin),
-		discarded: false,
-	}
+		writer: BufWriter::with_capacity(ms_to_frames(latency, sample_rate) * 4 * channels.count() as usize, stdin),
+	})
 }
 
-fn get_pacat(player_type: PlayerType) -> MutexGuard<'static, Pacat> {
-	static FILES: LazyLock<Mutex<Pacat>> = LazyLock::new(|| Mutex::new(spawn_pacat(PlayerType::File, 48000)));
-	static WAVES: LazyLock<Mutex<Pacat>> = LazyLock::new(|| Mutex::new(spawn_pacat(PlayerType::Wave, 48000)));
-	use PlayerType::*;
-	match player_type {
-		File => FILES.lock().unwrap(),
-		Wave => WAVES.lock().unwrap()
-	}
+// Restarts the output with the sample rate and channels from the config
+pub fn reset_output() {
+	OUTPUT_RESET.store(true, Ordering::Relaxed);
 }
 
-pub fn create_audio_player(player_type: PlayerType) {
+// Whether the output should stop, for exiting or restarting
+pub fn output_stopped() -> bool {
+	!is_running() || OUTPUT_RESET.load(Ordering::Relaxed)
+}
+
+// Spawns the mixer, which writes files and waves to a single output.
+// Joining the thread waits for the output to close.
+pub fn create_audio_player() -> JoinHandle<()> {
 	thread::spawn(move || {
-		use PlayerType::*;
-		if { acquire().no_pacat } || cmd_exists("pacat").is_err() {
-			// No pacat. Use cpal
-			let target_device = { acquire().cpal_device.clone() };
-			let device = if target_device.is_empty() {
-				cpal::default_host().default_output_device().expect("Failed to get default output device")
-			} else {
-				let device_id = DeviceId::from_str(&target_device).expect("Failed to create device ID");
-				let host = cpal::host_from_id(device_id.0).expect("Failed to get host");
-				host.device_by_id(&device_id).expect("Failed to find target device")
-			};
-			let (sample_rate, sample_format, config) = {
-				let mut config_range = device.supported_output_configs().unwrap().cycle();
-				let config = config_range
-				.find(|config| {
-					config.sample_format() == SampleFormat::F32 && config.channels() == 2
-				})
-				.unwrap_or(config_range.next().expect("No output device"));
-				let sample_rate = config.max_sample_rate().min(48000);
-				let config = config.with_sample_rate(sample_rate);
-				(sample_rate, config.sample_format(), config.into())
+		while is_running() {
+			OUTPUT_RESET.store(false, Ordering::Relaxed);
+			let (backend, sample_rate, channels, latency) = {
+				let app = acquire();
+				(app.backend.clone(), app.config.output_rate, app.config.output_channels, app.config.latency)
 			};
-			{ acquire().sample_rate = sample_rate; }
-			log::info(format!("Sample rate: {}", sample_rate).as_str());
-			let err_callback = |err| {
-				log::error(format!("{:?}", err).as_str());
-			};
-			let pair = Arc::new((Mutex::new(false), Condvar::new()));
-			let pair2 = pair.clone();
-			let stream = match sample_format {
-				SampleFormat::F32 => device.build_output_stream(&config, move |data: &mut [f32], _| cpal_data_callback(data, sample_rate, player_type, &pair2), err_callback, None),
-				SampleFormat::I16 => device.build_output_stream(&config, move |data: &mut [i16], _| cpal_data_callback(data, sample_rate, player_type, &pair2), err_callback, None),
-				SampleFormat::U16 => device.build_output_stream(&config, move |data: &mut [u16], _| cpal_data_callback(data, sample_rate, player_type, &pair2), err_callback, None),
-				_ => panic!("Unsupported sample format")
-			}.expect("Failed to create stream");
-			stream.play().unwrap();
-			// cpal will block when playing
-			let (lock, cvar) = &*pair;
-			let mut shared = lock.lock().expect("Failed to get shared mutex");
-			// Wait for redraw notice
-			while !(*shared) {
-				shared = cvar.wait(shared).expect("Failed to get shared mutex");
-			}
-			*shared = false;
-		} else {
-			let mut buf = [0_f32; CHUNK_SIZE];
-			while is_running() {
-				let sample_rate = { acquire().sample_rate };
-				let available = match player_type {
-					File => get_file_data(&mut buf),
-					Wave => get_wave_data(&mut buf, sample_rate),
-				};
-				let mut pacat = get_pacat(player_type);
-				if available {
-					if pacat.discarded {
-						// Pacat is killed. Needs respawn
-						*pacat = spawn_pacat(player_type, acquire().sample_rate);
-					}
-
-					pacat.writer.write_all(&bytemuck::cast_slice(&buf)).expect("Failed to write to pacat stdin");
-					drop(pacat);
-					// If blocked, we wait
-					loop {
-						let mut pacat = get_pacat(player_type);
-						if let Err(err) = pacat.writer.flush() {
-							if err.kind() != io::ErrorKind::WouldBlock {
-								break;
-							}
-							thread::sleep(Duration::from_millis(10));
-						} else {
-							pacat.last_used = SystemTime::now();
-							break;
-						}
-					}
-					buf.fill(0.0);
-				} else if !pacat.discarded {
-					if SystemTime::now().duration_since(pacat.last_used).expect("Failed to get pacat duration").as_secs() > 5 {
-						pacat.child.kill().expect("Failed to kill pacat");
-						pacat.discarded = true;
-					}
-					drop(pacat);
-				}
-				thread::sleep(Duration::from_millis(10));
+			let mut output = backend.create();
+			if let Err(err) = run_output(output.as_mut(), sample_rate, channels, latency) {
+				log::error(format!("Output {} failed: {}", backend.name(), err).as_str());
+				// Keep mixing, so everything else works without a device
+				log::info("Falling back to the null output");
+				drop(output);
+				run_output(&mut NullOutput, sample_rate, channels, latency).ok();
 			}
-			let mut pacat = get_pacat(player_type);
-			if !pacat.discarded {
-				pacat.child.kill().expect("Failed to kill pacat");
+			if is_running() {
+				// Playing and cached audio were decoded at the old sample rate
+				clear_playing_files();
+				clear_audio_cache();
+				log::info("Restarting audio output");
 			}
 		}
-	});
+	})
 }
 
-fn cpal_data_callback<T: Sample + FromSample<f32> + Send>(data: &mut [T], sample_rate: u32, player_type: PlayerType, pair: &Arc<(Mutex<bool>, Condvar)>) {
-	let pair = pair.clone();
-	if !is_running() {
-		let (lock, cvar) = &*pair;
-		let mut shared = lock.lock().expect("Failed to get shared mutex");
-		*shared = true;
-		cvar.notify_one();
-		return;
+fn run_output(output: &mut dyn OutputBackend, sample_rate: u32, channels: OutputChannels, latency: u32) -> Result<(), String> {
+	let (sample_rate, channels) = output.open(sample_rate, channels, latency)?;
+	{ acquire().sample_rate = sample_rate; }
+	set_output_format(sample_rate, channels);
+	preload_audio();
+	log::info(format!("Sample rate: {}, channels: {}, latency: {} ms", sample_rate, channels, latency).as_str());
+	output.run(Mixer::new(sample_rate, channels as usize, latency))
+}
+
+// Prefers the given layout and rate, then any stereo output
+pub fn output_config(device: &Device, rate: u32, channels: u16) -> Option<SupportedStreamConfigRange> {
+	let configs = device.supported_output_configs().ok()?.collect::<Vec<_>>();
+	let supports_rate = |config: &&SupportedStreamConfigRange| config.min_sample_rate() <= rate && rate <= config.max_sample_rate();
+	configs.iter()
+		.filter(supports_rate)
+		.find(|config| config.sample_format() == SampleFormat::F32 && config.channels() == channels)
+		.or_else(|| configs.iter().filter(supports_rate).find(|config| config.channels() == channels))
+		.or_else(|| configs.iter().find(|config| config.sample_format() == SampleFormat::F32 && config.channels() == 2))
+		.or(configs.first())
+		.copied()
+}
+
+// Mixes everything that is playing into the output's channel layout
+pub struct Mixer {
+	sample_rate: u32,
+	channels: usize,
+	// In milliseconds
+	latency: u32,
+	bus: MasterBus,
+	// The last chunk in stereo and in the output's layout, kept so the audio thread does not allocate
+	stereo: Vec<f32>,
+	output: Vec<f32>,
+}
+
+impl Mixer {
+	pub fn new(sample_rate: u32, channels: usize, latency: u32) -> Self {
+		let frames = (ms_to_frames(latency, sample_rate) / 2).max(MIN_CHUNK_FRAMES);
+		Self {
+			sample_rate,
+			channels,
+			latency,
+			bus: MasterBus::new(sample_rate, frames),
+			stereo: Vec::with_capacity(frames * 2),
+			output: Vec::with_capacity(frames * channels),
+		}
+	}
+
+	// Frames the output may buffer ahead
+	pub fn latency_frames(&self) -> usize {
+		ms_to_frames(self.latency, self.sample_rate)
+	}
+
+	// Frames to mix at a time, so two chunks fit in the latency
+	pub fn chunk_frames(&self) -> usize {
+		(self.latency_frames() / 2).max(MIN_CHUNK_FRAMES)
+	}
+
+	pub fn sample_rate(&self) -> u32 {
+		self.sample_rate
+	}
+
+	pub fn channels(&self) -> usize {
+		self.channels
 	}
 
-	use PlayerType::*;
-	let mut buf = vec![0.0; data.len()];
-	match player_type {
-		File => get_file_data(&mut buf),
-		Wave => get_wave_data(&mut buf, sample_rate),
-	};
+	// Mixes the next `frames` frames into `output`. Returns false, leaving silence, if nothing is playing and no input is open.
+	pub fn render(&mut self, frames: usize) -> bool {
+		fit(&mut self.stereo, frames * 2);
+		fit(&mut self.output, frames * self.channels);
+		let playing = mix(&mut self.stereo, self.sample_rate, &mut self.bus);
+		if playing {
+			to_output(&self.stereo, &mut self.output, self.channels);
+		}
+		// A latency test keeps the output busy, even with silence
+		probe_chunk(frames) || playing
+	}
 
-	data.copy_from_slice(&buf.par_iter().map(|sample| T::from_sample(*sample)).collect::<Vec<_>>());
+	// The chunk from the last `render`, in the output's channel layout
+	pub fn output(&self) -> &[f32] {
+		&self.output
+	}
 }
 
-fn get_file_data(buf: &mut [f32]) -> bool {
-	let mut playing_files = acquire_playing_files();
-	if playing_files.len() > 0 {
-		// No parallel because it creates too much overhead
-		let volume = { acquire().config.volume as f32 / 100.0 };
-		for (_uuid, playable) in playing_files.iter_mut() {
-			let volume = linear_to_logarithmic(playable.volume * volume);
-			let max_read = buf.len().min(playable.data.len() - playable.position);
-			for ii in 0..max_read {
-				buf[ii] += playable.data[ii + playable.position] * volume;
-			}
-			playable.position += max_read;
+// Mixer state kept between chunks
+struct MasterBus {
+	limiter: Limiter,
+	effects: MasterEffects,
+	ducker: Ducker,
+	// Voices that get lowered while a ducker plays
+	ducked: Vec<f32>,
+	captured: Vec<f32>,
+	// Frames of silence still to run through once the voices end
+	flush: usize,
+}
+
+impl MasterBus {
+	fn new(sample_rate: u32, frames: usize) -> Self {
+		Self {
+			limiter: Limiter::new(sample_rate),
+			effects: MasterEffects::default(),
+			ducker: Ducker::new(sample_rate),
+			ducked: Vec::with_capacity(frames * 2),
+			captured: Vec::with_capacity(frames * 2),
+			flush: 0,
 		}
-		let eofs = playing_files.par_iter().filter_map(|(uuid, playable)| {
-			if playable.position == playable.data.len() {
-				let (lock, cvar) = &*playable.finished;
-				let _locked = lock.lock().expect("Failed to lock conditional variable");
-				cvar.notify_one();
-				Some(*uuid)
-			} else {
-				None
-			}
-		}).collect::<Vec<_>>();
-		if !eofs.is_empty() {
-			let mut app = acquire();
-			eofs.iter().for_each(|uuid| {
-				playing_files.remove(uuid);
-				app.playing_file.remove(uuid);
-			});
-			notify_redraw();
+	}
+}
+
+// Zeroes `buf` at `len` samples. Only allocates when the chunk grows.
+fn fit(buf: &mut Vec<f32>, len: usize) {
+	buf.clear();
+	buf.resize(len, 0.0);
+}
+
+// Mixes everything that is playing into `buf`, then runs the master bus.
+// Returns false if nothing is playing, the master bus has played out, and no input is open.
+fn mix(buf: &mut [f32], sample_rate: u32, bus: &mut MasterBus) -> bool {
+	let settings = MIX_SETTINGS.lock().unwrap().clone();
+	fit(&mut bus.ducked, buf.len());
+	// Both must run to advance their voices
+	let (files, ducking) = get_file_data(buf, &mut bus.ducked, settings.volume, settings.normalize_target);
+	let waves = get_wave_data(if settings.duck_waves { &mut bus.ducked } else { buf }, settings.volume, sample_rate);
+	let voices = files || waves;
+	// Silence plays out the master effects' tail and what the limiter still holds once the voices end
+	let flushing = !voices && bus.flush > 0;
+	if voices {
+		bus.ducker.process(&mut bus.ducked, ducking, settings.duck_amount, settings.duck_attack, settings.duck_release);
+		for (sample, ducked) in buf.iter_mut().zip(bus.ducked.iter()) {
+			*sample += ducked;
 		}
-		drop(playing_files);
-		return true;
 	}
-	false
+	if voices || flushing {
+		bus.effects.process(buf, &settings.master_effects, sample_rate);
+		bus.limiter.process(buf, settings.limiter, settings.limiter_ceiling);
+		feed_monitor(buf, linear_to_logarithmic(settings.monitor_volume), sample_rate);
+		bus.flush = if voices { bus.effects.tail() + bus.limiter.lookahead() } else { bus.flush.saturating_sub(buf.len() / 2) };
+	} else {
+		bus.limiter.reset();
+		bus.effects.reset();
+		bus.ducker.reset();
+	}
+	let input = get_input_data(buf, &mut bus.captured, settings.mic_gain, settings.mic_muted);
+	voices || flushing || input
+}
+
+// The mic joins after the limiter, so it is not delayed by the look-ahead and the monitor does not play it back
+fn get_input_data(buf: &mut [f32], captured: &mut Vec<f32>, gain: f32, muted: bool) -> bool {
+	let mut input = acquire_input();
+	let Some(source) = input.as_mut() else { return false };
+	fit(captured, buf.len());
+	// Keep reading while muted so the mic does not lag once unmuted
+	source.read_into(captured);
+	if !muted {
+		let gain = linear_to_logarithmic(gain);
+		for (sample, captured) in buf.iter_mut().zip(captured.iter()) {
+			*sample += captured * gain;
+		}
+	}
+	true
 }
 
-fn get_wave_data(buf: &mut [f32], sample_rate: u32) -> bool {
+// Duckers play into `buf` and every other voice into `ducked`.
+// Returns whether anything played, and whether a ducker is playing.
+fn get_file_data(buf: &mut [f32], ducked: &mut [f32], volume: f32, target: Option<f32>) -> (bool, bool) {
+	let mut playing_files = acquire_playing_files();
+	if playing_files.is_empty() {
+		return (false, false);
+	}
+	// No parallel because it creates too much overhead
+	for playable in playing_files.values_mut() {
+		let gain = playable.gain(volume, target);
+		playable.mix_into(if playable.ducker { &mut *buf } else { &mut *ducked }, gain);
+	}
+	let ducking = playing_files.values().any(|playable| playable.ducker && !playable.paused);
+	// The threads that started the voices clear them from the app
+	playing_files.retain(|_uuid, playable| {
+		if playable.is_finished() {
+			playable.notify_finished();
+			return false;
+		}
+		true
+	});
+	(true, ducking)
+}
+
+fn get_wave_data(buf: &mut [f32], volume: f32, sample_rate: u32) -> bool {
 	let mut playing_waves = acquire_playing_waves();
 	if playing_waves.len() > 0 {
-		let volume = { acquire().config.volume as f32 / 100.0 };
 		// No parallel because it creates too much overhead
 		for (_uuid, playable) in playing_waves.iter_mut() {
-			let len = playable.len() as f32;
-			let mut playable_bytes = vec![0_f32; buf.len()];
-			for wave in playable {
-				for ii in 0..buf.len() / 2 {
-					let sample = match wave.wave_type {
-						WaveType::Sine => (PI * 2.0 * wave.phase).sin(),
-						WaveType::Square => if wave.phase > 0.5 { 1.0 } else { -1.0 },
-						WaveType::Triangle => {
-							let portion = wave.phase;
-							if portion > 0.5 {
-								-1.0 + (portion - 0.5) * 4.0
-							} else {
-								1.0 - portion * 4.0
-							}
-						},
-						WaveType::Saw => -1.0 + wave.phase * 2.0,
-					} * wave.amplitude * linear_to_logarithmic(wave.volume * volume);
-					playable_bytes[ii * 2] += sample;
-					playable_bytes[ii * 2 + 1] += sample;
-					wave.phase = wave.phase + (1.0 / sample_rate as f32) / wave.period;
-					if wave.phase >= 1.0 {
-						wave.phase -= 1.0;
-					}
-				}
-			}
-			for ii in 0..playable_bytes.len() {
-				buf[ii] += playable_bytes[ii] / len;
+			if playable.fade.is_stopped() {
+				continue;
 			}
+			playable.mix_into(buf, volume, sample_rate);
 		}
 		return true;
 	}
 	false
 }
 
-fn linear_to_logarithmic(volume: f32) -> f32 {
+pub fn linear_to_logarithmic(volume: f32) -> f32 {
 	if volume <= 0.0 {
 		0.0
 	} else {
@@ -264,4 +335,145 @@ pub fn list_audio_devices() -> Result<(), Box<dyn std::error::Error>> {
 		}
 	}
 	Ok(())
-}
\ No newline at end of file
+}
+#[cfg(test)]
+pub(crate) mod tests {
+	use std::{fs, sync::MutexGuard};
+
+	use hound::{WavSpec, WavWriter};
+
+	use uuid::Uuid;
+
+	use super::*;
+	use crate::{constant::APP_NAME, util::{file::{PlayOptions, PlayableFile, Resampling}, input::WavInput}};
+
+	// Tests that mix share the playing voices, the input and the settings, so they take turns
+	pub(crate) fn lock_mixer() -> MutexGuard<'static, ()> {
+		static LOCK: Mutex<()> = Mutex::new(());
+		LOCK.lock().unwrap_or_else(|err| err.into_inner())
+	}
+
+	// Path in the temp directory, unique to this run
+	pub(crate) fn temp_path(name: &str) -> String {
+		std::env::temp_dir().join(format!("{}-test-{}-{}.wav", APP_NAME, std::process::id(), name)).to_string_lossy().to_string()
+	}
+
+	// Writes interleaved stereo at 48 kHz, returning the path
+	pub(crate) fn write_wav(name: &str, samples: &[f32]) -> String {
+		let path = temp_path(name);
+		let spec = WavSpec {
+			channels: 2,
+			sample_rate: 48000,
+			bits_per_sample: 32,
+			sample_format: hound::SampleFormat::Float,
+		};
+		let mut writer = WavWriter::create(&path, spec).unwrap();
+		for sample in samples {
+			writer.write_sample(*sample).unwrap();
+		}
+		writer.finalize().unwrap();
+		path
+	}
+
+	// The default settings with `change` made to them
+	pub(crate) fn set_settings(change: impl FnOnce(&mut SoundboardConfig)) {
+		let mut config = SoundboardConfig::default();
+		change(&mut config);
+		set_mix_settings(&config);
+	}
+
+	// Lets the next output run after a test reset it
+	pub(crate) fn clear_output_reset() {
+		OUTPUT_RESET.store(false, Ordering::Relaxed);
+	}
+
+	fn assert_scaled(output: &[f32], input: &[f32], gain: f32) {
+		assert_eq!(output.len(), input.len());
+		for (index, (output, input)) in output.iter().zip(input).enumerate() {
+			assert!((output - input * gain).abs() < 1e-6, "sample {} is {}, expected {}", index, output, input * gain);
+		}
+	}
+
+	// Renders chunks until the mixer goes idle, returning everything it played
+	fn render_until_idle(mixer: &mut Mixer) -> Vec<f32> {
+		let frames = mixer.chunk_frames();
+		let mut played = vec![];
+		while mixer.render(frames) {
+			played.extend_from_slice(mixer.output());
+			assert!(played.len() < 48000 * 2 * 10, "the mixer never went idle");
+		}
+		played
+	}
+
+	fn play(path: &str) {
+		let voice = PlayableFile::load(&path.to_string(), PlayOptions::default(), 48000, Resampling::Low, false).unwrap();
+		acquire_playing_files().insert(Uuid::new_v4(), voice);
+	}
+
+	#[test]
+	fn limiter_plays_out_its_look_ahead() {
+		let _lock = lock_mixer();
+		set_settings(|_| ());
+		let mut mixer = Mixer::new(48000, 2, 20);
+		// Ends with a chunk, so the end is still in the look-ahead once the voice is gone
+		let samples = vec![0.5; mixer.chunk_frames() * 2 * 2];
+		let path = write_wav("look-ahead", &samples);
+		play(&path);
+		let played = render_until_idle(&mut mixer);
+		fs::remove_file(path).ok();
+		// Delayed by the look-ahead, with nothing cut off at the end
+		let delay = Limiter::new(48000).lookahead() * 2;
+		assert!(played[..delay].iter().all(|sample| *sample == 0.0));
+		assert_eq!(played[delay..delay + samples.len()], samples);
+	}
+
+	#[test]
+	fn master_effects_ring_out() {
+		let _lock = lock_mixer();
+		set_settings(|config| {
+			config.limiter = LimiterMode::Off;
+			config.master_effects = vec![Effect::Echo { delay: 100, feedback: 0, mix: 100 }];
+		});
+		let mut mixer = Mixer::new(48000, 2, 20);
+		let samples = vec![0.5; mixer.chunk_frames() * 2];
+		let path = write_wav("master-tail", &samples);
+		play(&path);
+		let played = render_until_idle(&mut mixer);
+		fs::remove_file(path).ok();
+		set_settings(|_| ());
+		// The echo comes back 100 ms after the voice has ended
+		let delay = 4800 * 2;
+		assert!(played.len() >= delay + samples.len());
+		assert_eq!(played[delay..delay + samples.len()], samples);
+	}
+
+	#[test]
+	fn mic_follows_gain_and_mute() {
+		let _lock = lock_mixer();
+		// A ramp, so every chunk differs
+		let samples = (0..9600).map(|index| (index % 200) as f32 / 200.0 - 0.5).collect::<Vec<_>>();
+		let path = write_wav("mic", &samples);
+		*acquire_input() = Some(Box::new(WavInput::new(&path, 48000).unwrap()));
+		let mut mixer = Mixer::new(48000, 2, 20);
+		let frames = mixer.chunk_frames();
+		let chunk = |index: usize| &samples[index * frames * 2..(index + 1) * frames * 2];
+
+		set_settings(|_| ());
+		assert!(mixer.render(frames));
+		assert_scaled(mixer.output(), chunk(0), 1.0);
+		set_settings(|config| config.mic_gain = 50);
+		assert!(mixer.render(frames));
+		assert_scaled(mixer.output(), chunk(1), linear_to_logarithmic(0.5));
+		// Muted, it is still read so it does not lag once unmuted
+		set_settings(|config| config.mic_muted = true);
+		assert!(mixer.render(frames));
+		assert!(mixer.output().iter().all(|sample| *sample == 0.0));
+		set_settings(|_| ());
+		assert!(mixer.render(frames));
+		assert_scaled(mixer.output(), chunk(3), 1.0);
+
+		*acquire_input() = None;
+		assert!(!mixer.render(frames));
+		fs::remove_file(path).ok();
+	}
+}
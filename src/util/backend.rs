@@ -0,0 +1,372 @@
+use std::{fs::File, io::{BufWriter, Write}, str::FromStr, sync::{Arc, Condvar, Mutex}, thread, time::{Duration, Instant, SystemTime}};
+
+use cmd_exists::cmd_exists;
+use cpal::{BufferSize, Device, DeviceId, FromSample, Sample, SampleFormat, StreamConfig, SupportedBufferSize, traits::{DeviceTrait, HostTrait, StreamTrait}};
+use hound::{WavSpec, WavWriter};
+
+use crate::{component::block::log, constant::APP_NAME, state::acquire, util::{audio::{Mixer, Pacat, output_config, output_stopped, spawn_pacat}, fade::ms_to_frames, channels::OutputChannels, record::{record, record_silence}}};
+
+// How often the WAV header is updated, so the file stays readable if cls is killed
+const WAV_FLUSH_SECS: u64 = 1;
+
+// Which output the mixer plays into, picked with --backend
+#[derive(Clone, Default, PartialEq)]
+pub enum Backend {
+	// pacat if it is installed and allowed, cpal otherwise
+	#[default]
+	Auto,
+	Pacat,
+	Cpal,
+	// Mixes in real time and throws the result away
+	Null,
+	// Writes the mix to a WAV file, in real time unless `fast`
+	WavFile { path: String, fast: bool },
+}
+
+impl Backend {
+	pub fn name(&self) -> &'static str {
+		match self {
+			Backend::Auto => "auto",
+			Backend::Pacat => "pacat",
+			Backend::Cpal => "cpal",
+			Backend::Null => "null",
+			Backend::WavFile { .. } => "wav-file",
+		}
+	}
+
+	// Whether anything plays into the cls sink, so PulseAudio needs to be set up
+	pub fn uses_sink(&self) -> bool {
+		!matches!(self, Backend::Null | Backend::WavFile { .. })
+	}
+
+	pub fn create(&self) -> Box<dyn OutputBackend> {
+		match self {
+			Backend::Auto => if { acquire().no_pacat } || cmd_exists("pacat").is_err() {
+				Box::new(CpalOutput::default())
+			} else {
+				Box::new(PacatOutput::default())
+			},
+			Backend::Pacat => Box::new(PacatOutput::default()),
+			Backend::Cpal => Box::new(CpalOutput::default()),
+			Backend::Null => Box::new(NullOutput),
+			Backend::WavFile { path, fast } => Box::new(WavFileOutput::new(path.clone(), *fast)),
+		}
+	}
+}
+
+// Somewhere for the mixer to play into
+pub trait OutputBackend {
+	// Gets the output ready for the sample rate, channels and latency (in milliseconds) from the config.
+	// Returns the sample rate and channel count it actually runs at.
+	fn open(&mut self, sample_rate: u32, channels: OutputChannels, latency: u32) -> Result<(u32, u16), String>;
+	// Plays the mix until the program exits or the output is reset
+	fn run(&mut self, mixer: Mixer) -> Result<(), String>;
+}
+
+// Sleeps so that frames go out no faster than real time, running at most `lead` frames ahead
+struct Pacer {
+	start: Instant,
+	frames: u64,
+	lead: u64,
+	sample_rate: u32,
+}
+
+impl Pacer {
+	fn new(sample_rate: u32, lead: usize) -> Self {
+		Self {
+			start: Instant::now(),
+			frames: 0,
+			lead: lead as u64,
+			sample_rate,
+		}
+	}
+
+	fn wait(&mut self, frames: usize) {
+		self.frames += frames as u64;
+		let due = Duration::from_secs_f64(self.frames.saturating_sub(self.lead) as f64 / self.sample_rate as f64);
+		if let Some(ahead) = due.checked_sub(self.start.elapsed()) {
+			thread::sleep(ahead);
+		}
+	}
+
+	// Starts counting from now, for after running ahead
+	fn reset(&mut self) {
+		self.start = Instant::now();
+		self.frames = 0;
+	}
+}
+
+// Plays into the cls sink. pacat is spawned when something plays, and killed after being idle for a while.
+#[derive(Default)]
+pub struct PacatOutput {
+	channels: OutputChannels,
+	latency: u32,
+}
+
+impl OutputBackend for PacatOutput {
+	fn open(&mut self, sample_rate: u32, channels: OutputChannels, latency: u32) -> Result<(u32, u16), String> {
+		cmd_exists("pacat").map_err(|_| "pacat is not installed")?;
+		self.channels = channels;
+		self.latency = latency;
+		Ok((sample_rate, channels.count()))
+	}
+
+	fn run(&mut self, mut mixer: Mixer) -> Result<(), String> {
+		let sample_rate = mixer.sample_rate();
+		let chunk = mixer.chunk_frames();
+		// pacat takes whatever the pipe holds, so stay within the latency of real time
+		let mut pacer = Pacer::new(sample_rate, mixer.latency_frames());
+		let mut pacat: Option<Pacat> = None;
+		let result = loop {
+			if output_stopped() {
+				break Ok(());
+			}
+			if mixer.render(chunk) {
+				if pacat.is_none() {
+					match spawn_pacat(APP_NAME, sample_rate, self.channels, self.latency) {
+						Ok(spawned) => pacat = Some(spawned),
+						Err(err) => break Err(format!("Failed to spawn pacat: {}", err)),
+					}
+				}
+				let writer = pacat.as_mut().unwrap();
+				// Blocks while pacat is behind
+				if let Err(err) = writer.writer.write_all(bytemuck::cast_slice(mixer.output())).and_then(|_| writer.writer.flush()) {
+					break Err(format!("Failed to write to pacat: {}", err));
+				}
+				writer.last_used = SystemTime::now();
+				record(mixer.output());
+			} else {
+				// Nothing is written while idle, so keep recordings in time
+				record_silence();
+				if let Some(idle) = pacat.as_mut() && SystemTime::now().duration_since(idle.last_used).is_ok_and(|duration| duration.as_secs() > 5) {
+					idle.child.kill().ok();
+					idle.child.wait().ok();
+					pacat = None;
+				}
+			}
+			pacer.wait(chunk);
+		};
+		if let Some(mut pacat) = pacat {
+			pacat.child.kill().ok();
+			pacat.child.wait().ok();
+		}
+		result
+	}
+}
+
+// Plays on a cpal device, which pulls the mix from its own thread
+#[derive(Default)]
+pub struct CpalOutput {
+	device: Option<Device>,
+	config: Option<(StreamConfig, SampleFormat)>,
+}
+
+impl OutputBackend for CpalOutput {
+	fn open(&mut self, sample_rate: u32, channels: OutputChannels, latency: u32) -> Result<(u32, u16), String> {
+		let target_device = { acquire().cpal_device.clone() };
+		let device = if target_device.is_empty() {
+			cpal::default_host().default_output_device().ok_or("No default output device")?
+		} else {
+			let device_id = DeviceId::from_str(&target_device).map_err(|err| err.to_string())?;
+			let host = cpal::host_from_id(device_id.0).map_err(|err| err.to_string())?;
+			host.device_by_id(&device_id).ok_or(format!("Device {} not found", target_device))?
+		};
+		let config = output_config(&device, sample_rate, channels.count()).ok_or("No output config")?;
+		let sample_rate = sample_rate.clamp(config.min_sample_rate(), config.max_sample_rate());
+		let config = config.with_sample_rate(sample_rate);
+		let channels = config.channels();
+		let mut stream_config = config.config();
+		// Two buffers fit in the latency, like the mixer chunks
+		let frames = (ms_to_frames(latency, sample_rate) / 2) as u32;
+		if let SupportedBufferSize::Range { min, max } = config.buffer_size() {
+			stream_config.buffer_size = BufferSize::Fixed(frames.clamp(*min, *max));
+		}
+		self.config = Some((stream_config, config.sample_format()));
+		self.device = Some(device);
+		Ok((sample_rate, channels))
+	}
+
+	fn run(&mut self, mut mixer: Mixer) -> Result<(), String> {
+		let (Some(device), Some((config, sample_format))) = (self.device.take(), self.config.take()) else {
+			return Err("Output is not open".to_string());
+		};
+		let err_callback = |err| {
+			log::error(format!("{:?}", err).as_str());
+		};
+		let pair = Arc::new((Mutex::new(false), Condvar::new()));
+		let pair2 = pair.clone();
+		let stream = match sample_format {
+			SampleFormat::F32 => device.build_output_stream(&config, move |data: &mut [f32], _| cpal_data_callback(data, &pair2, &mut mixer), err_callback, None),
+			SampleFormat::I16 => device.build_output_stream(&config, move |data: &mut [i16], _| cpal_data_callback(data, &pair2, &mut mixer), err_callback, None),
+			SampleFormat::U16 => device.build_output_stream(&config, move |data: &mut [u16], _| cpal_data_callback(data, &pair2, &mut mixer), err_callback, None),
+			format => return Err(format!("Unsupported sample format {}", format))
+		}.map_err(|err| err.to_string())?;
+		stream.play().map_err(|err| err.to_string())?;
+		// cpal plays on its own thread
+		let (lock, cvar) = &*pair;
+		let mut shared = lock.lock().expect("Failed to get shared mutex");
+		// Wait for exit or output reset. Also checked here, in case the device stops calling back.
+		while !(*shared) && !output_stopped() {
+			shared = cvar.wait_timeout(shared, Duration::from_millis(100)).expect("Failed to get shared mutex").0;
+		}
+		Ok(())
+	}
+}
+
+fn cpal_data_callback<T: Sample + FromSample<f32> + Send>(data: &mut [T], pair: &Arc<(Mutex<bool>, Condvar)>, mixer: &mut Mixer) {
+	if output_stopped() {
+		let (lock, cvar) = &**pair;
+		let mut shared = lock.lock().expect("Failed to get shared mutex");
+		*shared = true;
+		cvar.notify_one();
+		return;
+	}
+
+	// Silent while idle, so the device keeps playing
+	mixer.render(data.len() / mixer.channels());
+	record(mixer.output());
+	for (out, sample) in data.iter_mut().zip(mixer.output()) {
+		*out = T::from_sample(*sample);
+	}
+}
+
+// Mixes in real time without playing anything, for machines without any audio output
+#[derive(Default)]
+pub struct NullOutput;
+
+impl OutputBackend for NullOutput {
+	fn open(&mut self, sample_rate: u32, channels: OutputChannels, _latency: u32) -> Result<(u32, u16), String> {
+		Ok((sample_rate, channels.count()))
+	}
+
+	fn run(&mut self, mut mixer: Mixer) -> Result<(), String> {
+		let chunk = mixer.chunk_frames();
+		let mut pacer = Pacer::new(mixer.sample_rate(), 0);
+		while !output_stopped() {
+			if mixer.render(chunk) {
+				record(mixer.output());
+			} else {
+				record_silence();
+			}
+			pacer.wait(chunk);
+		}
+		Ok(())
+	}
+}
+
+// Writes the whole mix, silence included, to a WAV file.
+// In fast mode, anything playing is mixed as fast as possible and only silence is paced.
+pub struct WavFileOutput {
+	path: String,
+	fast: bool,
+	writer: Option<WavWriter<BufWriter<File>>>,
+}
+
+impl WavFileOutput {
+	pub fn new(path: String, fast: bool) -> Self {
+		Self {
+			path,
+			fast,
+			writer: None,
+		}
+	}
+}
+
+impl OutputBackend for WavFileOutput {
+	fn open(&mut self, sample_rate: u32, channels: OutputChannels, _latency: u32) -> Result<(u32, u16), String> {
+		let spec = WavSpec {
+			channels: channels.count(),
+			sample_rate,
+			bits_per_sample: 32,
+			sample_format: hound::SampleFormat::Float,
+		};
+		self.writer = Some(WavWriter::create(&self.path, spec).map_err(|err| err.to_string())?);
+		log::info(format!("Writing output to {}", self.path).as_str());
+		Ok((sample_rate, channels.count()))
+	}
+
+	fn run(&mut self, mut mixer: Mixer) -> Result<(), String> {
+		let Some(mut writer) = self.writer.take() else { return Err("Output is not open".to_string()) };
+		let sample_rate = mixer.sample_rate();
+		let chunk = mixer.chunk_frames();
+		let mut pacer = Pacer::new(sample_rate, 0);
+		let mut unflushed = 0;
+		while !output_stopped() {
+			let playing = mixer.render(chunk);
+			for sample in mixer.output() {
+				writer.write_sample(*sample).map_err(|err| err.to_string())?;
+			}
+			record(mixer.output());
+			unflushed += chunk;
+			if unflushed as u64 >= WAV_FLUSH_SECS * sample_rate as u64 {
+				writer.flush().map_err(|err| err.to_string())?;
+				unflushed = 0;
+			}
+			if self.fast && playing {
+				pacer.reset();
+			} else {
+				pacer.wait(chunk);
+			}
+		}
+		writer.finalize().map_err(|err| err.to_string())
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+
+	use uuid::Uuid;
+
+	use super::*;
+	use crate::util::{audio::{reset_output, tests::{clear_output_reset, lock_mixer, set_settings, temp_path, write_wav}}, file::{PlayOptions, PlayableFile, Resampling, acquire_playing_files}, limiter::LimiterMode};
+
+	#[test]
+	fn fast_wav_file_output_writes_the_mix() {
+		let _lock = lock_mixer();
+		// Without the limiter, samples come out as they were mixed
+		set_settings(|config| config.limiter = LimiterMode::Off);
+		let samples = (0..12000).flat_map(|frame| [(frame % 100) as f32 / 200.0, -((frame % 60) as f32) / 120.0]).collect::<Vec<_>>();
+		let input = write_wav("voice", &samples);
+		let voice = PlayableFile::load(&input, PlayOptions::default(), 48000, Resampling::Low, false).unwrap();
+		acquire_playing_files().insert(Uuid::new_v4(), voice);
+
+		let path = temp_path("wav-file-output");
+		let mut output = WavFileOutput::new(path.clone(), true);
+		let (sample_rate, channels) = output.open(48000, OutputChannels::Stereo, 20).unwrap();
+		let mixer = Mixer::new(sample_rate, channels as usize, 20);
+		let chunk = mixer.chunk_frames();
+		// Stops the output once the voice has played, when it goes back to real time
+		let stopper = thread::spawn(|| {
+			while !acquire_playing_files().is_empty() {
+				thread::sleep(Duration::from_millis(1));
+			}
+			reset_output();
+		});
+		let started = Instant::now();
+		output.run(mixer).unwrap();
+		let elapsed = started.elapsed();
+		stopper.join().unwrap();
+		clear_output_reset();
+		set_settings(|_| ());
+
+		let mut reader = hound::WavReader::open(&path).unwrap();
+		assert_eq!((reader.spec().sample_rate, reader.spec().channels), (48000, 2));
+		let written = reader.samples::<f32>().map(Result::unwrap).collect::<Vec<_>>();
+		fs::remove_file(&path).ok();
+		fs::remove_file(&input).ok();
+
+		// Whole chunks, with the voice and then at most a few idle ones
+		let frames = written.len() / 2;
+		assert_eq!(frames % chunk, 0);
+		assert!(frames >= 12000 && frames < 12000 + chunk * 20, "wrote {} frames", frames);
+		// 0.25 s of audio, mixed faster than it plays
+		assert!(elapsed.as_secs_f32() < 0.25, "took {:?}", elapsed);
+		// Centered at full volume, so the samples come out unchanged
+		for (index, (written, sample)) in written.iter().zip(samples.iter()).enumerate() {
+			assert!((written - sample).abs() < 1e-6, "sample {} is {}, expected {}", index, written, sample);
+		}
+		assert!(written[samples.len()..].iter().all(|sample| *sample == 0.0));
+	}
+}
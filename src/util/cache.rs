@@ -0,0 +1,161 @@
+use std::{collections::HashMap, ops::Deref, sync::Arc};
+
+use memmap2::Mmap;
+
+// Decoded interleaved stereo, either in memory or mapped from the disk cache
+pub enum Pcm {
+	Memory(Vec<f32>),
+	// Samples start at the offset, after the entry's header
+	Mapped(Mmap, usize),
+}
+
+impl Deref for Pcm {
+	type Target = [f32];
+
+	fn deref(&self) -> &[f32] {
+		match self {
+			Pcm::Memory(data) => data,
+			// Maps are page aligned, the header is padded, and the length is checked when loaded
+			Pcm::Mapped(map, offset) => bytemuck::cast_slice(&map[*offset..]),
+		}
+	}
+}
+
+struct CacheEntry {
+	data: Arc<Pcm>,
+	last_used: u64,
+}
+
+// Decoded audio kept in memory.
+// Least recently used files are evicted once the total size goes over the budget.
+pub struct AudioCache {
+	entries: HashMap<String, CacheEntry>,
+	bytes: usize,
+	budget: usize,
+	// Increases on every access, so the smallest `last_used` is the least recently used
+	clock: u64,
+}
+
+impl AudioCache {
+	pub fn new(budget: usize) -> Self {
+		Self {
+			entries: HashMap::new(),
+			bytes: 0,
+			budget,
+			clock: 0,
+		}
+	}
+
+	pub fn get(&mut self, path: &str) -> Option<Arc<Pcm>> {
+		self.clock += 1;
+		let entry = self.entries.get_mut(path)?;
+		entry.last_used = self.clock;
+		Some(entry.data.clone())
+	}
+
+	pub fn contains(&self, path: &str) -> bool {
+		self.entries.contains_key(path)
+	}
+
+	// Data larger than the whole budget is not kept
+	pub fn insert(&mut self, path: String, data: Arc<Pcm>) {
+		let size = data_size(&data);
+		if size > self.budget {
+			return;
+		}
+		self.clock += 1;
+		if let Some(old) = self.entries.insert(path, CacheEntry { data, last_used: self.clock }) {
+			self.bytes -= data_size(&old.data);
+		}
+		self.bytes += size;
+		self.evict();
+	}
+
+	pub fn set_budget(&mut self, budget: usize) {
+		self.budget = budget;
+		self.evict();
+	}
+
+	pub fn retain(&mut self, mut keep: impl FnMut(&str) -> bool) {
+		let mut bytes = self.bytes;
+		self.entries.retain(|path, entry| {
+			let retained = keep(path);
+			if !retained {
+				bytes -= data_size(&entry.data);
+			}
+			retained
+		});
+		self.bytes = bytes;
+	}
+
+	pub fn clear(&mut self) {
+		self.entries.clear();
+		self.bytes = 0;
+	}
+
+	fn evict(&mut self) {
+		while self.bytes > self.budget {
+			let Some(path) = self.entries.iter().min_by_key(|(_, entry)| entry.last_used).map(|(path, _)| path.clone()) else { break };
+			if let Some(entry) = self.entries.remove(&path) {
+				self.bytes -= data_size(&entry.data);
+			}
+		}
+	}
+}
+
+fn data_size(data: &[f32]) -> usize {
+	size_of_val(data)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// `samples` floats of 4 bytes each
+	fn pcm(samples: usize) -> Arc<Pcm> {
+		Arc::new(Pcm::Memory(vec![0.0; samples]))
+	}
+
+	#[test]
+	fn evicts_least_recently_used_first() {
+		let mut cache = AudioCache::new(400);
+		cache.insert("a".to_string(), pcm(40));
+		cache.insert("b".to_string(), pcm(40));
+		cache.insert("c".to_string(), pcm(20));
+		assert_eq!(cache.bytes, 400);
+		// Using "a" leaves "b" as the least recently used
+		cache.get("a");
+		cache.insert("d".to_string(), pcm(20));
+		assert!(cache.contains("a") && !cache.contains("b") && cache.contains("c") && cache.contains("d"));
+		// Then "c", then "a", which was last used before "d" was inserted, until there is room
+		cache.insert("e".to_string(), pcm(50));
+		assert!(!cache.contains("a") && !cache.contains("c") && cache.contains("d") && cache.contains("e"));
+		assert_eq!(cache.bytes, 280);
+	}
+
+	#[test]
+	fn stays_within_budget() {
+		let mut cache = AudioCache::new(1000);
+		for index in 0..50 {
+			cache.insert(index.to_string(), pcm(index * 7 % 100));
+			assert!(cache.bytes <= 1000);
+			assert_eq!(cache.bytes, cache.entries.values().map(|entry| data_size(&entry.data)).sum::<usize>());
+		}
+		// Data larger than the whole budget is left out, keeping what is there
+		let kept = cache.entries.len();
+		cache.insert("huge".to_string(), pcm(300));
+		assert!(!cache.contains("huge") && cache.entries.len() == kept);
+		cache.set_budget(0);
+		assert_eq!((cache.bytes, cache.entries.len()), (0, 0));
+	}
+
+	#[test]
+	fn replacing_an_entry_counts_it_once() {
+		let mut cache = AudioCache::new(1000);
+		cache.insert("a".to_string(), pcm(100));
+		cache.insert("a".to_string(), pcm(50));
+		assert_eq!(cache.bytes, 200);
+		cache.retain(|path| path != "a");
+		assert_eq!(cache.bytes, 0);
+	}
+}
@@ -0,0 +1,195 @@
+use std::f32::consts::FRAC_1_SQRT_2;
+
+use serde::{Deserialize, Serialize};
+use symphonium::symphonia::core::audio::Channels;
+
+// Channel layout of the output. Mixing is always done in stereo.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputChannels {
+	Mono,
+	#[default]
+	Stereo,
+	Surround51,
+	Surround71
+}
+
+impl OutputChannels {
+	pub fn next(&self) -> Self {
+		use OutputChannels::*;
+		match self {
+			Mono => Stereo,
+			Stereo => Surround51,
+			Surround51 => Surround71,
+			Surround71 => Mono,
+		}
+	}
+
+	pub fn count(&self) -> u16 {
+		use OutputChannels::*;
+		match self {
+			Mono => 1,
+			Stereo => 2,
+			Surround51 => 6,
+			Surround71 => 8,
+		}
+	}
+
+	pub fn label(&self) -> &'static str {
+		use OutputChannels::*;
+		match self {
+			Mono => "Mono",
+			Stereo => "Stereo",
+			Surround51 => "5.1",
+			Surround71 => "7.1",
+		}
+	}
+
+	// Channel map for pacat and the null sink, in the order `to_output` writes
+	pub fn channel_map(&self) -> &'static str {
+		use OutputChannels::*;
+		match self {
+			Mono => "mono",
+			Stereo => "front-left,front-right",
+			Surround51 => "front-left,front-right,front-center,lfe,rear-left,rear-right",
+			Surround71 => "front-left,front-right,front-center,lfe,rear-left,rear-right,side-left,side-right",
+		}
+	}
+}
+
+// Spreads the stereo mix over `channels` output channels, writing a frame of `output` per stereo frame.
+// Front left and right come first. Other channels are left silent.
+pub fn to_output(stereo: &[f32], output: &mut [f32], channels: usize) {
+	for (frame, out) in stereo.chunks_exact(2).zip(output.chunks_exact_mut(channels)) {
+		match out {
+			[mono] => *mono = (frame[0] + frame[1]) * 0.5,
+			[left, right, rest @ ..] => {
+				*left = frame[0];
+				*right = frame[1];
+				rest.fill(0.0);
+			},
+			[] => (),
+		}
+	}
+}
+
+// Mixes a source with any channel layout down to stereo.
+// Levels follow the usual ITU coefficients, scaled so a full-scale source does not clip.
+pub struct Downmix {
+	coefficients: Vec<[f32; 2]>,
+}
+
+impl Downmix {
+	pub fn new(channels: Option<Channels>, count: usize) -> Self {
+		// Some decoders report mono as front left, which should still reach both sides
+		let positions = match channels {
+			Some(channels) if count > 1 && channels.count() == count => channels.iter().collect(),
+			_ => default_layout(count),
+		};
+		let mut coefficients = positions.into_iter().map(coefficient).collect::<Vec<_>>();
+		let left = coefficients.iter().map(|coefficient| coefficient[0]).sum::<f32>();
+		let right = coefficients.iter().map(|coefficient| coefficient[1]).sum::<f32>();
+		let scale = left.max(right);
+		if scale > 0.0 {
+			for coefficient in coefficients.iter_mut() {
+				coefficient[0] /= scale;
+				coefficient[1] /= scale;
+			}
+		}
+		Self { coefficients }
+	}
+
+	// Mixes planar source channels into left and right
+	pub fn apply(&self, planar: &[&[f32]], left: &mut Vec<f32>, right: &mut Vec<f32>) {
+		let frames = planar.first().map_or(0, |channel| channel.len());
+		left.clear();
+		right.clear();
+		left.resize(frames, 0.0);
+		right.resize(frames, 0.0);
+		for (channel, [to_left, to_right]) in planar.iter().zip(self.coefficients.iter()) {
+			for (ii, sample) in channel.iter().enumerate() {
+				left[ii] += sample * to_left;
+				right[ii] += sample * to_right;
+			}
+		}
+	}
+
+	// Interleaved stereo from planar source channels
+	pub fn interleave(&self, planar: &[&[f32]]) -> Vec<f32> {
+		let (mut left, mut right) = (vec![], vec![]);
+		self.apply(planar, &mut left, &mut right);
+		left.into_iter().zip(right).flat_map(|(l, r)| [l, r]).collect()
+	}
+}
+
+// Usual channel orders when the source does not report its layout
+fn default_layout(count: usize) -> Vec<Channels> {
+	match count {
+		1 => vec![Channels::FRONT_CENTRE],
+		6 => vec![Channels::FRONT_LEFT, Channels::FRONT_RIGHT, Channels::FRONT_CENTRE, Channels::LFE1, Channels::REAR_LEFT, Channels::REAR_RIGHT],
+		8 => vec![Channels::FRONT_LEFT, Channels::FRONT_RIGHT, Channels::FRONT_CENTRE, Channels::LFE1, Channels::REAR_LEFT, Channels::REAR_RIGHT, Channels::SIDE_LEFT, Channels::SIDE_RIGHT],
+		// Anything else only keeps the first two channels
+		_ => (0..count).map(|ii| match ii {
+			0 => Channels::FRONT_LEFT,
+			1 => Channels::FRONT_RIGHT,
+			_ => Channels::empty(),
+		}).collect(),
+	}
+}
+
+fn coefficient(channel: Channels) -> [f32; 2] {
+	if channel == Channels::FRONT_LEFT {
+		[1.0, 0.0]
+	} else if channel == Channels::FRONT_RIGHT {
+		[0.0, 1.0]
+	} else if channel == Channels::FRONT_CENTRE || channel == Channels::REAR_CENTRE || channel == Channels::TOP_CENTRE || channel == Channels::TOP_FRONT_CENTRE || channel == Channels::TOP_REAR_CENTRE || channel == Channels::FRONT_CENTRE_HIGH {
+		[FRAC_1_SQRT_2, FRAC_1_SQRT_2]
+	} else if channel == Channels::LFE1 || channel == Channels::LFE2 || channel.is_empty() {
+		[0.0, 0.0]
+	} else if (Channels::REAR_LEFT | Channels::SIDE_LEFT | Channels::FRONT_LEFT_CENTRE | Channels::REAR_LEFT_CENTRE | Channels::FRONT_LEFT_WIDE | Channels::FRONT_LEFT_HIGH | Channels::TOP_FRONT_LEFT | Channels::TOP_REAR_LEFT).contains(channel) {
+		[FRAC_1_SQRT_2, 0.0]
+	} else {
+		[0.0, FRAC_1_SQRT_2]
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn coefficients_sum_to_unity() {
+		for count in [1, 2, 3, 6, 8] {
+			let downmix = Downmix::new(None, count);
+			let left = downmix.coefficients.iter().map(|coefficient| coefficient[0]).sum::<f32>();
+			let right = downmix.coefficients.iter().map(|coefficient| coefficient[1]).sum::<f32>();
+			assert!((left - 1.0).abs() < 1e-6 && (right - 1.0).abs() < 1e-6, "{} channels sum to ({}, {})", count, left, right);
+		}
+	}
+
+	#[test]
+	fn mono_reaches_both_sides() {
+		// Reported as front left by some decoders
+		let downmix = Downmix::new(Some(Channels::FRONT_LEFT), 1);
+		assert_eq!(downmix.interleave(&[&[0.5, -0.5]]), [0.5, 0.5, -0.5, -0.5]);
+	}
+
+	#[test]
+	fn full_scale_surround_does_not_clip() {
+		let channels = [[1.0_f32; 4]; 6];
+		let planar = channels.iter().map(|channel| channel.as_slice()).collect::<Vec<_>>();
+		let stereo = Downmix::new(None, 6).interleave(&planar);
+		assert!(stereo.iter().all(|sample| (sample - 1.0).abs() < 1e-6));
+	}
+
+	#[test]
+	fn output_layouts() {
+		let stereo = [0.5, 0.25, -1.0, 1.0];
+		let mut mono = [1.0; 2];
+		to_output(&stereo, &mut mono, 1);
+		assert_eq!(mono, [0.375, 0.0]);
+		let mut surround = [1.0; 12];
+		to_output(&stereo, &mut surround, 6);
+		assert_eq!(surround, [0.5, 0.25, 0.0, 0.0, 0.0, 0.0, -1.0, 1.0, 0.0, 0.0, 0.0, 0.0]);
+	}
+}
@@ -0,0 +1,63 @@
+use std::thread;
+
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use uuid::Uuid;
+
+use crate::{state::acquire, util::{file::{PlayableFile, acquire_playing_files}, wave::acquire_playing_waves}};
+
+// Short fade for voices cut off by another sound in their group, so they do not click
+const CHOKE_FADE_MS: u32 = 20;
+
+// The sound starting in a group, which never chokes itself.
+// Retriggering a file is left to its own retrigger policy.
+pub enum Choker<'a> {
+	File(&'a str),
+	// Dialogs by their internal id
+	Dialog(Uuid),
+	Wave(Uuid),
+}
+
+impl Choker<'_> {
+	fn keeps(&self, playable: &PlayableFile) -> bool {
+		match self {
+			Choker::File(path) => playable.dialog.is_none() && playable.path == *path,
+			Choker::Dialog(uuid) => playable.dialog == Some(*uuid),
+			Choker::Wave(_) => false,
+		}
+	}
+}
+
+// Fades out everything playing in `group` other than `choker`, and stops the waveforms and dialogs in it.
+// Must not be called with the app locked.
+pub fn choke_group(group: &str, choker: Option<Choker>, sample_rate: u32) {
+	for playable in acquire_playing_files().values_mut() {
+		if playable.group.as_deref() == Some(group) && !choker.as_ref().is_some_and(|choker| choker.keeps(playable)) && !playable.fade.is_stopping() {
+			playable.fade.stop(CHOKE_FADE_MS, sample_rate);
+		}
+	}
+	// A starting waveform is not playing yet
+	for playable in acquire_playing_waves().values_mut() {
+		if playable.group.as_deref() == Some(group) && !playable.fade.is_stopping() {
+			playable.fade.stop(CHOKE_FADE_MS, sample_rate);
+		}
+	}
+	let app = acquire();
+	app.waves.par_iter().filter(|wave| wave.group.as_deref() == Some(group) && !matches!(choker, Some(Choker::Wave(uuid)) if uuid == wave.uuid)).for_each(|wave| {
+		let mut playing = wave.playing.lock().expect("Failed to lock mutex");
+		playing.0 = false;
+		playing.1 = false;
+	});
+	app.dialogs.par_iter().filter(|dialog| dialog.group.as_deref() == Some(group) && !matches!(choker, Some(Choker::Dialog(uuid)) if uuid == dialog.uuid)).for_each(|dialog| {
+		let mut playing = dialog.playing.lock().expect("Failed to lock mutex");
+		playing.0 = false;
+		playing.1 = false;
+	});
+}
+
+pub fn stop_group(group: String) {
+	// Defer to avoid deadlock
+	thread::spawn(move || {
+		let sample_rate = { acquire().sample_rate };
+		choke_group(&group, None, sample_rate);
+	});
+}
@@ -5,7 +5,7 @@ use rand::Rng;
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 use uuid::Uuid;
 
-use crate::{config::DialogEntry, state::{acquire, notify_redraw}, util::{file::play_file, keyboard::keyboard_to_string}};
+use crate::{config::DialogEntry, state::{acquire, notify_redraw}, util::{effects::Effect, file::{PlayOptions, play_file}, keyboard::keyboard_to_string}};
 
 #[derive(Clone)]
 pub struct Dialog {
@@ -18,6 +18,12 @@ pub struct Dialog {
 	pub random: bool,
 	pub sequential: bool,
 	pub volume: u32,
+	pub fade_in: u32,
+	pub fade_out: u32,
+	pub effects: Vec<Effect>,
+	pub group: Option<String>,
+	pub pan: i32,
+	pub pan_random: u32,
 	pub play_lock: Arc<Mutex<()>>,
 	pub play_next: usize,
 	pub playing: Arc<Mutex<(bool, bool)>>
@@ -35,6 +41,12 @@ impl Default for Dialog {
 			random: true,
 			sequential: false,
 			volume: 100,
+			fade_in: 0,
+			fade_out: 0,
+			effects: vec![],
+			group: Option::None,
+			pan: 0,
+			pan_random: 0,
 			play_lock: Arc::new(Mutex::new(())),
 			play_next: 0,
 			playing: Arc::new(Mutex::new((false, false)))
@@ -52,19 +64,48 @@ impl Dialog {
 			delay: self.delay,
 			random: self.random,
 			sequential: self.sequential,
-			volume: 100,
+			volume: self.volume,
+			fade_in: self.fade_in,
+			fade_out: self.fade_out,
+			effects: self.effects.clone(),
+			group: self.group.clone(),
+			pan: self.pan,
+			pan_random: self.pan_random,
+		}
+	}
+
+	// Dialog settings, with the trim, effects, ducking, choke group and pan of the file itself
+	pub fn play_options(&self, path: &str, rng: &mut impl Rng) -> PlayOptions {
+		let (start, end, mut effects, duck, group, pan) = acquire().config.get_file_entry(path).map_or((None, None, vec![], false, None, 0), |entry| (entry.start, entry.end, entry.effects.clone(), entry.duck, entry.group.clone(), entry.pan));
+		// The dialog's effects run after the file's
+		effects.extend(self.effects.iter().cloned());
+		let spread = self.pan_random.min(100) as i32;
+		let pan = pan + self.pan + rng.gen_range(-spread..=spread);
+		PlayOptions {
+			volume: self.volume as f32 / 100.0,
+			fade_in: self.fade_in,
+			fade_out: self.fade_out,
+			start,
+			end,
+			effects,
+			duck,
+			group: self.group.clone().or(group),
+			dialog: Some(self.uuid),
+			pan: pan.clamp(-100, 100),
+			..PlayOptions::default()
 		}
 	}
 
-	fn get_next_path(&mut self) -> &String {
+	// `rng` picks the clips in random mode
+	pub fn get_next_path(&mut self, rng: &mut impl Rng) -> &String {
 		if self.random {
 			if self.play_next == 0 {
-				self.play_next = rand::thread_rng().gen_range(0..self.files.len());
+				self.play_next = rng.gen_range(0..self.files.len());
 			} else {
 				self.play_next -= 1;
 				let previous = self.play_next;
 				while self.play_next == previous {
-					self.play_next = rand::thread_rng().gen_range(0..self.files.len());
+					self.play_next = rng.gen_range(0..self.files.len());
 				}
 			}
 		}
@@ -100,8 +141,8 @@ impl Dialog {
 					} else {
 						Arc::new(Mutex::new(()))
 					};
-					let volume = dialog.volume as f32 / 100.0;
-					play_file(dialog.get_next_path(), volume, lock.clone());
+					let path = dialog.get_next_path(&mut rand::thread_rng()).clone();
+					play_file(&path, dialog.play_options(&path, &mut rand::thread_rng()), lock.clone());
 					if dialog.sequential {
 						let _locked = lock.lock().expect("Failed to lock play lock");
 					} else {
@@ -126,8 +167,8 @@ impl Dialog {
 					} else {
 						Arc::new(Mutex::new(()))
 					};
-					let volume = dialog.volume as f32 / 100.0;
-					play_file(dialog.get_next_path(), volume, lock.clone());
+					let path = dialog.get_next_path(&mut rand::thread_rng()).clone();
+					play_file(&path, dialog.play_options(&path, &mut rand::thread_rng()), lock.clone());
 					if dialog.sequential {
 						let _locked = lock.lock().expect("Failed to lock play lock");
 					} else {
@@ -0,0 +1,169 @@
+use std::{fs::{self, File}, io::Write, path::{Path, PathBuf}, sync::{Arc, atomic::{AtomicU64, Ordering}}, thread, time::{SystemTime, UNIX_EPOCH}};
+
+use memmap2::Mmap;
+use uuid::Uuid;
+
+use crate::{component::block::log, constant::APP_NAME, util::{cache::Pcm, file::Resampling}};
+
+// Most bytes kept on disk. Nothing is written when 0.
+static LIMIT: AtomicU64 = AtomicU64::new(0);
+
+pub fn cache_dir() -> Option<PathBuf> {
+	dirs::cache_dir().map(|dir| dir.join(APP_NAME).join("pcm"))
+}
+
+pub fn set_disk_cache_size(megabytes: u32) {
+	LIMIT.store(megabytes as u64 * 1024 * 1024, Ordering::Relaxed);
+}
+
+// Everything that changes the decoded samples. A replaced file has a different stamp, so its old entry is never read again and ages out.
+fn entry_key(path: &str, sample_rate: u32, resampling: Resampling) -> Option<String> {
+	let meta = fs::metadata(path).ok()?;
+	let modified = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?.as_nanos();
+	Some(format!("{}\0{}\0{}\0{}\0{:?}", path, modified, meta.len(), sample_rate, resampling))
+}
+
+// Entries are named after a hash of their key, which has to stay the same across builds
+fn entry_path(key: &str) -> Option<PathBuf> {
+	Some(cache_dir()?.join(format!("{:016x}.pcm", fnv1a(key.as_bytes()))))
+}
+
+// 64-bit FNV-1a
+fn fnv1a(bytes: &[u8]) -> u64 {
+	bytes.iter().fold(0xcbf29ce484222325, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001b3))
+}
+
+// An entry starts with the length of its key and the key, padded so the samples after it stay aligned
+fn entry_header(key: &str) -> Vec<u8> {
+	let mut header = (key.len() as u32).to_le_bytes().to_vec();
+	header.extend_from_slice(key.as_bytes());
+	header.resize(header.len().next_multiple_of(size_of::<f32>()), 0);
+	header
+}
+
+// Maps previously decoded audio, if there is any
+pub fn load_pcm(path: &str, sample_rate: u32, resampling: Resampling) -> Option<Pcm> {
+	if LIMIT.load(Ordering::Relaxed) == 0 {
+		return None;
+	}
+	let key = entry_key(path, sample_rate, resampling)?;
+	let entry = entry_path(&key)?;
+	let file = File::open(&entry).ok()?;
+	// SAFETY: entries are written to a temporary file and renamed into place, so a mapped entry is never modified
+	let map = unsafe { Mmap::map(&file) }.ok()?;
+	let header = entry_header(&key);
+	if !map.starts_with(&header) {
+		// Written by an older version, or another file whose key has the same hash
+		return None;
+	}
+	if !(map.len() - header.len()).is_multiple_of(size_of::<f32>()) {
+		fs::remove_file(&entry).ok();
+		return None;
+	}
+	// Recently used entries are evicted last
+	file.set_modified(SystemTime::now()).ok();
+	Some(Pcm::Mapped(map, header.len()))
+}
+
+// Writes decoded audio in the background
+pub fn store_pcm(path: &str, sample_rate: u32, resampling: Resampling, data: Arc<Pcm>) {
+	let limit = LIMIT.load(Ordering::Relaxed);
+	if limit == 0 || size_of_val::<[f32]>(&data) as u64 > limit {
+		return;
+	}
+	let path = path.to_string();
+	thread::spawn(move || {
+		let Some(key) = entry_key(&path, sample_rate, resampling) else { return };
+		let Some(entry) = entry_path(&key) else { return };
+		if entry.exists() {
+			return;
+		}
+		if let Some(parent) = entry.parent() {
+			fs::create_dir_all(parent).ok();
+		}
+		// Named per write, so threads storing the same entry do not write over each other
+		let temp = entry.with_extension(format!("{}.tmp", Uuid::new_v4()));
+		let written = File::create(&temp).and_then(|mut file| {
+			file.write_all(&entry_header(&key))?;
+			file.write_all(bytemuck::cast_slice::<f32, u8>(&data))
+		});
+		if written.and_then(|_| fs::rename(&temp, &entry)).is_err() {
+			fs::remove_file(&temp).ok();
+			log::error(format!("Failed to write {} to the disk cache", path).as_str());
+			return;
+		}
+		if let Some(dir) = entry.parent() {
+			evict(dir, limit);
+		}
+	});
+}
+
+// Removes the least recently used entries in `dir` until they fit in `limit` bytes
+fn evict(dir: &Path, limit: u64) {
+	let Ok(read_dir) = fs::read_dir(dir) else { return };
+	// Files still being written are not entries yet
+	let mut entries = read_dir.filter_map(|entry| {
+		let entry = entry.ok()?;
+		if entry.path().extension().is_none_or(|ext| ext != "pcm") {
+			return None;
+		}
+		let meta = entry.metadata().ok()?;
+		Some((entry.path(), meta.len(), meta.modified().ok()?))
+	}).collect::<Vec<_>>();
+	let mut total = entries.iter().map(|(_, len, _)| len).sum::<u64>();
+	entries.sort_by_key(|(_, _, modified)| *modified);
+	for (path, len, _) in entries {
+		if total <= limit {
+			break;
+		}
+		if fs::remove_file(path).is_ok() {
+			total -= len;
+		}
+	}
+}
+
+// Deletes every entry. Returns the number of bytes freed.
+pub fn clear_disk_cache() -> std::io::Result<u64> {
+	let Some(dir) = cache_dir().filter(|dir| dir.exists()) else { return Ok(0) };
+	let size = fs::read_dir(&dir)?.filter_map(|entry| entry.ok()?.metadata().ok()).map(|meta| meta.len()).sum();
+	fs::remove_dir_all(dir)?;
+	Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn hash_is_stable() {
+		// Reference values of 64-bit FNV-1a
+		assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+		assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+		assert_eq!(fnv1a(b"foobar"), 0x85944171f73967e8);
+	}
+
+	#[test]
+	fn eviction_leaves_files_being_written() {
+		let dir = std::env::temp_dir().join(format!("{}-test-{}-evict", APP_NAME, std::process::id()));
+		fs::create_dir_all(&dir).unwrap();
+		for name in ["old.pcm", "new.pcm", "new.1234.tmp"] {
+			fs::write(dir.join(name), [0; 100]).unwrap();
+			thread::sleep(std::time::Duration::from_millis(10));
+		}
+		evict(&dir, 150);
+		let mut left = fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().file_name().to_string_lossy().to_string()).collect::<Vec<_>>();
+		left.sort();
+		fs::remove_dir_all(&dir).ok();
+		assert_eq!(left, ["new.1234.tmp", "new.pcm"]);
+	}
+
+	#[test]
+	fn header_keeps_samples_aligned() {
+		for key in ["", "a", "ab", "abc", "/some/file.wav\0stamp"] {
+			let header = entry_header(key);
+			assert_eq!(header.len() % size_of::<f32>(), 0);
+			assert_eq!(header[..4], (key.len() as u32).to_le_bytes());
+			assert!(header[4..].starts_with(key.as_bytes()));
+		}
+	}
+}
@@ -0,0 +1,39 @@
+// Smoothed gain for the voices under a ducker.
+// Falls toward the ducked level while a ducker plays, and recovers once none are left.
+pub struct Ducker {
+	gain: f32,
+	sample_rate: u32,
+}
+
+impl Ducker {
+	pub fn new(sample_rate: u32) -> Self {
+		Self {
+			gain: 1.0,
+			sample_rate,
+		}
+	}
+
+	// Scales `buf` by the gain, with `amount` in dB and `attack` / `release` in milliseconds
+	pub fn process(&mut self, buf: &mut [f32], active: bool, amount: f32, attack: u32, release: u32) {
+		let (target, time) = if active {
+			(10_f32.powf(-amount.max(0.0) / 20.0), attack)
+		} else {
+			(1.0, release)
+		};
+		// One-pole smoothing, reaching about 63% of the way in `time`
+		let coefficient = if time == 0 { 1.0 } else { 1.0 - (-1.0 / (time as f32 / 1000.0 * self.sample_rate as f32)).exp() };
+		for frame in buf.chunks_exact_mut(2) {
+			self.gain += (target - self.gain) * coefficient;
+			frame[0] *= self.gain;
+			frame[1] *= self.gain;
+		}
+		// The smoothing never quite arrives
+		if (target - self.gain).abs() < 0.0001 {
+			self.gain = target;
+		}
+	}
+
+	pub fn reset(&mut self) {
+		self.gain = 1.0;
+	}
+}
@@ -0,0 +1,494 @@
+use std::f32::consts::PI;
+
+use serde::{Deserialize, Serialize};
+
+use crate::util::fade::ms_to_frames;
+
+// Longest tail kept playing after a voice ends
+const MAX_TAIL_MS: u32 = 5000;
+// Reverb tunings from Freeverb, in frames at 44.1 kHz
+const COMB_TUNING: [usize; 4] = [1116, 1277, 1422, 1617];
+const ALLPASS_TUNING: [usize; 2] = [556, 441];
+const STEREO_SPREAD: usize = 23;
+
+// An effect and its settings, as stored in the config.
+// Integers only, so entries holding them stay comparable.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Effect {
+	// Cutoff in Hz
+	LowPass { cutoff: u32 },
+	HighPass { cutoff: u32 },
+	// Gain of each band in dB
+	Eq { low: i32, mid: i32, high: i32 },
+	// Delay in ms, feedback and mix in percent
+	Echo { delay: u32, feedback: u32, mix: u32 },
+	// All in percent
+	Reverb { room: u32, damping: u32, mix: u32 },
+	// Drive in dB, mix in percent
+	Distortion { drive: u32, mix: u32 },
+	// Bit depth, and how many frames each sample is held for
+	Bitcrush { bits: u32, downsample: u32 },
+}
+
+impl Default for Effect {
+	fn default() -> Self {
+		Self::defaults()[0].clone()
+	}
+}
+
+impl Effect {
+	// Every kind with its default settings, in the order they are cycled through
+	fn defaults() -> [Effect; 7] {
+		[
+			Effect::LowPass { cutoff: 1000 },
+			Effect::HighPass { cutoff: 200 },
+			Effect::Eq { low: 0, mid: 0, high: 0 },
+			Effect::Echo { delay: 250, feedback: 40, mix: 50 },
+			Effect::Reverb { room: 50, damping: 50, mix: 30 },
+			Effect::Distortion { drive: 12, mix: 100 },
+			Effect::Bitcrush { bits: 8, downsample: 4 },
+		]
+	}
+
+	pub fn label(&self) -> &'static str {
+		match self {
+			Effect::LowPass { .. } => "Low-pass",
+			Effect::HighPass { .. } => "High-pass",
+			Effect::Eq { .. } => "EQ",
+			Effect::Echo { .. } => "Echo",
+			Effect::Reverb { .. } => "Reverb",
+			Effect::Distortion { .. } => "Distortion",
+			Effect::Bitcrush { .. } => "Bitcrush",
+		}
+	}
+
+	// The default of the next or previous kind
+	pub fn cycle(&self, delta: i32) -> Effect {
+		let defaults = Self::defaults();
+		let index = defaults.iter().position(|effect| std::mem::discriminant(effect) == std::mem::discriminant(self)).unwrap_or(0);
+		let next = (index as i32 + delta).rem_euclid(defaults.len() as i32) as usize;
+		defaults[next].clone()
+	}
+
+	// Name, value and unit of each parameter
+	pub fn params(&self) -> Vec<(&'static str, i64, &'static str)> {
+		match *self {
+			Effect::LowPass { cutoff } | Effect::HighPass { cutoff } => vec![("Cutoff", cutoff as i64, " Hz")],
+			Effect::Eq { low, mid, high } => vec![("Low", low as i64, " dB"), ("Mid", mid as i64, " dB"), ("High", high as i64, " dB")],
+			Effect::Echo { delay, feedback, mix } => vec![("Delay", delay as i64, " ms"), ("Feedback", feedback as i64, "%"), ("Mix", mix as i64, "%")],
+			Effect::Reverb { room, damping, mix } => vec![("Room", room as i64, "%"), ("Damping", damping as i64, "%"), ("Mix", mix as i64, "%")],
+			Effect::Distortion { drive, mix } => vec![("Drive", drive as i64, " dB"), ("Mix", mix as i64, "%")],
+			Effect::Bitcrush { bits, downsample } => vec![("Bits", bits as i64, ""), ("Downsample", downsample as i64, "x")],
+		}
+	}
+
+	// Values outside the range of the parameter are clamped
+	pub fn set_param(&mut self, index: usize, value: i64) {
+		let clamp = |min: i64, max: i64| value.clamp(min, max);
+		match (self, index) {
+			(Effect::LowPass { cutoff } | Effect::HighPass { cutoff }, 0) => *cutoff = clamp(20, 20000) as u32,
+			(Effect::Eq { low, .. }, 0) => *low = clamp(-24, 24) as i32,
+			(Effect::Eq { mid, .. }, 1) => *mid = clamp(-24, 24) as i32,
+			(Effect::Eq { high, .. }, 2) => *high = clamp(-24, 24) as i32,
+			(Effect::Echo { delay, .. }, 0) => *delay = clamp(1, 2000) as u32,
+			(Effect::Echo { feedback, .. }, 1) => *feedback = clamp(0, 95) as u32,
+			(Effect::Echo { mix, .. }, 2) => *mix = clamp(0, 100) as u32,
+			(Effect::Reverb { room, .. }, 0) => *room = clamp(0, 100) as u32,
+			(Effect::Reverb { damping, .. }, 1) => *damping = clamp(0, 100) as u32,
+			(Effect::Reverb { mix, .. }, 2) => *mix = clamp(0, 100) as u32,
+			(Effect::Distortion { drive, .. }, 0) => *drive = clamp(0, 48) as u32,
+			(Effect::Distortion { mix, .. }, 1) => *mix = clamp(0, 100) as u32,
+			(Effect::Bitcrush { bits, .. }, 0) => *bits = clamp(1, 16) as u32,
+			(Effect::Bitcrush { downsample, .. }, 1) => *downsample = clamp(1, 64) as u32,
+			_ => ()
+		}
+	}
+}
+
+// Runs interleaved stereo through a list of effects, in order
+pub struct EffectChain {
+	processors: Vec<Processor>,
+	// Frames the effects keep ringing for after the input stops
+	tail: usize,
+}
+
+impl EffectChain {
+	// Returns None if there are no effects
+	pub fn new(effects: &[Effect], sample_rate: u32) -> Option<Self> {
+		if effects.is_empty() {
+			return None;
+		}
+		let processors = effects.iter().map(|effect| Processor::new(effect, sample_rate)).collect::<Vec<_>>();
+		let tail = processors.iter().map(|processor| processor.tail()).sum::<usize>().min(ms_to_frames(MAX_TAIL_MS, sample_rate));
+		Some(Self { processors, tail })
+	}
+
+	pub fn tail(&self) -> usize {
+		self.tail
+	}
+
+	pub fn process(&mut self, buf: &mut [f32]) {
+		for processor in self.processors.iter_mut() {
+			processor.process(buf);
+		}
+	}
+
+	// Clears delay lines and filter history
+	pub fn reset(&mut self) {
+		for processor in self.processors.iter_mut() {
+			processor.reset();
+		}
+	}
+}
+
+// Master bus effects, rebuilt whenever the config changes them
+#[derive(Default)]
+pub struct MasterEffects {
+	effects: Vec<Effect>,
+	chain: Option<EffectChain>,
+}
+
+impl MasterEffects {
+	pub fn process(&mut self, buf: &mut [f32], effects: &[Effect], sample_rate: u32) {
+		if self.effects != effects {
+			self.effects = effects.to_vec();
+			self.chain = EffectChain::new(effects, sample_rate);
+		}
+		if let Some(chain) = self.chain.as_mut() {
+			chain.process(buf);
+		}
+	}
+
+	// Frames the effects keep ringing for after the voices end
+	pub fn tail(&self) -> usize {
+		self.chain.as_ref().map_or(0, |chain| chain.tail())
+	}
+
+	pub fn reset(&mut self) {
+		if let Some(chain) = self.chain.as_mut() {
+			chain.reset();
+		}
+	}
+}
+
+enum Processor {
+	Filters(Vec<Biquad>),
+	Echo(Echo),
+	Reverb(Box<Reverb>),
+	Distortion { gain: f32, mix: f32 },
+	Bitcrush { step: f32, hold: usize, counter: usize, held: [f32; 2] },
+}
+
+impl Processor {
+	fn new(effect: &Effect, sample_rate: u32) -> Self {
+		let rate = sample_rate as f32;
+		match *effect {
+			Effect::LowPass { cutoff } => Processor::Filters(vec![Biquad::low_pass(cutoff as f32, rate)]),
+			Effect::HighPass { cutoff } => Processor::Filters(vec![Biquad::high_pass(cutoff as f32, rate)]),
+			Effect::Eq { low, mid, high } => Processor::Filters(vec![
+				Biquad::low_shelf(200.0, low as f32, rate),
+				Biquad::peaking(1000.0, mid as f32, rate),
+				Biquad::high_shelf(5000.0, high as f32, rate),
+			]),
+			Effect::Echo { delay, feedback, mix } => Processor::Echo(Echo::new(ms_to_frames(delay, sample_rate).max(1), feedback as f32 / 100.0, mix as f32 / 100.0)),
+			Effect::Reverb { room, damping, mix } => Processor::Reverb(Box::new(Reverb::new(room as f32 / 100.0, damping as f32 / 100.0, mix as f32 / 100.0, sample_rate))),
+			Effect::Distortion { drive, mix } => Processor::Distortion { gain: 10_f32.powf(drive as f32 / 20.0), mix: mix as f32 / 100.0 },
+			Effect::Bitcrush { bits, downsample } => Processor::Bitcrush { step: 2.0 / 2_f32.powi(bits as i32), hold: downsample.max(1) as usize, counter: 0, held: [0.0; 2] },
+		}
+	}
+
+	fn tail(&self) -> usize {
+		match self {
+			Processor::Echo(echo) => echo.tail(),
+			Processor::Reverb(reverb) => reverb.tail(),
+			_ => 0
+		}
+	}
+
+	fn process(&mut self, buf: &mut [f32]) {
+		match self {
+			Processor::Filters(filters) => for filter in filters.iter_mut() {
+				filter.process(buf);
+			},
+			Processor::Echo(echo) => echo.process(buf),
+			Processor::Reverb(reverb) => reverb.process(buf),
+			Processor::Distortion { gain, mix } => for sample in buf.iter_mut() {
+				*sample += ((*sample * *gain).tanh() - *sample) * *mix;
+			},
+			Processor::Bitcrush { step, hold, counter, held } => for frame in buf.chunks_exact_mut(2) {
+				if *counter == 0 {
+					held[0] = (frame[0] / *step).round() * *step;
+					held[1] = (frame[1] / *step).round() * *step;
+				}
+				*counter = (*counter + 1) % *hold;
+				frame.copy_from_slice(held);
+			},
+		}
+	}
+
+	fn reset(&mut self) {
+		match self {
+			Processor::Filters(filters) => filters.iter_mut().for_each(|filter| filter.state = [[0.0; 2]; 2]),
+			Processor::Echo(echo) => echo.line.fill(0.0),
+			Processor::Reverb(reverb) => reverb.reset(),
+			Processor::Distortion { .. } => (),
+			Processor::Bitcrush { counter, .. } => *counter = 0,
+		}
+	}
+}
+
+// Second-order filter from the Audio EQ Cookbook, in transposed direct form II
+struct Biquad {
+	b: [f32; 3],
+	a: [f32; 2],
+	// Per channel
+	state: [[f32; 2]; 2],
+}
+
+impl Biquad {
+	fn from_coefficients(b: [f32; 3], a: [f32; 3]) -> Self {
+		Self {
+			b: [b[0] / a[0], b[1] / a[0], b[2] / a[0]],
+			a: [a[1] / a[0], a[2] / a[0]],
+			state: [[0.0; 2]; 2],
+		}
+	}
+
+	// Cosine and alpha at `frequency`, kept under the Nyquist frequency
+	fn omega(frequency: f32, q: f32, rate: f32) -> (f32, f32) {
+		let w0 = 2.0 * PI * frequency.min(rate * 0.45) / rate;
+		(w0.cos(), w0.sin() / (2.0 * q))
+	}
+
+	fn low_pass(frequency: f32, rate: f32) -> Self {
+		let (cos, alpha) = Self::omega(frequency, 0.707, rate);
+		Self::from_coefficients([(1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0], [1.0 + alpha, -2.0 * cos, 1.0 - alpha])
+	}
+
+	fn high_pass(frequency: f32, rate: f32) -> Self {
+		let (cos, alpha) = Self::omega(frequency, 0.707, rate);
+		Self::from_coefficients([(1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0], [1.0 + alpha, -2.0 * cos, 1.0 - alpha])
+	}
+
+	fn peaking(frequency: f32, gain: f32, rate: f32) -> Self {
+		let (cos, alpha) = Self::omega(frequency, 0.707, rate);
+		let a = 10_f32.powf(gain / 40.0);
+		Self::from_coefficients([1.0 + alpha * a, -2.0 * cos, 1.0 - alpha * a], [1.0 + alpha / a, -2.0 * cos, 1.0 - alpha / a])
+	}
+
+	fn low_shelf(frequency: f32, gain: f32, rate: f32) -> Self {
+		let (cos, alpha) = Self::omega(frequency, 0.707, rate);
+		let a = 10_f32.powf(gain / 40.0);
+		let sqrt = 2.0 * a.sqrt() * alpha;
+		Self::from_coefficients(
+			[a * ((a + 1.0) - (a - 1.0) * cos + sqrt), 2.0 * a * ((a - 1.0) - (a + 1.0) * cos), a * ((a + 1.0) - (a - 1.0) * cos - sqrt)],
+			[(a + 1.0) + (a - 1.0) * cos + sqrt, -2.0 * ((a - 1.0) + (a + 1.0) * cos), (a + 1.0) + (a - 1.0) * cos - sqrt]
+		)
+	}
+
+	fn high_shelf(frequency: f32, gain: f32, rate: f32) -> Self {
+		let (cos, alpha) = Self::omega(frequency, 0.707, rate);
+		let a = 10_f32.powf(gain / 40.0);
+		let sqrt = 2.0 * a.sqrt() * alpha;
+		Self::from_coefficients(
+			[a * ((a + 1.0) + (a - 1.0) * cos + sqrt), -2.0 * a * ((a - 1.0) + (a + 1.0) * cos), a * ((a + 1.0) + (a - 1.0) * cos - sqrt)],
+			[(a + 1.0) - (a - 1.0) * cos + sqrt, 2.0 * ((a - 1.0) - (a + 1.0) * cos), (a + 1.0) - (a - 1.0) * cos - sqrt]
+		)
+	}
+
+	fn process(&mut self, buf: &mut [f32]) {
+		for frame in buf.chunks_exact_mut(2) {
+			for (sample, state) in frame.iter_mut().zip(self.state.iter_mut()) {
+				let input = *sample;
+				let output = self.b[0] * input + state[0];
+				state[0] = self.b[1] * input - self.a[0] * output + state[1];
+				state[1] = self.b[2] * input - self.a[1] * output;
+				*sample = output;
+			}
+		}
+	}
+}
+
+// Feedback delay, added on top of the dry signal
+struct Echo {
+	// Interleaved stereo
+	line: Vec<f32>,
+	position: usize,
+	feedback: f32,
+	mix: f32,
+}
+
+impl Echo {
+	fn new(frames: usize, feedback: f32, mix: f32) -> Self {
+		Self {
+			line: vec![0.0; frames * 2],
+			position: 0,
+			feedback,
+			mix,
+		}
+	}
+
+	// Until the repeats fall under -60 dB
+	fn tail(&self) -> usize {
+		let delay = self.line.len() / 2;
+		if self.feedback <= 0.0 {
+			return delay;
+		}
+		delay * (0.001_f32.ln() / self.feedback.ln()).ceil() as usize
+	}
+
+	fn process(&mut self, buf: &mut [f32]) {
+		for sample in buf.iter_mut() {
+			let delayed = self.line[self.position];
+			self.line[self.position] = *sample + delayed * self.feedback;
+			*sample += delayed * self.mix;
+			self.position = (self.position + 1) % self.line.len();
+		}
+	}
+}
+
+// Freeverb-style reverb: parallel damped combs into series allpasses, slightly detuned per channel
+struct Reverb {
+	combs: [Vec<Comb>; 2],
+	allpasses: [Vec<Allpass>; 2],
+	feedback: f32,
+	damping: f32,
+	mix: f32,
+}
+
+struct Comb {
+	buf: Vec<f32>,
+	position: usize,
+	filtered: f32,
+}
+
+struct Allpass {
+	buf: Vec<f32>,
+	position: usize,
+}
+
+impl Reverb {
+	fn new(room: f32, damping: f32, mix: f32, sample_rate: u32) -> Self {
+		let scale = |frames: usize| (frames as f32 * sample_rate as f32 / 44100.0) as usize;
+		let combs = |spread: usize| COMB_TUNING.iter().map(|frames| Comb { buf: vec![0.0; scale(frames + spread).max(1)], position: 0, filtered: 0.0 }).collect();
+		let allpasses = |spread: usize| ALLPASS_TUNING.iter().map(|frames| Allpass { buf: vec![0.0; scale(frames + spread).max(1)], position: 0 }).collect();
+		Self {
+			combs: [combs(0), combs(STEREO_SPREAD)],
+			allpasses: [allpasses(0), allpasses(STEREO_SPREAD)],
+			feedback: 0.7 + room * 0.28,
+			damping: damping * 0.4,
+			mix,
+		}
+	}
+
+	// Until the longest comb decays under -60 dB
+	fn tail(&self) -> usize {
+		let longest = self.combs[1].iter().map(|comb| comb.buf.len()).max().unwrap_or(0);
+		longest * (0.001_f32.ln() / self.feedback.ln()).ceil() as usize
+	}
+
+	fn reset(&mut self) {
+		for comb in self.combs.iter_mut().flatten() {
+			comb.buf.fill(0.0);
+			comb.filtered = 0.0;
+		}
+		for allpass in self.allpasses.iter_mut().flatten() {
+			allpass.buf.fill(0.0);
+		}
+	}
+
+	fn process(&mut self, buf: &mut [f32]) {
+		for frame in buf.chunks_exact_mut(2) {
+			// Both channels feed the same reverb, like a room would
+			let input = (frame[0] + frame[1]) * 0.015;
+			for (channel, sample) in frame.iter_mut().enumerate() {
+				let mut wet = 0.0;
+				for comb in self.combs[channel].iter_mut() {
+					let output = comb.buf[comb.position];
+					comb.filtered = output * (1.0 - self.damping) + comb.filtered * self.damping;
+					comb.buf[comb.position] = input + comb.filtered * self.feedback;
+					comb.position = (comb.position + 1) % comb.buf.len();
+					wet += output;
+				}
+				for allpass in self.allpasses[channel].iter_mut() {
+					let delayed = allpass.buf[allpass.position];
+					allpass.buf[allpass.position] = wet + delayed * 0.5;
+					allpass.position = (allpass.position + 1) % allpass.buf.len();
+					wet = delayed - wet;
+				}
+				*sample = *sample * (1.0 - self.mix) + wet * 3.0 * self.mix;
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn impulse(frames: usize) -> Vec<f32> {
+		let mut buf = vec![0.0; frames * 2];
+		buf[0] = 1.0;
+		buf[1] = 1.0;
+		buf
+	}
+
+	#[test]
+	fn no_effects_make_no_chain() {
+		assert!(EffectChain::new(&[], 48000).is_none());
+	}
+
+	#[test]
+	fn params_are_clamped() {
+		let mut effect = Effect::Echo { delay: 250, feedback: 40, mix: 50 };
+		effect.set_param(0, 0);
+		effect.set_param(1, 200);
+		effect.set_param(2, -5);
+		assert_eq!(effect, Effect::Echo { delay: 1, feedback: 95, mix: 0 });
+	}
+
+	#[test]
+	fn cycling_wraps_around() {
+		assert_eq!(Effect::default().cycle(-1).label(), "Bitcrush");
+		assert_eq!(Effect::Bitcrush { bits: 4, downsample: 1 }.cycle(1), Effect::default());
+	}
+
+	#[test]
+	fn echo_repeats_after_the_delay() {
+		// 1 ms at 48 kHz
+		let mut chain = EffectChain::new(&[Effect::Echo { delay: 1, feedback: 50, mix: 100 }], 48000).unwrap();
+		let mut buf = impulse(200);
+		chain.process(&mut buf);
+		assert_eq!(buf[0], 1.0);
+		assert_eq!(buf[48 * 2], 1.0);
+		assert_eq!(buf[96 * 2], 0.5);
+		assert_eq!(buf[144 * 2 + 1], 0.25);
+		chain.reset();
+		let mut silence = vec![0.0; 400];
+		chain.process(&mut silence);
+		assert!(silence.iter().all(|sample| *sample == 0.0));
+	}
+
+	#[test]
+	fn filters_keep_their_band() {
+		let mut low_pass = EffectChain::new(&[Effect::LowPass { cutoff: 1000 }], 48000).unwrap();
+		let mut high_pass = EffectChain::new(&[Effect::HighPass { cutoff: 1000 }], 48000).unwrap();
+		// A constant level settles at full level through a low-pass and at nothing through a high-pass
+		let mut low = vec![0.5; 9600];
+		let mut high = low.clone();
+		low_pass.process(&mut low);
+		high_pass.process(&mut high);
+		assert!((low[low.len() - 1] - 0.5).abs() < 1e-4);
+		assert!(high[high.len() - 1].abs() < 1e-4);
+	}
+
+	#[test]
+	fn flat_eq_changes_nothing() {
+		let mut chain = EffectChain::new(&[Effect::Eq { low: 0, mid: 0, high: 0 }], 48000).unwrap();
+		let input = (0..2000).map(|index| (index as f32 * 0.3).sin() * 0.5).collect::<Vec<_>>();
+		let mut buf = input.clone();
+		chain.process(&mut buf);
+		assert!(buf.iter().zip(input.iter()).all(|(output, input)| (output - input).abs() < 1e-5));
+	}
+}
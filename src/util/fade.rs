@@ -0,0 +1,60 @@
+// Linear gain ramp for a playing voice, advanced once per frame by the mixer
+#[derive(Clone, Copy)]
+pub struct Fade {
+	gain: f32,
+	step: f32,
+	stopping: bool,
+	// Length of the fade before the natural end of the sound, in frames
+	tail: usize,
+}
+
+impl Fade {
+	pub fn new(fade_in: u32, fade_out: u32, sample_rate: u32) -> Self {
+		let frames = ms_to_frames(fade_in, sample_rate);
+		Self {
+			gain: if frames == 0 { 1.0 } else { 0.0 },
+			step: if frames == 0 { 0.0 } else { 1.0 / frames as f32 },
+			stopping: false,
+			tail: ms_to_frames(fade_out, sample_rate),
+		}
+	}
+
+	// Fades out from the current gain, replacing any fade already running
+	pub fn stop(&mut self, duration: u32, sample_rate: u32) {
+		let frames = ms_to_frames(duration, sample_rate);
+		self.stopping = true;
+		if frames == 0 {
+			self.gain = 0.0;
+			self.step = 0.0;
+		} else {
+			self.step = -1.0 / frames as f32;
+		}
+	}
+
+	pub fn is_stopping(&self) -> bool {
+		self.stopping
+	}
+
+	pub fn is_stopped(&self) -> bool {
+		self.stopping && self.gain <= 0.0
+	}
+
+	// Returns the gain for the current frame and moves on to the next one
+	pub fn next(&mut self, remaining: Option<usize>) -> f32 {
+		let gain = self.gain;
+		if self.step != 0.0 {
+			self.gain = (self.gain + self.step).clamp(0.0, 1.0);
+			if !self.stopping && self.gain >= 1.0 {
+				self.step = 0.0;
+			}
+		}
+		match remaining {
+			Some(remaining) if remaining < self.tail => gain * remaining as f32 / self.tail as f32,
+			_ => gain
+		}
+	}
+}
+
+pub fn ms_to_frames(ms: u32, sample_rate: u32) -> usize {
+	(ms as u64 * sample_rate as u64 / 1000) as usize
+}
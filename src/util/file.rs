@@ -1,10 +1,18 @@
-use std::{collections::HashMap, io::{Error, Read}, num::NonZero, path::Path, process::{Command, Stdio}, sync::{Arc, Condvar, LazyLock, Mutex, MutexGuard}, thread, time::{Duration, SystemTime}};
+use std::{collections::{HashMap, HashSet}, io::{Error, Read}, num::NonZero, path::Path, process::{Command, Stdio}, sync::{Arc, Condvar, LazyLock, Mutex, MutexGuard}, thread, time::{Duration, Instant}};
 
-use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use serde::{Deserialize, Serialize};
 use symphonium::{ResampleQuality, SymphoniumLoader};
 use uuid::Uuid;
 
-use crate::{component::block::log, constant::ENDIANESS, state::{acquire, is_running, notify_redraw}};
+use crate::{component::block::log, config::{FileEntry, SoundboardConfig}, constant::ENDIANESS, state::{acquire, is_running, notify_redraw}, util::{audio::linear_to_logarithmic, cache::{AudioCache, Pcm}, channels::Downmix, choke::{Choker, choke_group}, disk_cache::{load_pcm, set_disk_cache_size, store_pcm}, effects::{Effect, EffectChain}, fade::{Fade, ms_to_frames}, loudness::{cached_loudness, normalize_gain}, pan::balance_gains, shift::Shifter, stream::{StreamBuffer, stream_ffmpeg, stream_symphonia}}};
+
+// Files longer than this are streamed instead of fully decoded and cached
+const STREAM_THRESHOLD_SECS: u64 = 30;
+// Fallback when the length cannot be probed
+const STREAM_THRESHOLD_BYTES: u64 = 16 * 1024 * 1024;
+// Short fade for voices cut off by a retrigger, so they do not click
+const RESTART_FADE_MS: u32 = 10;
 
 pub fn parent_file(str: &str) -> (String, String) {
 	let path = Path::new(str);
@@ -13,43 +21,600 @@ pub fn parent_file(str: &str) -> (String, String) {
 	(parent, name)
 }
 
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum PlaybackMode {
+	#[default]
+	OneShot,
+	// Loops while the hotkey is held
+	Hold,
+	// Each trigger starts or stops a loop
+	Toggle
+}
+
+impl PlaybackMode {
+	pub fn next(&self) -> Self {
+		use PlaybackMode::*;
+		match self {
+			OneShot => Hold,
+			Hold => Toggle,
+			Toggle => OneShot,
+		}
+	}
+}
+
+// What triggering a file does while it is already playing
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Retrigger {
+	// Plays another copy on top, up to the file's voice limit
+	#[default]
+	Overlap,
+	// Stops the playing copies and starts again
+	Restart,
+	// Does nothing until it finishes
+	Ignore,
+	// Stops the playing copies instead of starting a new one
+	Stop
+}
+
+impl Retrigger {
+	pub fn next(&self) -> Self {
+		use Retrigger::*;
+		match self {
+			Overlap => Restart,
+			Restart => Ignore,
+			Ignore => Stop,
+			Stop => Overlap,
+		}
+	}
+
+	pub fn label(&self) -> &'static str {
+		use Retrigger::*;
+		match self {
+			Overlap => "Overlap",
+			Restart => "Restart",
+			Ignore => "Ignore",
+			Stop => "Stop",
+		}
+	}
+}
+
+// Resampler used when a file's sample rate differs from the output
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Resampling {
+	#[default]
+	Low,
+	High
+}
+
+impl Resampling {
+	pub fn next(&self) -> Self {
+		use Resampling::*;
+		match self {
+			Low => High,
+			High => Low,
+		}
+	}
+
+	pub fn label(&self) -> &'static str {
+		use Resampling::*;
+		match self {
+			Low => "Low",
+			High => "High",
+		}
+	}
+
+	pub fn quality(&self) -> ResampleQuality {
+		use Resampling::*;
+		match self {
+			Low => ResampleQuality::Low,
+			High => ResampleQuality::High,
+		}
+	}
+}
+
+pub enum AudioSource {
+	Cached(Arc<Pcm>),
+	Stream(Arc<StreamBuffer>),
+}
+
+// Per-play settings, taken from the file entry or the dialog playing the file
+#[derive(Clone)]
+pub struct PlayOptions {
+	pub volume: f32,
+	pub fade_in: u32,
+	pub fade_out: u32,
+	// Trim points in milliseconds
+	pub start: Option<u32>,
+	pub end: Option<u32>,
+	pub looping: bool,
+	// Stop once the file's hotkey is released
+	pub hold: bool,
+	// Playback rate multiplier
+	pub speed: f32,
+	// Pitch shift in semitones
+	pub pitch: i32,
+	// Keep the pitch when the speed changes
+	pub stretch: bool,
+	pub effects: Vec<Effect>,
+	// Lowers the other voices while playing
+	pub duck: bool,
+	// Choke group
+	pub group: Option<String>,
+	// Internal id of the dialog playing the file
+	pub dialog: Option<Uuid>,
+	// -100 (left) to 100 (right)
+	pub pan: i32,
+}
+
+impl Default for PlayOptions {
+	fn default() -> Self {
+		Self {
+			volume: 1.0,
+			fade_in: 0,
+			fade_out: 0,
+			start: None,
+			end: None,
+			looping: false,
+			hold: false,
+			speed: 1.0,
+			pitch: 0,
+			stretch: false,
+			effects: vec![],
+			duck: false,
+			group: None,
+			dialog: None,
+			pan: 0,
+		}
+	}
+}
+
+impl From<&FileEntry> for PlayOptions {
+	fn from(entry: &FileEntry) -> Self {
+		Self {
+			volume: entry.volume as f32 / 100.0,
+			fade_in: entry.fade_in,
+			fade_out: entry.fade_out,
+			start: entry.start,
+			end: entry.end,
+			looping: false,
+			hold: false,
+			speed: entry.speed as f32 / 100.0,
+			pitch: entry.pitch,
+			stretch: entry.stretch,
+			effects: entry.effects.clone(),
+			duck: entry.duck,
+			group: entry.group.clone(),
+			dialog: None,
+			pan: entry.pan,
+		}
+	}
+}
+
 pub struct PlayableFile {
-	pub data: Vec<f32>,
+	pub path: String,
+	pub source: AudioSource,
+	// Sample index into the source, trimmed region included
 	pub position: usize,
+	// Where a loop restarts from
+	pub start: usize,
+	pub end: Option<usize>,
+	pub looping: bool,
+	pub paused: bool,
 	pub volume: f32,
-	pub finished: Arc<(Mutex<()>, Condvar)>,
+	// Left and right gains
+	pub pan: (f32, f32),
+	// Integrated loudness in LUFS, if measured during scanning
+	pub loudness: Option<f32>,
+	pub fade: Fade,
+	// Speed and pitch, if either is changed
+	pub shifter: Option<Shifter>,
+	pub effects: Option<EffectChain>,
+	// Frames of effect tail left to play once the source has ended
+	pub tail: usize,
+	pub ducker: bool,
+	pub group: Option<String>,
+	pub dialog: Option<Uuid>,
+	pub started: Instant,
+	// Set once the mixer drops the voice, for the thread that started it
+	pub finished: Arc<(Mutex<bool>, Condvar)>,
+	// Source samples and the voice before its effects, kept between chunks so the mixer does not allocate
+	scratch: (Vec<f32>, Vec<f32>),
+}
+
+impl PlayableFile {
+	// Decodes or starts streaming the file, set up with `options`.
+	// Long files are only streamed if `allow_stream` is set and the file is not looping.
+	pub fn load(path: &String, options: PlayOptions, sample_rate: u32, resampling: Resampling, allow_stream: bool) -> Option<Self> {
+		let start = options.start.map_or(0, |start| ms_to_frames(start, sample_rate) * 2);
+		let end = options.end.map(|end| ms_to_frames(end, sample_rate) * 2);
+		let source = load_audio(path, sample_rate, resampling, start, allow_stream && !options.looping)?;
+		if let AudioSource::Stream(stream) = &source {
+			stream.wait_ready(Duration::from_millis(50));
+		}
+		let effects = EffectChain::new(&options.effects, sample_rate);
+		let tail = effects.as_ref().map_or(0, |effects| effects.tail());
+		let start = match &source {
+			AudioSource::Cached(data) => start.min(data.len()),
+			AudioSource::Stream(_) => start,
+		};
+		Some(Self {
+			path: path.clone(),
+			position: start,
+			source,
+			start,
+			end,
+			looping: options.looping,
+			paused: false,
+			volume: options.volume,
+			// Decoded files are stereo, so panning them is a balance
+			pan: balance_gains(options.pan),
+			loudness: cached_loudness(path),
+			fade: Fade::new(options.fade_in, options.fade_out, sample_rate),
+			shifter: Shifter::new(options.speed, options.pitch, options.stretch, sample_rate),
+			effects,
+			tail,
+			ducker: options.duck,
+			group: options.group,
+			dialog: options.dialog,
+			started: Instant::now(),
+			finished: Arc::new((Mutex::new(false), Condvar::new())),
+			scratch: (vec![], vec![]),
+		})
+	}
+
+	// Gain under the master `volume`, with normalization to `target` if it is on
+	pub fn gain(&self, volume: f32, target: Option<f32>) -> f32 {
+		// Normalization brings the clip to the target, and the file volume trims on top of it
+		let gain = target.zip(self.loudness).map_or(1.0, |(target, loudness)| normalize_gain(loudness, target));
+		linear_to_logarithmic(self.volume * volume) * gain
+	}
+
+	pub fn mix_into(&mut self, buf: &mut [f32], volume: f32) {
+		if self.paused {
+			return;
+		}
+		// Frames left until the end, for the fade out before it
+		let remaining = if self.looping { None } else {
+			let tempo = self.shifter.as_ref().map_or(1.0, |shifter| shifter.tempo());
+			self.end_position().map(|end| (end.saturating_sub(self.position) / 2) as f64 / tempo).map(|frames| frames as usize)
+		};
+		let (mut samples, mut voice) = std::mem::take(&mut self.scratch);
+		samples.clear();
+		samples.resize(buf.len(), 0.0);
+		let read = match self.shifter.take() {
+			Some(mut shifter) => {
+				let read = shifter.process(&mut samples, |input| self.read_source(input));
+				self.shifter = Some(shifter);
+				read
+			},
+			None => self.read_source(&mut samples),
+		};
+		if let Some(effects) = self.effects.as_mut() {
+			// Effects run after the fades, so their tails ring out past the end
+			voice.clear();
+			voice.resize(buf.len(), 0.0);
+			mix_frames(&mut voice, &samples[..read], volume, self.pan, &mut self.fade, remaining);
+			effects.process(&mut voice);
+			for (out, sample) in buf.iter_mut().zip(voice.iter()) {
+				*out += sample;
+			}
+			if self.source_ended() {
+				self.tail = self.tail.saturating_sub((buf.len() - read) / 2);
+			}
+		} else {
+			mix_frames(buf, &samples[..read], volume, self.pan, &mut self.fade, remaining);
+		}
+		self.scratch = (samples, voice);
+	}
+
+	// Copies source samples into `out`, up to the end of the trimmed region. Returns how many were read.
+	fn read_source(&mut self, out: &mut [f32]) -> usize {
+		if self.looping && let AudioSource::Cached(data) = &self.source {
+			// Looping voices are always cached, so the position can wrap around
+			let end = self.end_position().unwrap_or(data.len());
+			let mut filled = 0;
+			while filled < out.len() {
+				let read = (out.len() - filled).min(end.saturating_sub(self.position));
+				if read == 0 && self.position <= self.start {
+					break;
+				}
+				out[filled..filled + read].copy_from_slice(&data[self.position..self.position + read]);
+				filled += read;
+				self.position += read;
+				if self.position >= end {
+					self.position = self.start;
+				}
+			}
+			return filled;
+		}
+		let end = self.end_position();
+		let max_read = end.map_or(out.len(), |end| out.len().min(end.saturating_sub(self.position)));
+		match &self.source {
+			AudioSource::Cached(data) => {
+				out[..max_read].copy_from_slice(&data[self.position..self.position + max_read]);
+				self.position += max_read;
+				max_read
+			},
+			AudioSource::Stream(stream) => {
+				let read = stream.read_into(&mut out[..max_read]);
+				self.position += read;
+				read
+			}
+		}
+	}
+
+	// Where playback stops, if known
+	fn end_position(&self) -> Option<usize> {
+		let length = match &self.source {
+			AudioSource::Cached(data) => Some(data.len()),
+			AudioSource::Stream(stream) => stream.length,
+		};
+		match (self.end, length) {
+			(Some(end), Some(length)) => Some(end.min(length)),
+			(end, length) => end.or(length),
+		}
+	}
+
+	// Moves the position by `offset` samples within the trimmed region.
+	// Streams cannot go back, since decoded samples are not kept.
+	pub fn seek(&mut self, offset: i64) -> bool {
+		let target = (self.position as i64 + offset).max(self.start as i64) as usize;
+		let target = self.end_position().map_or(target, |end| target.min(end));
+		// Stay on a frame boundary
+		let target = target - target % 2;
+		if let Some(shifter) = self.shifter.as_mut() {
+			shifter.reset();
+		}
+		match &self.source {
+			AudioSource::Cached(_) => self.position = target,
+			AudioSource::Stream(stream) => {
+				if target < self.position {
+					return false;
+				}
+				stream.skip(target - self.position);
+				self.position = target;
+			}
+		}
+		true
+	}
+
+	// Whether everything from the source has been played
+	fn source_ended(&self) -> bool {
+		!self.looping && (self.end_position().is_some_and(|end| self.position >= end)
+			|| match &self.source {
+				AudioSource::Cached(_) => false,
+				AudioSource::Stream(stream) => stream.is_drained(),
+			})
+			// The shifter reads ahead, so let it play out what it has
+			&& self.shifter.as_ref().is_none_or(|shifter| shifter.is_dry())
+	}
+
+	// Wakes the thread waiting for the voice to end
+	pub fn notify_finished(&self) {
+		let (lock, cvar) = &*self.finished;
+		*lock.lock().unwrap() = true;
+		cvar.notify_all();
+	}
+
+	pub fn is_finished(&self) -> bool {
+		// Paused voices never reach the end of a fade, so stop them right away
+		(self.paused && self.fade.is_stopping())
+			|| self.fade.is_stopped()
+			|| (self.source_ended() && self.tail == 0)
+	}
+}
+
+fn mix_frames(buf: &mut [f32], samples: &[f32], volume: f32, (left, right): (f32, f32), fade: &mut Fade, remaining: Option<usize>) {
+	for (ii, frame) in samples.chunks_exact(2).enumerate() {
+		let gain = volume * fade.next(remaining.map(|remaining| remaining.saturating_sub(ii)));
+		buf[ii * 2] += frame[0] * gain * left;
+		buf[ii * 2 + 1] += frame[1] * gain * right;
+	}
+}
+
+impl Drop for PlayableFile {
+	fn drop(&mut self) {
+		// Let the decoder thread exit
+		if let AudioSource::Stream(stream) = &self.source {
+			stream.cancel();
+		}
+	}
 }
 
 static PLAYING_FILES: LazyLock<Mutex<HashMap<Uuid, PlayableFile>>> = LazyLock::new(|| { Mutex::new(HashMap::new()) });
 static SYMPHONIUM_LOADER: LazyLock<Mutex<SymphoniumLoader>> = LazyLock::new(|| { Mutex::new(SymphoniumLoader::new()) });
-static AUDIO_CACHE: LazyLock<Mutex<HashMap<String, (Vec<f32>, SystemTime)>>> = LazyLock::new(|| { Mutex::new(HashMap::new()) });
+// The budget is set from the config once the app starts
+static AUDIO_CACHE: LazyLock<Mutex<AudioCache>> = LazyLock::new(|| { Mutex::new(AudioCache::new(0)) });
+// Files whose hotkey is still held down after triggering a loop, so key repeats are ignored
+static HELD_FILES: LazyLock<Mutex<HashSet<String>>> = LazyLock::new(|| { Mutex::new(HashSet::new()) });
+// Voices that were triggered but are still loading, by path, so retrigger checks count them
+static LOADING_FILES: LazyLock<Mutex<HashMap<String, usize>>> = LazyLock::new(|| { Mutex::new(HashMap::new()) });
+// When each file was last triggered, for its cooldown
+static LAST_TRIGGERED: LazyLock<Mutex<HashMap<String, Instant>>> = LazyLock::new(|| { Mutex::new(HashMap::new()) });
 
 pub fn acquire_playing_files() -> MutexGuard<'static, HashMap<Uuid, PlayableFile>> {
 	PLAYING_FILES.lock().unwrap()
 }
 
-pub fn play_file_auto_volume(path: &String, lock: Arc<Mutex<()>>) {
+// Plays a file with its entry's settings, playback mode and retrigger policy.
+// `hotkey` is set when triggered by the file's hotkey, which hold-to-loop depends on.
+pub fn play_file_auto_volume(path: &String, hotkey: bool, lock: Arc<Mutex<()>>) {
 	let path = path.clone();
 	thread::spawn(move || {
 		let app = acquire();
-		let pathed = Path::new(&path);
-		let parent = pathed.parent().unwrap().to_str().unwrap().to_string();
-		let name = pathed.file_name().unwrap().to_os_string().into_string().unwrap();
-		let volume: f32 = match app.config.files.get(&parent) {
-			Some(map) => {
-				match map.get(&name) {
-					Some(entry) => (entry.volume as f32) / 100.0,
-					None => 1.0,
-				}
-			},
-			None => 1.0
+		let (mut options, mode, retrigger, max_voices, cooldown) = match app.config.get_file_entry(&path) {
+			Some(entry) => (PlayOptions::from(entry), entry.mode, entry.retrigger, entry.max_voices, entry.cooldown),
+			None => (PlayOptions::default(), PlaybackMode::OneShot, Retrigger::Overlap, 0, 0)
 		};
+		let sample_rate = app.sample_rate;
 		drop(app);
-		play_file(&path, volume, lock);
+
+		if hotkey && mode != PlaybackMode::OneShot {
+			if !HELD_FILES.lock().unwrap().insert(path.clone()) {
+				return;
+			}
+			release_held_file(path.clone());
+		}
+		if !cooled_down(&path, cooldown) {
+			return;
+		}
+		// Counts the voice from here on, so a second trigger sees it while it loads
+		let mut loading = None;
+		// Toggling already decides what a second trigger does
+		if mode != PlaybackMode::Toggle {
+			match retrigger {
+				Retrigger::Overlap => if max_voices > 0 {
+					let Some(voice) = stop_oldest(&path, max_voices as usize, sample_rate) else { return };
+					loading = Some(voice);
+				},
+				Retrigger::Restart => {
+					stop_voices(&path, RESTART_FADE_MS, sample_rate);
+				},
+				Retrigger::Ignore => {
+					let Some(voice) = reserve_if_idle(&path) else { return };
+					loading = Some(voice);
+				},
+				Retrigger::Stop => if stop_voices(&path, options.fade_out, sample_rate) {
+					return;
+				},
+			}
+		}
+		match mode {
+			PlaybackMode::OneShot => (),
+			PlaybackMode::Hold => {
+				// Without a key to hold, play it once
+				options.looping = hotkey;
+				options.hold = hotkey;
+			},
+			PlaybackMode::Toggle => {
+				if stop_loop(&path, options.fade_out, sample_rate) {
+					return;
+				}
+				options.looping = true;
+			}
+		}
+		play_loading_file(&path, options, lock, loading);
+	});
+}
+
+// Fades out looping voices of a file. Returns false if there were none.
+fn stop_loop(path: &str, fade: u32, sample_rate: u32) -> bool {
+	let mut stopped = false;
+	for playable in acquire_playing_files().values_mut() {
+		if playable.path == path && playable.looping && !playable.fade.is_stopping() {
+			playable.fade.stop(fade, sample_rate);
+			stopped = true;
+		}
+	}
+	stopped
+}
+
+// Records the trigger unless it comes within `cooldown` milliseconds of the last one
+fn cooled_down(path: &str, cooldown: u32) -> bool {
+	if cooldown == 0 {
+		return true;
+	}
+	let mut last_triggered = LAST_TRIGGERED.lock().unwrap();
+	if last_triggered.get(path).is_some_and(|last| last.elapsed() < Duration::from_millis(cooldown as u64)) {
+		return false;
+	}
+	last_triggered.insert(path.to_string(), Instant::now());
+	true
+}
+
+// A voice of a file that is still loading. Stops being counted once it plays or fails to load.
+struct LoadingVoice(String);
+
+impl LoadingVoice {
+	// Must be created with the playing files locked, so the retrigger check and the reservation happen together
+	fn new(path: &str) -> Self {
+		*LOADING_FILES.lock().unwrap().entry(path.to_string()).or_default() += 1;
+		Self(path.to_string())
+	}
+}
+
+impl Drop for LoadingVoice {
+	fn drop(&mut self) {
+		let mut loading = LOADING_FILES.lock().unwrap();
+		if let Some(count) = loading.get_mut(&self.0) {
+			*count -= 1;
+			if *count == 0 {
+				loading.remove(&self.0);
+			}
+		}
+	}
+}
+
+fn loading_voices(path: &str) -> usize {
+	LOADING_FILES.lock().unwrap().get(path).copied().unwrap_or(0)
+}
+
+// Reserves a voice, unless the file is already playing or loading
+fn reserve_if_idle(path: &str) -> Option<LoadingVoice> {
+	let playing_files = acquire_playing_files();
+	if loading_voices(path) > 0 || playing_files.values().any(|playable| playable.path == path && !playable.fade.is_stopping()) {
+		return None;
+	}
+	Some(LoadingVoice::new(path))
+}
+
+// Fades out every voice of a file. Returns false if there were none.
+fn stop_voices(path: &str, fade: u32, sample_rate: u32) -> bool {
+	let mut stopped = false;
+	for playable in acquire_playing_files().values_mut() {
+		if playable.path == path && !playable.fade.is_stopping() {
+			playable.fade.stop(fade, sample_rate);
+			stopped = true;
+		}
+	}
+	stopped
+}
+
+// Stops the oldest voices of a file to make room for a new one within `max` voices, and reserves it.
+// Returns None if voices that are still loading already take up all of them.
+fn stop_oldest(path: &str, max: usize, sample_rate: u32) -> Option<LoadingVoice> {
+	let mut playing_files = acquire_playing_files();
+	let loading = loading_voices(path);
+	if loading >= max {
+		return None;
+	}
+	let keep = max - 1 - loading;
+	let mut voices = playing_files.values_mut()
+		.filter(|playable| playable.path == path && !playable.fade.is_stopping())
+		.collect::<Vec<_>>();
+	if voices.len() > keep {
+		voices.sort_by_key(|playable| playable.started);
+		let excess = voices.len() - keep;
+		for playable in voices.into_iter().take(excess) {
+			playable.fade.stop(RESTART_FADE_MS, sample_rate);
+		}
+	}
+	Some(LoadingVoice::new(path))
+}
+
+fn hotkey_pressed(path: &str) -> bool {
+	acquire().hotkey.get(path).is_some_and(|keys| !keys.is_empty() && keys.par_iter().all(|key| key.is_pressed()))
+}
+
+// Accepts the hotkey again once it is released
+fn release_held_file(path: String) {
+	thread::spawn(move || {
+		while is_running() && hotkey_pressed(&path) {
+			thread::sleep(Duration::from_millis(100));
+		}
+		HELD_FILES.lock().unwrap().remove(&path);
 	});
 }
 
-pub fn play_file(path: &String, volume: f32, lock: Arc<Mutex<()>>) {
+pub fn play_file(path: &String, options: PlayOptions, lock: Arc<Mutex<()>>) {
+	play_loading_file(path, options, lock, None);
+}
+
+// Plays a file whose voice may already be counted by `loading`
+fn play_loading_file(path: &String, options: PlayOptions, lock: Arc<Mutex<()>>, loading: Option<LoadingVoice>) {
 	let string = path.trim().to_string();
 	thread::spawn(move || {
 		let _locked = lock.lock().expect("Failed to lock while playing file");
@@ -66,66 +631,237 @@ pub fn play_file(path: &String, volume: f32, lock: Arc<Mutex<()>>) {
 			return;
 		}
 		let sample_rate = app.sample_rate;
+		let resampling = app.config.file_resampling(&string);
 		drop(app);
 
-		let mut cache = AUDIO_CACHE.lock().unwrap();
-		let interleaved = if cache.contains_key(&string) {
-			let (data, last_accessed) = cache.get_mut(&string).unwrap();
-			*last_accessed = SystemTime::now();
-			let data = data.clone();
-			drop(cache);
-			data
-		} else {
-			drop(cache);
-			let mut loader = SYMPHONIUM_LOADER.lock().unwrap();
-			let result = loader.load_f32(&string, NonZero::new(sample_rate), ResampleQuality::Low, None);
-			drop(loader);
-			let data = if result.is_err() {
-				log::error(format!("File {} cannot be decoded with symphonium", string).as_str());
-				log::error(format!("{:?}", result.unwrap_err()).as_str());
-
-				let result = read_file_ffmpeg(&string, sample_rate);
-				if result.is_err() {
-					log::error(format!("File {} cannot be decoded with ffmpeg", string).as_str());
-					log::error(format!("{:?}", result.unwrap_err()).as_str());
-					return;
-				}
-				result.unwrap()
-			} else {
-				let audio_data = result.unwrap();
-				if audio_data.channels() == 1 {
-					audio_data.data[0].par_iter().zip(audio_data.data[0].par_iter()).flat_map(|(a, b)| [*a, *b]).collect()
-				} else if audio_data.channels() > 2 {
-					audio_data.data[0].par_iter().zip(audio_data.data[1].par_iter()).flat_map(|(a, b)| [*a, *b]).collect()
-				} else {
-					audio_data.as_interleaved()
-				}
+		let (hold, fade_out) = (options.hold, options.fade_out);
+		let Some(playable) = PlayableFile::load(&string, options, sample_rate, resampling, true) else { return };
+		let finished = playable.finished.clone();
+		if let Some(group) = &playable.group {
+			let choker = match playable.dialog {
+				Some(uuid) => Choker::Dialog(uuid),
+				None => Choker::File(&string),
 			};
-			let mut cache = AUDIO_CACHE.lock().unwrap();
-			cache.insert(string.clone(), (data.clone(), SystemTime::now()));
-			data
-		};
-
-		let finished = Arc::new((Mutex::new(()), Condvar::new()));
-		acquire_playing_files().insert(uuid, PlayableFile { data: interleaved, position: 0, volume, finished: finished.clone() });
+			choke_group(group, Some(choker), sample_rate);
+		}
+		// Playing before it stops counting as loading, so no trigger misses it in between
+		let mut playing_files = acquire_playing_files();
+		playing_files.insert(uuid, playable);
+		drop(loading);
+		drop(playing_files);
 		let mut app = acquire();
 		app.playing_file.insert(uuid, string.to_string());
 		drop(app);
 		notify_redraw();
 
-		let (lock, cvar) = &*finished;
-		drop(cvar.wait(lock.lock().unwrap()).unwrap());
+		if hold {
+			while is_running() && hotkey_pressed(&string) {
+				thread::sleep(Duration::from_millis(100));
+			}
+			// Let the fade out finish before releasing the lock, unless a global stop already started one
+			if let Some(playable) = acquire_playing_files().get_mut(&uuid) && !playable.fade.is_stopping() {
+				playable.fade.stop(fade_out, sample_rate);
+			}
+			while acquire_playing_files().contains_key(&uuid) {
+				thread::sleep(Duration::from_millis(10));
+			}
+		} else {
+			let (lock, cvar) = &*finished;
+			drop(cvar.wait_while(lock.lock().unwrap(), |finished| !*finished).unwrap());
+		}
+		let mut app = acquire();
+		app.playing_file.remove(&uuid);
+		app.paused_file.remove(&uuid);
+		drop(app);
+		notify_redraw();
+	});
+}
+
+// Streams drop `skip` samples before buffering. Cached data is always complete.
+// Long files are only streamed if `allow_stream` is set.
+fn load_audio(path: &String, sample_rate: u32, resampling: Resampling, skip: usize, allow_stream: bool) -> Option<AudioSource> {
+	if let Some(data) = AUDIO_CACHE.lock().unwrap().get(path) {
+		return Some(AudioSource::Cached(data));
+	}
+	if let Some(pcm) = load_pcm(path, sample_rate, resampling) {
+		let data = Arc::new(pcm);
+		AUDIO_CACHE.lock().unwrap().insert(path.clone(), data.clone());
+		return Some(AudioSource::Cached(data));
+	}
+	let quality = resampling.quality();
+
+	let mut loader = SYMPHONIUM_LOADER.lock().unwrap();
+	let result = loader.probe_from_file(path).and_then(|probed| {
+		if allow_stream && should_stream_probed(path, &probed) {
+			return Ok(Err(probed));
+		}
+		let layout = probed.probe_result().format.default_track().and_then(|track| track.codec_params.channels);
+		loader.decode_probed_f32(probed, NonZero::new(sample_rate), quality, None).map(|audio_data| Ok((audio_data, layout)))
 	});
+	drop(loader);
+	let data = match result {
+		Ok(Err(probed)) => return Some(AudioSource::Stream(stream_symphonia(path, probed, sample_rate, quality, skip))),
+		Ok(Ok((audio_data, layout))) => {
+			if audio_data.channels() == 2 {
+				audio_data.as_interleaved()
+			} else {
+				let planar = audio_data.data.iter().map(|channel| channel.as_slice()).collect::<Vec<_>>();
+				Downmix::new(layout, audio_data.channels()).interleave(&planar)
+			}
+		},
+		Err(err) => {
+			log::error(format!("File {} cannot be decoded with symphonium", path).as_str());
+			log::error(format!("{:?}", err).as_str());
+
+			let duration = ffprobe::ffprobe(path).ok().and_then(|info| info.format.get_duration());
+			if allow_stream && should_stream_ffmpeg(path, duration) {
+				match stream_ffmpeg(path, sample_rate, duration, skip) {
+					Ok(stream) => return Some(AudioSource::Stream(stream)),
+					Err(err) => {
+						log::error(format!("File {} cannot be decoded with ffmpeg", path).as_str());
+						log::error(format!("{:?}", err).as_str());
+						return None;
+					}
+				}
+			}
+			match read_file_ffmpeg(path, sample_rate) {
+				Ok(data) => data,
+				Err(err) => {
+					log::error(format!("File {} cannot be decoded with ffmpeg", path).as_str());
+					log::error(format!("{:?}", err).as_str());
+					return None;
+				}
+			}
+		}
+	};
+	let data = Arc::new(Pcm::Memory(data));
+	AUDIO_CACHE.lock().unwrap().insert(path.clone(), data.clone());
+	store_pcm(path, sample_rate, resampling, data.clone());
+	Some(AudioSource::Cached(data))
 }
 
-pub fn stop_all() {
+fn should_stream_probed(path: &str, probed: &symphonium::ProbedAudioSource) -> bool {
+	let track = probed.probe_result().format.default_track();
+	match (track.and_then(|track| track.codec_params.n_frames), probed.sample_rate()) {
+		(Some(frames), Some(rate)) => frames / rate.get() as u64 >= STREAM_THRESHOLD_SECS,
+		_ => std::fs::metadata(path).is_ok_and(|meta| meta.len() >= STREAM_THRESHOLD_BYTES)
+	}
+}
+
+fn should_stream_ffmpeg(path: &str, duration: Option<Duration>) -> bool {
+	match duration {
+		Some(duration) => duration.as_secs() >= STREAM_THRESHOLD_SECS,
+		None => std::fs::metadata(path).is_ok_and(|meta| meta.len() >= STREAM_THRESHOLD_BYTES)
+	}
+}
+
+pub fn stop_all(fade: u32) {
 	// Defer to avoid deadlock
 	thread::spawn(move || {
-		acquire_playing_files().clear();
-		acquire().playing_file.clear();
+		if fade == 0 {
+			clear_playing_files();
+			return;
+		}
+		// The mixer removes the voices once they are silent
+		let sample_rate = { acquire().sample_rate };
+		for playable in acquire_playing_files().values_mut() {
+			playable.fade.stop(fade, sample_rate);
+		}
 	});
 }
 
+pub fn clear_playing_files() {
+	for (_uuid, playable) in acquire_playing_files().drain() {
+		playable.notify_finished();
+	}
+	let mut app = acquire();
+	app.playing_file.clear();
+	app.paused_file.clear();
+}
+
+// Drops all decoded audio, for when the output sample rate changes
+pub fn clear_audio_cache() {
+	AUDIO_CACHE.lock().unwrap().clear();
+}
+
+// Drops decoded audio of the files matching `filter`, so they are resampled again on the next play
+pub fn clear_cached_files(filter: impl Fn(&str) -> bool) {
+	AUDIO_CACHE.lock().unwrap().retain(|path| !filter(path));
+}
+
+// Applies the memory and disk cache sizes from the config, in megabytes
+pub fn set_cache_limits(config: &SoundboardConfig) {
+	AUDIO_CACHE.lock().unwrap().set_budget(config.cache_size as usize * 1024 * 1024);
+	set_disk_cache_size(config.disk_cache_size);
+}
+
+// Decodes files with hotkeys or IDs in the background, so their first play is instant.
+// Long files are streamed when played, so they are skipped.
+pub fn preload_audio() {
+	thread::spawn(move || {
+		let (paths, sample_rate) = {
+			let app = acquire();
+			if app.edit {
+				return;
+			}
+			let mut paths = app.hotkey.keys().chain(app.file_ids.values()).cloned().collect::<Vec<_>>();
+			paths.sort();
+			paths.dedup();
+			let paths = paths.into_iter().map(|path| {
+				let resampling = app.config.file_resampling(&path);
+				(path, resampling)
+			}).collect::<Vec<_>>();
+			(paths, app.sample_rate)
+		};
+		for (path, resampling) in paths {
+			if !is_running() || { acquire().sample_rate } != sample_rate {
+				return;
+			}
+			if AUDIO_CACHE.lock().unwrap().contains(&path) {
+				continue;
+			}
+			if let Some(AudioSource::Stream(stream)) = load_audio(&path, sample_rate, resampling, 0, true) {
+				stream.cancel();
+			}
+		}
+	});
+}
+
+// Pauses or resumes a playing file, or all of them if `uuid` is None.
+// Returns false if nothing was changed.
+pub fn set_paused(uuid: Option<Uuid>, paused: bool) -> bool {
+	let mut changed = vec![];
+	for (id, playable) in acquire_playing_files().iter_mut() {
+		if uuid.is_none_or(|uuid| uuid == *id) && playable.paused != paused {
+			playable.paused = paused;
+			changed.push(*id);
+		}
+	}
+	let mut app = acquire();
+	for id in changed.iter() {
+		if paused {
+			app.paused_file.insert(*id);
+		} else {
+			app.paused_file.remove(id);
+		}
+	}
+	!changed.is_empty()
+}
+
+// Seeks a playing file, or all of them if `uuid` is None, by `offset` milliseconds.
+// Returns false if nothing could be seeked.
+pub fn seek(uuid: Option<Uuid>, offset: i32) -> bool {
+	let sample_rate = { acquire().sample_rate };
+	let samples = ms_to_frames(offset.unsigned_abs(), sample_rate) as i64 * 2 * offset.signum() as i64;
+	let mut seeked = false;
+	for (id, playable) in acquire_playing_files().iter_mut() {
+		if uuid.is_none_or(|uuid| uuid == *id) {
+			seeked |= playable.seek(samples);
+		}
+	}
+	seeked
+}
+
 pub fn read_file_ffmpeg(path: &str, sample_rate: u32) -> Result<Vec<f32>, Error> {
 	let result = Command::new("ffmpeg").args([
 		"-loglevel", "-8",
@@ -143,22 +879,27 @@ pub fn read_file_ffmpeg(path: &str, sample_rate: u32) -> Result<Vec<f32>, Error>
 	Ok(buf.chunks(4).map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap())).collect())
 }
 
-pub fn audio_cache_invalidator() {
-	thread::spawn(move || {
-		while is_running() {
-			let mut cache = AUDIO_CACHE.lock().unwrap();
-			let removable = cache.par_iter().filter_map(|(key, (_data, last_accessed))| {
-				if SystemTime::now().duration_since(*last_accessed).unwrap().as_secs() > 60 {
-					Some(key.clone())
-				} else {
-					None
-				}
-			}).collect::<Vec<_>>();
-			for key in removable {
-				cache.remove(&key);
-			}
-			drop(cache);
-			thread::sleep(Duration::from_mins(1));
-		}
-	});
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::util::audio::tests::lock_mixer;
+
+	#[test]
+	fn loading_voices_count_for_retriggers() {
+		let _lock = lock_mixer();
+		let path = "/loading/voice.wav";
+		let first = reserve_if_idle(path);
+		assert!(first.is_some());
+		// A trigger right after sees the first one loading
+		assert!(reserve_if_idle(path).is_none());
+		drop(first);
+		assert!(reserve_if_idle(path).is_some());
+		assert_eq!(loading_voices(path), 0);
+
+		let voices = (0..3).map(|_| stop_oldest(path, 3, 48000)).collect::<Vec<_>>();
+		assert!(voices.iter().all(Option::is_some));
+		assert!(stop_oldest(path, 3, 48000).is_none());
+		drop(voices);
+		assert_eq!(loading_voices(path), 0);
+	}
+}
@@ -0,0 +1,214 @@
+use std::{collections::VecDeque, io::Read, num::NonZero, path::Path, process::{Child, Command, Stdio}, str::FromStr, sync::{Arc, LazyLock, Mutex, MutexGuard}, thread, time::Duration};
+
+use cmd_exists::cmd_exists;
+use cpal::{DeviceId, FromSample, Sample, SampleFormat, Stream, SupportedStreamConfigRange, traits::{DeviceTrait, HostTrait, StreamTrait}};
+use symphonium::{ResampleQuality, SymphoniumLoader};
+
+use crate::{component::block::log, constant::ENDIANESS, state::{acquire, is_running}, util::{channels::Downmix, fade::ms_to_frames}};
+
+// Most captured audio kept waiting for the mixer. Older samples are dropped so the mic does not lag.
+const MAX_QUEUE_MS: u32 = 100;
+
+// Audio captured into the output bus, as interleaved stereo at the output sample rate
+pub trait InputSource: Send {
+	// Fills `buf`, with silence for whatever is not captured yet
+	fn read_into(&mut self, buf: &mut [f32]);
+}
+
+// Filled by a capture thread or callback
+struct QueuedInput {
+	queue: Arc<Mutex<VecDeque<f32>>>,
+}
+
+impl InputSource for QueuedInput {
+	fn read_into(&mut self, buf: &mut [f32]) {
+		let mut queue = self.queue.lock().unwrap();
+		let len = buf.len().min(queue.len());
+		for (out, sample) in buf.iter_mut().zip(queue.drain(..len)) {
+			*out = sample;
+		}
+		buf[len..].fill(0.0);
+	}
+}
+
+// Loops a decoded file, paced by the mixer. Useful without any capture device.
+pub struct WavInput {
+	data: Vec<f32>,
+	position: usize,
+}
+
+impl WavInput {
+	pub fn new(path: &str, sample_rate: u32) -> Result<Self, String> {
+		let audio_data = SymphoniumLoader::new().load_f32(path, NonZero::new(sample_rate), ResampleQuality::Low, None).map_err(|err| format!("{:?}", err))?;
+		let data = if audio_data.channels() == 2 {
+			audio_data.as_interleaved()
+		} else {
+			let planar = audio_data.data.iter().map(|channel| channel.as_slice()).collect::<Vec<_>>();
+			Downmix::new(None, audio_data.channels()).interleave(&planar)
+		};
+		Ok(Self { data, position: 0 })
+	}
+}
+
+impl InputSource for WavInput {
+	fn read_into(&mut self, buf: &mut [f32]) {
+		if self.data.is_empty() {
+			buf.fill(0.0);
+			return;
+		}
+		for sample in buf.iter_mut() {
+			*sample = self.data[self.position];
+			self.position = (self.position + 1) % self.data.len();
+		}
+	}
+}
+
+// Keeps the capture alive. Dropping it stops capturing.
+enum Capture {
+	Parec(Child),
+	Cpal(Stream),
+}
+
+static INPUT: LazyLock<Mutex<Option<Box<dyn InputSource>>>> = LazyLock::new(|| { Mutex::new(None) });
+
+pub fn acquire_input() -> MutexGuard<'static, Option<Box<dyn InputSource>>> {
+	INPUT.lock().unwrap()
+}
+
+// Keeps the mic input in line with the config.
+// The target is a WAV file, a cpal device ID, "default" for the default device, or a source name for parec.
+pub fn create_input() {
+	thread::spawn(move || {
+		let mut opened = (String::new(), 0);
+		let mut capture: Option<Capture> = None;
+		while is_running() {
+			let (target, sample_rate, no_pacat, latency) = {
+				let app = acquire();
+				(app.config.mic.trim().to_string(), app.sample_rate, app.no_pacat, app.config.latency)
+			};
+			if opened.0 != target || opened.1 != sample_rate {
+				*acquire_input() = None;
+				if let Some(capture) = capture.take() {
+					close_capture(capture);
+				}
+				if !target.is_empty() {
+					match open_input(&target, sample_rate, no_pacat, latency) {
+						Ok((source, new_capture)) => {
+							log::info(format!("Capturing from {}", target).as_str());
+							*acquire_input() = Some(source);
+							capture = new_capture;
+						},
+						Err(err) => log::error(format!("Failed to open input {}: {}", target, err).as_str())
+					}
+				}
+				opened = (target, sample_rate);
+			}
+			if let Some(Capture::Parec(child)) = capture.as_mut() && !matches!(child.try_wait(), Ok(None)) {
+				log::error("parec exited");
+				*acquire_input() = None;
+				capture = None;
+			}
+			thread::sleep(Duration::from_millis(500));
+		}
+		if let Some(capture) = capture {
+			close_capture(capture);
+		}
+	});
+}
+
+fn open_input(target: &str, sample_rate: u32, no_pacat: bool, latency: u32) -> Result<(Box<dyn InputSource>, Option<Capture>), String> {
+	if Path::new(target).is_file() {
+		return Ok((Box::new(WavInput::new(target, sample_rate)?), None));
+	}
+	let queue = Arc::new(Mutex::new(VecDeque::new()));
+	let source = Box::new(QueuedInput { queue: queue.clone() });
+	let device_id = DeviceId::from_str(target).ok();
+	if device_id.is_none() && target != "default" && !no_pacat && cmd_exists("parec").is_ok() {
+		return Ok((source, Some(open_parec(target, sample_rate, latency, queue)?)));
+	}
+	let device = match device_id {
+		Some(device_id) => cpal::host_from_id(device_id.0).map_err(|err| err.to_string())?.device_by_id(&device_id),
+		None => cpal::default_host().default_input_device(),
+	}.ok_or("Device not found")?;
+	let configs = device.supported_input_configs().map_err(|err| err.to_string())?.collect::<Vec<_>>();
+	// Capture is not resampled, so the device has to run at the output rate
+	let config = configs.iter()
+		.filter(|config| config.min_sample_rate() <= sample_rate && sample_rate <= config.max_sample_rate())
+		.min_by_key(|config| (config.sample_format() != SampleFormat::F32, config.channels().abs_diff(2)))
+		.copied()
+		.ok_or(format!("{} Hz is not supported", sample_rate))?;
+	let stream = build_input_stream(&device, config, sample_rate, queue)?;
+	stream.play().map_err(|err| err.to_string())?;
+	Ok((source, Some(Capture::Cpal(stream))))
+}
+
+fn build_input_stream(device: &cpal::Device, config: SupportedStreamConfigRange, sample_rate: u32, queue: Arc<Mutex<VecDeque<f32>>>) -> Result<Stream, String> {
+	let channels = config.channels() as usize;
+	let sample_format = config.sample_format();
+	let config = config.with_sample_rate(sample_rate).into();
+	let err_callback = |err| {
+		log::error(format!("{:?}", err).as_str());
+	};
+	match sample_format {
+		SampleFormat::F32 => device.build_input_stream(&config, move |data: &[f32], _| capture_callback(data, channels, sample_rate, &queue), err_callback, None),
+		SampleFormat::I16 => device.build_input_stream(&config, move |data: &[i16], _| capture_callback(data, channels, sample_rate, &queue), err_callback, None),
+		SampleFormat::U16 => device.build_input_stream(&config, move |data: &[u16], _| capture_callback(data, channels, sample_rate, &queue), err_callback, None),
+		format => return Err(format!("Unsupported sample format {}", format))
+	}.map_err(|err| err.to_string())
+}
+
+fn capture_callback<T: Sample + Send>(data: &[T], channels: usize, sample_rate: u32, queue: &Mutex<VecDeque<f32>>) where f32: FromSample<T> {
+	let samples = data.iter().map(|sample| f32::from_sample(*sample)).collect::<Vec<_>>();
+	push_frames(queue, &samples, channels, sample_rate);
+}
+
+fn open_parec(source: &str, sample_rate: u32, latency: u32, queue: Arc<Mutex<VecDeque<f32>>>) -> Result<Capture, String> {
+	let mut child = Command::new("parec").args([
+		"-d",
+		source,
+		"--channels=2",
+		format!("--rate={}", sample_rate).as_str(),
+		format!("--format=float32{}", ENDIANESS).as_str(),
+		format!("--latency-msec={}", latency).as_str()
+	])
+		.stdout(Stdio::piped())
+		.spawn().map_err(|err| err.to_string())?;
+	let mut stdout = child.stdout.take().unwrap();
+	thread::spawn(move || {
+		// Half the latency at a time, like the mixer chunks
+		let mut bytes = vec![0_u8; ms_to_frames(latency, sample_rate).div_ceil(2).max(1) * 2 * 4];
+		// Ends once the process is killed
+		while stdout.read_exact(&mut bytes).is_ok() {
+			let samples = bytes.chunks_exact(4).map(|chunk| f32::from_ne_bytes(chunk.try_into().unwrap())).collect::<Vec<_>>();
+			push_frames(&queue, &samples, 2, sample_rate);
+		}
+	});
+	Ok(Capture::Parec(child))
+}
+
+// Queues captured frames as stereo. Mono is copied to both sides, and extra channels are dropped.
+fn push_frames(queue: &Mutex<VecDeque<f32>>, samples: &[f32], channels: usize, sample_rate: u32) {
+	let mut queue = queue.lock().unwrap();
+	for frame in samples.chunks_exact(channels) {
+		match frame {
+			[mono] => queue.extend([*mono, *mono]),
+			[left, right, ..] => queue.extend([*left, *right]),
+			[] => (),
+		}
+	}
+	let max = ms_to_frames(MAX_QUEUE_MS, sample_rate) * 2;
+	if queue.len() > max {
+		let excess = queue.len() - max;
+		queue.drain(..excess);
+	}
+}
+
+fn close_capture(capture: Capture) {
+	match capture {
+		Capture::Parec(mut child) => {
+			child.kill().ok();
+			child.wait().ok();
+		},
+		Capture::Cpal(stream) => drop(stream),
+	}
+}
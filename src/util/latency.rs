@@ -0,0 +1,73 @@
+use std::{sync::Mutex, thread, time::{Duration, Instant}};
+
+use crate::{state::acquire, util::{audio::{Mixer, reset_output}, backend::Backend, pulseaudio::{load_null_sink, unload_module}}};
+
+// Time at the start of the test that is left out, while the output fills its buffer
+const WARM_UP_MS: u128 = 500;
+
+// When each chunk was asked from the mixer and how many frames it had, while a latency test runs
+static PROBE: Mutex<Option<Vec<(Instant, usize)>>> = Mutex::new(None);
+
+// Notes a chunk being mixed. Returns whether a latency test is running.
+pub fn probe_chunk(frames: usize) -> bool {
+	let mut probe = PROBE.lock().unwrap();
+	let Some(chunks) = probe.as_mut() else { return false };
+	chunks.push((Instant::now(), frames));
+	true
+}
+
+// Plays silence through `backend` for `seconds`, and reports how far the chunks strayed from when they were due.
+// Runs without another instance. Must not be called with the app locked.
+pub fn latency_test(backend: &Backend, latency: Option<u32>, seconds: f32) -> Result<String, String> {
+	let (sample_rate, channels, latency) = {
+		let app = acquire();
+		(app.config.output_rate, app.config.output_channels, latency.unwrap_or(app.config.latency))
+	};
+	let mut output = backend.create();
+	// pacat plays into the cls sink, which only exists while an instance is running
+	let module = if backend.uses_sink() { load_null_sink(sample_rate, channels) } else { String::new() };
+	let result = output.open(sample_rate, channels, latency).and_then(|(sample_rate, channels)| {
+		let mixer = Mixer::new(sample_rate, channels as usize, latency);
+		let chunk = mixer.chunk_frames();
+		*PROBE.lock().unwrap() = Some(vec![]);
+		let timer = thread::spawn(move || {
+			thread::sleep(Duration::from_secs_f32(seconds.max(0.0)));
+			reset_output();
+		});
+		let result = output.run(mixer);
+		timer.join().ok();
+		let chunks = PROBE.lock().unwrap().take().unwrap_or_default();
+		result.map(|_| report(&chunks, sample_rate, latency, chunk))
+	});
+	unload_module(&module).ok();
+	result
+}
+
+fn report(chunks: &[(Instant, usize)], sample_rate: u32, latency: u32, chunk: usize) -> String {
+	let Some((start, _)) = chunks.first() else { return "No chunks were mixed".to_string() };
+	// Lateness of each chunk against the one before it, in milliseconds
+	let jitter = chunks.windows(2)
+		.filter(|pair| pair[0].0.duration_since(*start).as_millis() >= WARM_UP_MS)
+		.map(|pair| {
+			let due = pair[0].1 as f64 / sample_rate as f64 * 1000.0;
+			pair[1].0.duration_since(pair[0].0).as_secs_f64() * 1000.0 - due
+		})
+		.collect::<Vec<_>>();
+	if jitter.is_empty() {
+		return "Too few chunks were mixed to measure. Try a longer duration".to_string();
+	}
+	let chunk_ms = chunk as f64 / sample_rate as f64 * 1000.0;
+	let mut lateness = jitter.iter().map(|jitter| jitter.abs()).collect::<Vec<_>>();
+	lateness.sort_by(|a, b| a.total_cmp(b));
+	let mean = lateness.iter().sum::<f64>() / lateness.len() as f64;
+	let p99 = lateness[((lateness.len() - 1) as f64 * 0.99) as usize];
+	let max = lateness[lateness.len() - 1];
+	// Late by a whole chunk eats into the latency, and may be heard as a gap
+	let late = jitter.iter().filter(|jitter| **jitter > chunk_ms).count();
+	[
+		format!("Latency: {} ms, chunk: {} frames ({:.2} ms) at {} Hz", latency, chunk, chunk_ms, sample_rate),
+		format!("Chunks measured: {}", jitter.len()),
+		format!("Jitter: mean {:.3} ms, p99 {:.3} ms, max {:.3} ms", mean, p99, max),
+		format!("Chunks late by more than a chunk: {}", late),
+	].join("\n")
+}
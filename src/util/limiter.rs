@@ -0,0 +1,156 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use crate::util::fade::ms_to_frames;
+
+// How far ahead the limiter sees peaks coming
+const LOOKAHEAD_MS: u32 = 5;
+// Time for the gain to recover after a peak
+const RELEASE_MS: f32 = 100.0;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum LimiterMode {
+	Off,
+	#[default]
+	Limiter,
+	// Saturates smoothly instead of reducing gain
+	SoftClip
+}
+
+impl LimiterMode {
+	pub fn next(&self) -> Self {
+		use LimiterMode::*;
+		match self {
+			Off => Limiter,
+			Limiter => SoftClip,
+			SoftClip => Off,
+		}
+	}
+
+	pub fn label(&self) -> &'static str {
+		use LimiterMode::*;
+		match self {
+			Off => "Off",
+			Limiter => "Limiter",
+			SoftClip => "Soft Clip",
+		}
+	}
+}
+
+// Master bus stage that keeps interleaved stereo output under a ceiling.
+// The limiter delays the output by the look-ahead so the gain is already down when a peak arrives.
+pub struct Limiter {
+	delay: VecDeque<[f32; 2]>,
+	// Gain each delayed frame needs to stay under the ceiling
+	required: VecDeque<f32>,
+	lookahead: usize,
+	gain: f32,
+	release: f32,
+}
+
+impl Limiter {
+	pub fn new(sample_rate: u32) -> Self {
+		let lookahead = ms_to_frames(LOOKAHEAD_MS, sample_rate).max(1);
+		Self {
+			delay: VecDeque::with_capacity(lookahead + 1),
+			required: VecDeque::with_capacity(lookahead + 1),
+			lookahead,
+			gain: 1.0,
+			release: 1.0 - (-1.0 / (RELEASE_MS / 1000.0 * sample_rate as f32)).exp(),
+		}
+	}
+
+	// Frames the output is delayed by
+	pub fn lookahead(&self) -> usize {
+		self.lookahead
+	}
+
+	// `ceiling` is in dBFS
+	pub fn process(&mut self, buf: &mut [f32], mode: LimiterMode, ceiling: f32) {
+		let ceiling = 10_f32.powf(ceiling.min(0.0) / 20.0);
+		match mode {
+			LimiterMode::Off => self.reset(),
+			LimiterMode::Limiter => self.limit(buf, ceiling),
+			LimiterMode::SoftClip => {
+				self.reset();
+				for sample in buf.iter_mut() {
+					*sample = ceiling * (*sample / ceiling).tanh();
+				}
+			},
+		}
+	}
+
+	fn limit(&mut self, buf: &mut [f32], ceiling: f32) {
+		for frame in buf.chunks_exact_mut(2) {
+			let peak = frame[0].abs().max(frame[1].abs());
+			self.delay.push_back([frame[0], frame[1]]);
+			self.required.push_back(if peak > ceiling { ceiling / peak } else { 1.0 });
+			let ([left, right], required) = if self.delay.len() > self.lookahead {
+				(self.delay.pop_front().unwrap(), self.required.pop_front().unwrap())
+			} else {
+				([0.0, 0.0], 1.0)
+			};
+
+			let target = self.required.iter().copied().fold(required, f32::min);
+			if target < self.gain {
+				self.gain = target;
+			} else {
+				self.gain += (target - self.gain) * self.release;
+			}
+			frame[0] = left * self.gain;
+			frame[1] = right * self.gain;
+		}
+	}
+
+	// Drops the delayed audio, for when the output goes idle or the limiter is bypassed
+	pub fn reset(&mut self) {
+		self.delay.clear();
+		self.required.clear();
+		self.gain = 1.0;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// Loud noise with sudden peaks, the same on every run
+	fn noise(frames: usize) -> Vec<f32> {
+		let mut state = 1_u32;
+		(0..frames * 2).map(|index| {
+			state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+			let sample = (state >> 8) as f32 / (1 << 23) as f32 * 2.0 - 1.0;
+			if index % 997 == 0 { sample * 8.0 } else { sample * 3.0 }
+		}).collect()
+	}
+
+	#[test]
+	fn ceiling_is_never_exceeded() {
+		for mode in [LimiterMode::Limiter, LimiterMode::SoftClip] {
+			for ceiling_db in [0.0, -1.0, -6.0] {
+				let ceiling = 10_f32.powf(ceiling_db / 20.0);
+				let mut limiter = Limiter::new(48000);
+				let mut buf = noise(48000);
+				// Chunks of different sizes, like the outputs mix
+				for chunk in buf.chunks_mut(480) {
+					limiter.process(chunk, mode, ceiling_db);
+				}
+				let peak = buf.iter().fold(0.0_f32, |peak, sample| peak.max(sample.abs()));
+				assert!(peak <= ceiling * (1.0 + 1e-6), "{:?} at {} dB peaked at {}", mode, ceiling_db, peak);
+			}
+		}
+	}
+
+	#[test]
+	fn quiet_audio_is_only_delayed() {
+		let mut limiter = Limiter::new(48000);
+		let input = (0..2000).map(|index| (index as f32 * 0.01).sin() * 0.5).collect::<Vec<_>>();
+		let mut buf = input.clone();
+		limiter.process(&mut buf, LimiterMode::Limiter, 0.0);
+		let delay = limiter.lookahead * 2;
+		assert!(buf[..delay].iter().all(|sample| *sample == 0.0));
+		assert_eq!(buf[delay..], input[..input.len() - delay]);
+	}
+}
@@ -0,0 +1,232 @@
+use std::{collections::HashMap, f64::consts::PI, path::PathBuf, sync::{LazyLock, Mutex}, time::UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use symphonium::SymphoniumLoader;
+
+use crate::{component::block::log, constant::APP_NAME, util::stream::{decode_ffmpeg, decode_symphonia}};
+
+// Gating blocks are 400 ms long with 75% overlap, so they are built from 100 ms steps
+const STEP_MS: usize = 100;
+const STEPS_PER_BLOCK: usize = 4;
+const ABSOLUTE_GATE: f64 = -70.0;
+const RELATIVE_GATE: f64 = -10.0;
+// Avoid blowing up near-silent clips
+const MAX_GAIN_DB: f32 = 20.0;
+
+#[derive(Clone, Copy, Default)]
+struct Biquad {
+	b: [f64; 3],
+	a: [f64; 2],
+	z: [f64; 2],
+}
+
+impl Biquad {
+	fn process(&mut self, input: f64) -> f64 {
+		let output = self.b[0] * input + self.z[0];
+		self.z[0] = self.b[1] * input - self.a[0] * output + self.z[1];
+		self.z[1] = self.b[2] * input - self.a[1] * output;
+		output
+	}
+}
+
+// K-weighting filter from ITU-R BS.1770, with coefficients derived for any sample rate
+fn k_weighting(sample_rate: u32) -> [Biquad; 2] {
+	let rate = sample_rate as f64;
+
+	// High shelf
+	let k = (PI * 1681.974450955533 / rate).tan();
+	let q = 0.7071752369554196;
+	let vh = 10_f64.powf(3.999843853973347 / 20.0);
+	let vb = vh.powf(0.4996667741545416);
+	let a0 = 1.0 + k / q + k * k;
+	let shelf = Biquad {
+		b: [(vh + vb * k / q + k * k) / a0, 2.0 * (k * k - vh) / a0, (vh - vb * k / q + k * k) / a0],
+		a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+		z: [0.0; 2],
+	};
+
+	// High pass
+	let k = (PI * 38.13547087602444 / rate).tan();
+	let q = 0.5003270373238773;
+	let a0 = 1.0 + k / q + k * k;
+	let high_pass = Biquad {
+		b: [1.0, -2.0, 1.0],
+		a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+		z: [0.0; 2],
+	};
+
+	[shelf, high_pass]
+}
+
+// Measures integrated loudness (EBU R128) of interleaved stereo samples
+pub struct LoudnessMeter {
+	filters: [[Biquad; 2]; 2],
+	step_frames: usize,
+	frames: usize,
+	energy: f64,
+	steps: Vec<f64>,
+}
+
+impl LoudnessMeter {
+	pub fn new(sample_rate: u32) -> Self {
+		let filters = k_weighting(sample_rate);
+		Self {
+			filters: [filters, filters],
+			step_frames: (sample_rate as usize * STEP_MS / 1000).max(1),
+			frames: 0,
+			energy: 0.0,
+			steps: vec![],
+		}
+	}
+
+	pub fn process(&mut self, samples: &[f32]) {
+		for frame in samples.chunks_exact(2) {
+			for (channel, sample) in frame.iter().enumerate() {
+				let [shelf, high_pass] = &mut self.filters[channel];
+				let weighted = high_pass.process(shelf.process(*sample as f64));
+				self.energy += weighted * weighted;
+			}
+			self.frames += 1;
+			if self.frames == self.step_frames {
+				self.steps.push(self.energy / self.step_frames as f64);
+				self.frames = 0;
+				self.energy = 0.0;
+			}
+		}
+	}
+
+	// Returns None if the audio is too short or silent
+	pub fn integrated(&self) -> Option<f32> {
+		let blocks = self.steps.windows(STEPS_PER_BLOCK)
+			.map(|steps| steps.iter().sum::<f64>() / STEPS_PER_BLOCK as f64)
+			.filter(|power| block_loudness(*power) > ABSOLUTE_GATE)
+			.collect::<Vec<_>>();
+		if blocks.is_empty() {
+			return None;
+		}
+		let threshold = block_loudness(blocks.iter().sum::<f64>() / blocks.len() as f64) + RELATIVE_GATE;
+		let gated = blocks.iter().filter(|power| block_loudness(**power) > threshold).collect::<Vec<_>>();
+		if gated.is_empty() {
+			return None;
+		}
+		Some(block_loudness(gated.iter().copied().sum::<f64>() / gated.len() as f64) as f32)
+	}
+}
+
+fn block_loudness(power: f64) -> f64 {
+	-0.691 + 10.0 * power.log10()
+}
+
+// Linear gain that brings a clip measured at `loudness` to `target` (both in LUFS)
+pub fn normalize_gain(loudness: f32, target: f32) -> f32 {
+	10_f32.powf((target - loudness).min(MAX_GAIN_DB) / 20.0)
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+struct LoudnessRecord {
+	modified: u64,
+	size: u64,
+	loudness: f32,
+}
+
+static LOUDNESS_CACHE: LazyLock<Mutex<HashMap<String, LoudnessRecord>>> = LazyLock::new(|| {
+	let cache = cache_path()
+		.and_then(|path| std::fs::read_to_string(path).ok())
+		.and_then(|str| serde_json::from_str(&str).ok())
+		.unwrap_or_default();
+	Mutex::new(cache)
+});
+
+fn cache_path() -> Option<PathBuf> {
+	dirs::cache_dir().map(|dir| dir.join(APP_NAME).join("loudness.json"))
+}
+
+// Modified time and size, to notice when a file is replaced
+fn file_stamp(path: &str) -> Option<(u64, u64)> {
+	let meta = std::fs::metadata(path).ok()?;
+	let modified = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?.as_secs();
+	Some((modified, meta.len()))
+}
+
+pub fn cached_loudness(path: &str) -> Option<f32> {
+	let (modified, size) = file_stamp(path)?;
+	let cache = LOUDNESS_CACHE.lock().unwrap();
+	cache.get(path)
+		.filter(|record| record.modified == modified && record.size == size)
+		.map(|record| record.loudness)
+}
+
+// Returns true if the loudness was newly measured
+pub fn measure_loudness(path: &str, loader: &SymphoniumLoader, sample_rate: u32) -> bool {
+	if cached_loudness(path).is_some() {
+		return false;
+	}
+	let Some((modified, size)) = file_stamp(path) else { return false };
+
+	let mut meter = None;
+	if let Ok(mut probed) = loader.probe_from_file(path) {
+		let source_rate = probed.sample_rate().map_or(sample_rate, |rate| rate.get());
+		let mut symphonia_meter = LoudnessMeter::new(source_rate);
+		if decode_symphonia(&mut probed, None, |samples| { symphonia_meter.process(samples); true }).is_ok() {
+			meter = Some(symphonia_meter);
+		}
+	}
+	if meter.is_none() {
+		let mut ffmpeg_meter = LoudnessMeter::new(sample_rate);
+		if decode_ffmpeg(path, sample_rate, |samples| { ffmpeg_meter.process(samples); true }).is_ok() {
+			meter = Some(ffmpeg_meter);
+		}
+	}
+	let Some(loudness) = meter.and_then(|meter| meter.integrated()) else {
+		log::warn(format!("Could not measure loudness of {}", path).as_str());
+		return false;
+	};
+	LOUDNESS_CACHE.lock().unwrap().insert(path.to_string(), LoudnessRecord { modified, size, loudness });
+	true
+}
+
+pub fn save_loudness_cache() {
+	let Some(path) = cache_path() else { return };
+	path.parent().inspect(|parent| {
+		let _ = std::fs::create_dir_all(parent);
+	});
+	let serialized = serde_json::to_string(&*LOUDNESS_CACHE.lock().unwrap()).expect("Failed to serialize loudness cache");
+	if std::fs::write(path, serialized).is_err() {
+		log::error("Failed to save loudness cache");
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn gain_is_capped() {
+		assert!((normalize_gain(-80.0, -14.0) - 10.0).abs() < 1e-4);
+		assert!((normalize_gain(-34.0, -14.0) - 10.0).abs() < 1e-4);
+		// Turning down is not limited
+		assert!((normalize_gain(0.0, -40.0) - 0.01).abs() < 1e-6);
+		assert!((normalize_gain(-14.0, -14.0) - 1.0).abs() < 1e-6);
+	}
+
+	#[test]
+	fn full_scale_sine_is_zero_lufs() {
+		// BS.1770 calibration: a 997 Hz sine at 0 dBFS in both channels reads 0 LUFS
+		let rate = 48000;
+		let samples = (0..rate * 5).flat_map(|frame| {
+			let sample = (2.0 * PI * 997.0 * frame as f64 / rate as f64).sin() as f32;
+			[sample, sample]
+		}).collect::<Vec<_>>();
+		let mut meter = LoudnessMeter::new(rate as u32);
+		meter.process(&samples);
+		let loudness = meter.integrated().unwrap();
+		assert!(loudness.abs() < 0.1, "measured {} LUFS", loudness);
+	}
+
+	#[test]
+	fn silence_has_no_loudness() {
+		let mut meter = LoudnessMeter::new(48000);
+		meter.process(&vec![0.0; 48000 * 2 * 2]);
+		assert_eq!(meter.integrated(), None);
+	}
+}
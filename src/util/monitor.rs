@@ -0,0 +1,159 @@
+use std::{collections::VecDeque, io::{self, Write}, process::Child, str::FromStr, sync::{LazyLock, Mutex, atomic::{AtomicBool, Ordering}}, thread, time::Duration};
+
+use cmd_exists::cmd_exists;
+use cpal::{DeviceId, FromSample, Sample, SampleFormat, Stream, traits::{DeviceTrait, HostTrait, StreamTrait}};
+
+use crate::{component::block::log, state::{acquire, is_running}, util::{audio::{Pacat, output_config, spawn_pacat}, channels::{OutputChannels, to_output}, fade::ms_to_frames}};
+
+// Most audio kept waiting for the monitor. Older samples are dropped so it does not fall behind.
+const MAX_QUEUE_MS: u32 = 100;
+const PACAT_CHUNK: usize = 1024;
+
+// Mixed audio waiting to be played on the monitor, as interleaved stereo
+static QUEUE: LazyLock<Mutex<VecDeque<f32>>> = LazyLock::new(|| { Mutex::new(VecDeque::new()) });
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+enum MonitorOutput {
+	Pacat(Child),
+	Cpal(Stream),
+}
+
+// Queues a copy of the master mix for the monitor
+pub fn feed_monitor(stereo: &[f32], volume: f32, sample_rate: u32) {
+	if !ACTIVE.load(Ordering::Relaxed) {
+		return;
+	}
+	let mut queue = QUEUE.lock().unwrap();
+	queue.extend(stereo.iter().map(|sample| sample * volume));
+	let max = ms_to_frames(MAX_QUEUE_MS, sample_rate) * 2;
+	if queue.len() > max {
+		let excess = queue.len() - max;
+		queue.drain(..excess);
+	}
+}
+
+// Fills `buf` from the queue, with silence for whatever is missing
+fn take_monitor(buf: &mut [f32]) {
+	let mut queue = QUEUE.lock().unwrap();
+	let len = buf.len().min(queue.len());
+	for (out, sample) in buf.iter_mut().zip(queue.drain(..len)) {
+		*out = sample;
+	}
+	buf[len..].fill(0.0);
+}
+
+// Keeps the monitor output in line with the config.
+// The target is a cpal device ID, "default" for the default device, or a sink name for pacat.
+pub fn create_monitor() {
+	thread::spawn(move || {
+		let mut opened = (String::new(), 0);
+		let mut output: Option<MonitorOutput> = None;
+		while is_running() {
+			let (target, sample_rate, no_pacat, latency) = {
+				let app = acquire();
+				(app.config.monitor.trim().to_string(), app.sample_rate, app.no_pacat, app.config.latency)
+			};
+			if opened.0 != target || opened.1 != sample_rate {
+				ACTIVE.store(false, Ordering::Relaxed);
+				if let Some(output) = output.take() {
+					close_monitor(output);
+				}
+				QUEUE.lock().unwrap().clear();
+				if !target.is_empty() {
+					match open_monitor(&target, sample_rate, no_pacat, latency) {
+						Ok(new_output) => {
+							log::info(format!("Monitoring on {}", target).as_str());
+							output = Some(new_output);
+							ACTIVE.store(true, Ordering::Relaxed);
+						},
+						Err(err) => log::error(format!("Failed to open monitor {}: {}", target, err).as_str())
+					}
+				}
+				opened = (target, sample_rate);
+			}
+			if let Some(MonitorOutput::Pacat(child)) = output.as_mut() && !matches!(child.try_wait(), Ok(None)) {
+				log::error("Monitor pacat exited");
+				ACTIVE.store(false, Ordering::Relaxed);
+				output = None;
+			}
+			thread::sleep(Duration::from_millis(500));
+		}
+		if let Some(output) = output {
+			close_monitor(output);
+		}
+	});
+}
+
+fn open_monitor(target: &str, sample_rate: u32, no_pacat: bool, latency: u32) -> Result<MonitorOutput, String> {
+	let device_id = DeviceId::from_str(target).ok();
+	if device_id.is_none() && target != "default" && !no_pacat && cmd_exists("pacat").is_ok() {
+		return open_pacat(target, sample_rate, latency).map_err(|err| err.to_string());
+	}
+	let device = match device_id {
+		Some(device_id) => cpal::host_from_id(device_id.0).map_err(|err| err.to_string())?.device_by_id(&device_id),
+		None => cpal::default_host().default_output_device(),
+	}.ok_or("Device not found")?;
+	let config = output_config(&device, sample_rate, 2).ok_or("No output config")?;
+	if sample_rate < config.min_sample_rate() || sample_rate > config.max_sample_rate() {
+		return Err(format!("{} Hz is not supported", sample_rate));
+	}
+	let channels = config.channels() as usize;
+	let sample_format = config.sample_format();
+	let config = config.with_sample_rate(sample_rate).into();
+	let err_callback = |err| {
+		log::error(format!("{:?}", err).as_str());
+	};
+	let mut scratch = Scratch::default();
+	let stream = match sample_format {
+		SampleFormat::F32 => device.build_output_stream(&config, move |data: &mut [f32], _| monitor_callback(data, channels, &mut scratch), err_callback, None),
+		SampleFormat::I16 => device.build_output_stream(&config, move |data: &mut [i16], _| monitor_callback(data, channels, &mut scratch), err_callback, None),
+		SampleFormat::U16 => device.build_output_stream(&config, move |data: &mut [u16], _| monitor_callback(data, channels, &mut scratch), err_callback, None),
+		format => return Err(format!("Unsupported sample format {}", format))
+	}.map_err(|err| err.to_string())?;
+	stream.play().map_err(|err| err.to_string())?;
+	Ok(MonitorOutput::Cpal(stream))
+}
+
+// pacat paces the writer, which plays silence while nothing is queued
+fn open_pacat(sink: &str, sample_rate: u32, latency: u32) -> io::Result<MonitorOutput> {
+	let Pacat { child, mut writer, .. } = spawn_pacat(sink, sample_rate, OutputChannels::Stereo, latency)?;
+	thread::spawn(move || {
+		let mut buf = [0_f32; PACAT_CHUNK];
+		loop {
+			take_monitor(&mut buf);
+			// Fails once the process is killed
+			if writer.write_all(bytemuck::cast_slice(&buf)).and_then(|_| writer.flush()).is_err() {
+				break;
+			}
+		}
+	});
+	Ok(MonitorOutput::Pacat(child))
+}
+
+fn close_monitor(output: MonitorOutput) {
+	match output {
+		MonitorOutput::Pacat(mut child) => {
+			child.kill().ok();
+			child.wait().ok();
+		},
+		MonitorOutput::Cpal(stream) => drop(stream),
+	}
+}
+
+// Stereo and output buffers owned by the callback, so it only allocates when the device asks for more
+#[derive(Default)]
+struct Scratch {
+	stereo: Vec<f32>,
+	output: Vec<f32>,
+}
+
+fn monitor_callback<T: Sample + FromSample<f32> + Send>(data: &mut [T], channels: usize, scratch: &mut Scratch) {
+	let frames = data.len() / channels;
+	scratch.stereo.resize(frames * 2, 0.0);
+	scratch.output.resize(frames * channels, 0.0);
+	take_monitor(&mut scratch.stereo);
+	to_output(&scratch.stereo, &mut scratch.output, channels);
+	for (out, sample) in data.iter_mut().zip(scratch.output.iter()) {
+		*out = T::from_sample(*sample);
+	}
+}
@@ -0,0 +1,55 @@
+use std::f32::consts::{FRAC_PI_4, SQRT_2};
+
+// Left and right gains for a mono source panned from -100 (left) to 100 (right).
+// Equal-power law raised by 3 dB, so the center is unchanged, and capped so neither side is boosted.
+pub fn pan_gains(pan: i32) -> (f32, f32) {
+	let angle = (pan.clamp(-100, 100) as f32 / 100.0 + 1.0) * FRAC_PI_4;
+	((angle.cos() * SQRT_2).clamp(0.0, 1.0), (angle.sin() * SQRT_2).clamp(0.0, 1.0))
+}
+
+// Left and right gains for a stereo source. Only the opposite side is turned down, so no channel moves over.
+pub fn balance_gains(pan: i32) -> (f32, f32) {
+	let pan = pan.clamp(-100, 100) as f32 / 100.0;
+	(1.0 - pan.max(0.0), 1.0 + pan.min(0.0))
+}
+
+pub fn pan_label(pan: i32) -> String {
+	match pan {
+		0 => "Center".to_string(),
+		..0 => format!("L {}", -pan),
+		_ => format!("R {}", pan),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn assert_gains(gains: fn(i32) -> (f32, f32), pan: i32, expected: (f32, f32)) {
+		let (left, right) = gains(pan);
+		assert!((left - expected.0).abs() < 1e-6 && (right - expected.1).abs() < 1e-6, "pan {} gave ({}, {})", pan, left, right);
+	}
+
+	#[test]
+	fn center_and_hard_pans() {
+		for gains in [pan_gains, balance_gains] {
+			assert_gains(gains, 0, (1.0, 1.0));
+			assert_gains(gains, -100, (1.0, 0.0));
+			assert_gains(gains, 100, (0.0, 1.0));
+			// Out of range pans stay hard
+			assert_gains(gains, -150, (1.0, 0.0));
+		}
+		assert_gains(balance_gains, 50, (0.5, 1.0));
+	}
+
+	#[test]
+	fn gains_never_boost() {
+		for pan in -100..=100 {
+			for (left, right) in [pan_gains(pan), balance_gains(pan)] {
+				assert!((0.0..=1.0).contains(&left) && (0.0..=1.0).contains(&right));
+				// The side panned towards stays at full level
+				assert!((if pan < 0 { left } else { right } - 1.0).abs() < 1e-6);
+			}
+		}
+	}
+}
@@ -1,14 +1,17 @@
 use std::process::Command;
 
-use crate::{component::block::log, constant::APP_NAME};
+use crate::{component::block::log, constant::APP_NAME, util::channels::OutputChannels};
 
-pub fn load_null_sink() -> String {
+pub fn load_null_sink(rate: u32, channels: OutputChannels) -> String {
 	let appname = APP_NAME;
 	let result = Command::new("pactl").args([
 		"load-module",
 		"module-null-sink",
 		format!("sink_name={appname}").as_str(),
-		"formats=s32le"
+		"formats=s32le",
+		format!("rate={rate}").as_str(),
+		format!("channels={}", channels.count()).as_str(),
+		format!("channel_map={}", channels.channel_map()).as_str(),
 	]).output();
 
 	if result.is_err() {
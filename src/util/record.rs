@@ -0,0 +1,216 @@
+use std::{fs::File, io::{self, BufWriter, Error, Write}, path::Path, process::{Child, ChildStdin, Command, Stdio}, sync::{LazyLock, Mutex, atomic::{AtomicBool, Ordering}, mpsc::{Receiver, SyncSender, sync_channel}}, thread, time::Instant};
+
+use hound::{SampleFormat, WavSpec, WavWriter};
+
+use crate::{component::block::log, constant::ENDIANESS, state::notify_redraw, util::replay::{feed_replay, is_replay_enabled, reset_replay}};
+
+// Chunks of output that can wait for the writer thread before they are dropped
+const QUEUE_CHUNKS: usize = 256;
+
+enum Encoder {
+	Wav(WavWriter<BufWriter<File>>),
+	// FLAC is encoded by ffmpeg
+	Ffmpeg(Child, BufWriter<ChildStdin>),
+}
+
+// Copy of the final output being written to disk
+struct Recording {
+	path: String,
+	encoder: Encoder,
+	sample_rate: u32,
+	channels: u16,
+}
+
+// What the output sends to the writer thread, in order
+enum Message {
+	// The output started with this sample rate and channel count
+	Start(Instant, u32, u16),
+	// Interleaved output samples
+	Samples(Vec<f32>),
+	// The output is idle and wrote nothing up to this time
+	Idle(Instant),
+	// Answered once everything sent before is written
+	Sync(SyncSender<()>),
+}
+
+// Sender to the writer thread, and the chunks it is done with so the output can reuse them
+struct Writer {
+	sender: SyncSender<Message>,
+	// Only locked by the output thread
+	returned: Mutex<Receiver<Vec<f32>>>,
+}
+
+static RECORDING: LazyLock<Mutex<Option<Recording>>> = LazyLock::new(|| { Mutex::new(None) });
+// Sample rate and channels of the output, which recordings are written in
+static OUTPUT_FORMAT: Mutex<(u32, u16)> = Mutex::new((48000, 2));
+// Recording and the replay buffer are written on their own thread, so the output never waits on the disk
+static WRITER: LazyLock<Writer> = LazyLock::new(|| {
+	let (sender, receiver) = sync_channel(QUEUE_CHUNKS);
+	let (returner, returned) = sync_channel(QUEUE_CHUNKS);
+	thread::spawn(move || write_output(receiver, returner));
+	Writer { sender, returned: Mutex::new(returned) }
+});
+// Set when the writer thread fell behind and output was dropped
+static DROPPED: AtomicBool = AtomicBool::new(false);
+
+// Called when the output starts. A recording in another format is finished, since it cannot change midway.
+pub fn set_output_format(sample_rate: u32, channels: u16) {
+	*OUTPUT_FORMAT.lock().unwrap() = (sample_rate, channels);
+	WRITER.sender.send(Message::Start(Instant::now(), sample_rate, channels)).ok();
+	let changed = RECORDING.lock().unwrap().as_ref().is_some_and(|recording| recording.sample_rate != sample_rate || recording.channels != channels);
+	if changed && let Some(path) = stop_recording() {
+		log::info(format!("Output format changed, so the recording was saved to {}", path).as_str());
+	}
+}
+
+// Starts writing the output to `path`, as FLAC if it ends with .flac and WAV otherwise
+pub fn start_recording(path: &str) -> Result<(), Error> {
+	let mut recording = RECORDING.lock().unwrap();
+	if recording.is_some() {
+		return Err(Error::new(io::ErrorKind::AlreadyExists, "Already recording"));
+	}
+	let (sample_rate, channels) = *OUTPUT_FORMAT.lock().unwrap();
+	let flac = Path::new(path).extension().is_some_and(|ext| ext.eq_ignore_ascii_case("flac"));
+	let encoder = if flac {
+		let mut child = Command::new("ffmpeg").args([
+			"-loglevel", "-8",
+			"-y",
+			"-f", format!("f32{}", ENDIANESS).as_str(),
+			"-ar", sample_rate.to_string().as_str(),
+			"-ac", channels.to_string().as_str(),
+			"-i", "-",
+			path
+		]).stdin(Stdio::piped()).spawn()?;
+		let stdin = child.stdin.take().unwrap();
+		Encoder::Ffmpeg(child, BufWriter::new(stdin))
+	} else {
+		let spec = WavSpec {
+			channels,
+			sample_rate,
+			bits_per_sample: 32,
+			sample_format: SampleFormat::Float,
+		};
+		Encoder::Wav(WavWriter::create(path, spec).map_err(Error::other)?)
+	};
+	*recording = Some(Recording {
+		path: path.to_string(),
+		encoder,
+		sample_rate,
+		channels,
+	});
+	drop(recording);
+	log::info(format!("Recording to {}", path).as_str());
+	notify_redraw();
+	Ok(())
+}
+
+// Finishes the file once the output sent so far is written. Returns its path, or None if nothing was recording.
+pub fn stop_recording() -> Option<String> {
+	sync_output();
+	finish_recording()
+}
+
+// Waits until the writer thread has written everything the output sent
+pub fn sync_output() {
+	let (sender, receiver) = sync_channel(1);
+	if WRITER.sender.send(Message::Sync(sender)).is_ok() {
+		receiver.recv().ok();
+	}
+}
+
+fn finish_recording() -> Option<String> {
+	let recording = RECORDING.lock().unwrap().take()?;
+	let result = match recording.encoder {
+		Encoder::Wav(writer) => writer.finalize().map_err(Error::other),
+		Encoder::Ffmpeg(mut child, mut stdin) => stdin.flush().and_then(|_| {
+			// Closing stdin lets ffmpeg finish the file
+			drop(stdin);
+			child.wait().map(|_| ())
+		}),
+	};
+	if let Err(err) = result {
+		log::error(format!("Failed to finish recording {}: {:?}", recording.path, err).as_str());
+	}
+	notify_redraw();
+	Some(recording.path)
+}
+
+pub fn recording_path() -> Option<String> {
+	RECORDING.lock().unwrap().as_ref().map(|recording| recording.path.clone())
+}
+
+// Hands interleaved output samples, in the output's channel layout, to the writer thread for the recording and the replay buffer.
+// Called from the output thread, so it never blocks and only allocates until enough chunks are going around.
+pub fn record(samples: &[f32]) {
+	let mut chunk = WRITER.returned.lock().unwrap().try_recv().unwrap_or_default();
+	chunk.clear();
+	chunk.extend_from_slice(samples);
+	if WRITER.sender.try_send(Message::Samples(chunk)).is_err() {
+		DROPPED.store(true, Ordering::Relaxed);
+	}
+}
+
+// Pads with silence up to now, for outputs that stop while idle
+pub fn record_silence() {
+	if WRITER.sender.try_send(Message::Idle(Instant::now())).is_err() {
+		DROPPED.store(true, Ordering::Relaxed);
+	}
+}
+
+fn write_output(receiver: Receiver<Message>, returner: SyncSender<Vec<f32>>) {
+	// When the output started, and the frames written since
+	let mut clock = (Instant::now(), 0_u64);
+	let (mut sample_rate, mut channels) = (48000_u32, 2_u16);
+	for message in receiver {
+		match message {
+			Message::Start(start, rate, count) => {
+				clock = (start, 0);
+				(sample_rate, channels) = (rate, count);
+				reset_replay(sample_rate, channels);
+			},
+			Message::Samples(chunk) => {
+				clock.1 += (chunk.len() / channels as usize) as u64;
+				write_samples(&chunk);
+				returner.try_send(chunk).ok();
+			},
+			Message::Idle(now) => {
+				let due = (now.saturating_duration_since(clock.0).as_secs_f64() * sample_rate as f64) as u64;
+				if due <= clock.1 {
+					continue;
+				}
+				let missing = due - clock.1;
+				clock.1 = due;
+				if RECORDING.lock().unwrap().is_some() || is_replay_enabled() {
+					write_samples(&vec![0.0; missing as usize * channels as usize]);
+				}
+			},
+			Message::Sync(sender) => { sender.send(()).ok(); },
+		}
+		if DROPPED.swap(false, Ordering::Relaxed) {
+			log::error("Recording fell behind the output, so some of it was dropped");
+		}
+	}
+}
+
+fn write_samples(samples: &[f32]) {
+	feed_replay(samples);
+	let mut lock = RECORDING.lock().unwrap();
+	let Some(recording) = lock.as_mut() else { return };
+	if let Err(err) = recording.write(samples) {
+		log::error(format!("Failed to write recording {}: {:?}", recording.path, err).as_str());
+		drop(lock);
+		finish_recording();
+	}
+}
+
+impl Recording {
+	fn write(&mut self, samples: &[f32]) -> Result<(), Error> {
+		match &mut self.encoder {
+			Encoder::Wav(writer) => for sample in samples {
+				writer.write_sample(*sample).map_err(Error::other)?;
+			},
+			Encoder::Ffmpeg(_, stdin) => stdin.write_all(bytemuck::cast_slice(samples))?,
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,197 @@
+use std::io::{self, Error};
+
+use hound::{SampleFormat, WavSpec, WavWriter};
+use rand::{SeedableRng, rngs::StdRng};
+
+use crate::{state::acquire, util::file::PlayableFile};
+
+// Frames mixed at a time
+const CHUNK_FRAMES: usize = 1024;
+
+// How long a dialog keeps starting clips
+pub enum RenderLength {
+	Seconds(f32),
+	Clips(usize),
+}
+
+// Renders the waveform with `id` to a WAV file, as if its hotkey was held for `seconds`, fade out included.
+// Returns the length of the file in seconds.
+pub fn render_wave(id: u32, path: &str, seconds: f32) -> Result<f32, Error> {
+	let (wave, sample_rate) = {
+		let app = acquire();
+		let wave = app.waves.iter().find(|wave| wave.id == Some(id)).cloned();
+		(wave, app.config.output_rate)
+	};
+	let Some(wave) = wave else { return Err(Error::new(io::ErrorKind::NotFound, format!("Waveform ID {} does not exist", id))) };
+	if wave.waves.is_empty() {
+		return Err(Error::new(io::ErrorKind::InvalidInput, "Waveform has no waves"));
+	}
+	let mut playable = wave.playable(sample_rate);
+	let held = (seconds.max(0.0) * sample_rate as f32) as usize;
+	let mut output = vec![];
+	loop {
+		let frame = output.len() / 2;
+		// Released
+		if frame >= held && !playable.fade.is_stopping() {
+			playable.fade.stop(wave.fade_out, sample_rate);
+		}
+		if playable.fade.is_stopped() {
+			break;
+		}
+		let frames = if frame < held { CHUNK_FRAMES.min(held - frame) } else { CHUNK_FRAMES };
+		let mut buf = vec![0.0; frames * 2];
+		playable.mix_into(&mut buf, 1.0, sample_rate);
+		output.extend(buf);
+	}
+	write_wav(path, &output, sample_rate)
+}
+
+// Renders the dialog with `id` to a WAV file, with its delay, sequential and random settings.
+// Clips are picked and panned with `seed`, or at random without one.
+// Returns the length of the file in seconds.
+pub fn render_dialog(id: u32, path: &str, length: RenderLength, seed: Option<u64>) -> Result<f32, Error> {
+	let (dialog, sample_rate, target) = {
+		let app = acquire();
+		let dialog = app.dialogs.iter().find(|dialog| dialog.id == Some(id)).cloned();
+		(dialog, app.config.output_rate, app.config.normalize.then_some(app.config.normalize_target))
+	};
+	let Some(mut dialog) = dialog else { return Err(Error::new(io::ErrorKind::NotFound, format!("Dialog ID {} does not exist", id))) };
+	if dialog.files.is_empty() {
+		return Err(Error::new(io::ErrorKind::InvalidInput, "Dialog has no files"));
+	}
+	let delay = (dialog.delay.max(0.0) * sample_rate as f32) as usize;
+	if !dialog.sequential && delay == 0 && matches!(length, RenderLength::Seconds(_)) {
+		return Err(Error::new(io::ErrorKind::InvalidInput, "Dialogs without a delay can only be rendered by clips"));
+	}
+	let mut rng = match seed {
+		Some(seed) => StdRng::seed_from_u64(seed),
+		None => StdRng::from_entropy(),
+	};
+
+	let mut voices: Vec<PlayableFile> = vec![];
+	let mut output = vec![];
+	let mut started = 0;
+	// Frame where the next clip starts. Sequential clips wait for the last one to finish.
+	let mut next_start = Some(0);
+	loop {
+		let frame = output.len() / 2;
+		if next_start.is_none() && dialog.sequential && voices.is_empty() {
+			next_start = Some(frame);
+		}
+		let more = next_start.is_some_and(|start| match length {
+			RenderLength::Seconds(seconds) => (start as f32) < seconds * sample_rate as f32,
+			RenderLength::Clips(clips) => started < clips,
+		});
+		if !more {
+			next_start = None;
+			if voices.is_empty() {
+				break;
+			}
+		} else if next_start.is_some_and(|start| start <= frame) {
+			let path = dialog.get_next_path(&mut rng).clone();
+			let options = dialog.play_options(&path, &mut rng);
+			let resampling = { acquire().config.file_resampling(&path) };
+			let Some(playable) = PlayableFile::load(&path, options, sample_rate, resampling, false) else {
+				return Err(Error::other(format!("File {} cannot be decoded", path)));
+			};
+			voices.push(playable);
+			started += 1;
+			next_start = (!dialog.sequential).then_some(frame + delay);
+			continue;
+		}
+
+		let frames = next_start.map_or(CHUNK_FRAMES, |start| CHUNK_FRAMES.min(start - frame));
+		let mut buf = vec![0.0; frames * 2];
+		for voice in voices.iter_mut() {
+			let gain = voice.gain(1.0, target);
+			voice.mix_into(&mut buf, gain);
+		}
+		voices.retain(|voice| !voice.is_finished());
+		output.extend(buf);
+	}
+	write_wav(path, &output, sample_rate)
+}
+
+fn write_wav(path: &str, samples: &[f32], sample_rate: u32) -> Result<f32, Error> {
+	let spec = WavSpec {
+		channels: 2,
+		sample_rate,
+		bits_per_sample: 32,
+		sample_format: SampleFormat::Float,
+	};
+	let mut writer = WavWriter::create(path, spec).map_err(Error::other)?;
+	for sample in samples {
+		writer.write_sample(*sample).map_err(Error::other)?;
+	}
+	writer.finalize().map_err(Error::other)?;
+	Ok(samples.len() as f32 / 2.0 / sample_rate as f32)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{f32::consts::PI, fs};
+
+	use super::*;
+	use crate::util::{audio::tests::{temp_path, write_wav}, dialog::Dialog, wave::{Wave, Waveform}};
+
+	fn read_wav(path: &str) -> (u32, Vec<f32>) {
+		let mut reader = hound::WavReader::open(path).unwrap();
+		let rate = reader.spec().sample_rate;
+		(rate, reader.samples::<f32>().map(Result::unwrap).collect())
+	}
+
+	#[test]
+	fn wave_lasts_as_long_as_it_is_held() {
+		let wave = Waveform {
+			id: Some(900001),
+			waves: vec![Wave { frequency: 1000.0, amplitude: 0.5, ..Default::default() }],
+			volume: 100,
+			pan: 0,
+			..Default::default()
+		};
+		{ acquire().waves.push(wave); }
+		let path = temp_path("render-wave");
+		let seconds = render_wave(900001, &path, 0.5).unwrap();
+		let (rate, samples) = read_wav(&path);
+		fs::remove_file(&path).ok();
+
+		assert_eq!(seconds, 0.5);
+		assert_eq!(samples.len(), rate as usize / 2 * 2);
+		// Centered, so both sides are at the wave's level
+		for (frame, pair) in samples.chunks_exact(2).enumerate().take(100) {
+			let expected = (2.0 * PI * 1000.0 * frame as f32 / rate as f32).sin() * 0.5;
+			assert!((pair[0] - expected).abs() < 1e-3 && (pair[1] - expected).abs() < 1e-3, "frame {} is {:?}, expected {}", frame, pair, expected);
+		}
+		let peak = samples.iter().fold(0.0_f32, |peak, sample| peak.max(sample.abs()));
+		assert!((peak - 0.5).abs() < 1e-3, "peak is {}", peak);
+	}
+
+	#[test]
+	fn same_seed_renders_the_same_dialog() {
+		let clip = |frequency: f32| (0..4800).flat_map(|frame| {
+			let sample = (2.0 * PI * frequency * frame as f32 / 48000.0).sin() * 0.5;
+			[sample, sample]
+		}).collect::<Vec<_>>();
+		let files = vec![write_wav("dialog-a", &clip(440.0)), write_wav("dialog-b", &clip(660.0))];
+		let dialog = Dialog {
+			id: Some(900002),
+			files: files.clone(),
+			delay: 0.05,
+			random: true,
+			pan_random: 100,
+			..Default::default()
+		};
+		{ acquire().dialogs.push(dialog); }
+		let paths = ["render-dialog-1", "render-dialog-2", "render-dialog-3"].map(temp_path);
+		for (path, seed) in paths.iter().zip([7, 7, 8]) {
+			render_dialog(900002, path, RenderLength::Clips(8), Some(seed)).unwrap();
+		}
+		let rendered = paths.each_ref().map(|path| fs::read(path).unwrap());
+		for path in paths.iter().chain(files.iter()) {
+			fs::remove_file(path).ok();
+		}
+
+		assert!(rendered[0] == rendered[1]);
+		assert!(rendered[0] != rendered[2]);
+	}
+}
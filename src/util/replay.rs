@@ -0,0 +1,102 @@
+use std::{collections::VecDeque, io::{self, Error}, path::PathBuf, sync::{LazyLock, Mutex, atomic::{AtomicU32, Ordering}}, time::{SystemTime, UNIX_EPOCH}};
+
+use hound::{SampleFormat, WavSpec, WavWriter};
+
+use crate::{config::FileEntry, constant::APP_NAME, state::{Scanning, acquire, notify_redraw}, util::{record::sync_output, tab::scan}};
+
+// Seconds of output kept. Nothing is kept when 0.
+static LENGTH: AtomicU32 = AtomicU32::new(0);
+
+// The last seconds of output, in the output's channel layout
+struct Replay {
+	samples: VecDeque<f32>,
+	sample_rate: u32,
+	channels: u16,
+}
+
+static REPLAY: LazyLock<Mutex<Replay>> = LazyLock::new(|| { Mutex::new(Replay {
+	samples: VecDeque::new(),
+	sample_rate: 48000,
+	channels: 2,
+}) });
+
+pub fn set_replay_length(seconds: u32) {
+	LENGTH.store(seconds, Ordering::Relaxed);
+	if seconds == 0 {
+		REPLAY.lock().unwrap().samples = VecDeque::new();
+	}
+}
+
+pub fn is_replay_enabled() -> bool {
+	LENGTH.load(Ordering::Relaxed) > 0
+}
+
+// Drops what was kept, for when the output format changes
+pub fn reset_replay(sample_rate: u32, channels: u16) {
+	let mut replay = REPLAY.lock().unwrap();
+	replay.samples.clear();
+	replay.sample_rate = sample_rate;
+	replay.channels = channels;
+}
+
+pub fn feed_replay(samples: &[f32]) {
+	let length = LENGTH.load(Ordering::Relaxed);
+	if length == 0 {
+		return;
+	}
+	let mut replay = REPLAY.lock().unwrap();
+	let capacity = length as usize * replay.sample_rate as usize * replay.channels as usize;
+	replay.samples.extend(samples);
+	let excess = replay.samples.len().saturating_sub(capacity);
+	replay.samples.drain(..excess);
+}
+
+// Writes the replay buffer to a WAV file at `path`.
+// Without a path, it goes into the replay tab with the next free ID, or the audio directory if there is no replay tab.
+// Returns the path and the ID. Must not be called with the app locked.
+pub fn save_replay(path: Option<String>) -> Result<(String, Option<u32>), Error> {
+	// Include the output still on its way to the buffer
+	sync_output();
+	let (samples, spec) = {
+		let replay = REPLAY.lock().unwrap();
+		let spec = WavSpec {
+			channels: replay.channels,
+			sample_rate: replay.sample_rate,
+			bits_per_sample: 32,
+			sample_format: SampleFormat::Float,
+		};
+		(replay.samples.iter().copied().collect::<Vec<_>>(), spec)
+	};
+	if samples.is_empty() {
+		return Err(Error::new(io::ErrorKind::NotFound, "Nothing to replay"));
+	}
+	let (path, tab) = match path {
+		Some(path) => (path, None),
+		None => {
+			let app = acquire();
+			let tab = app.config.tabs.iter().position(|tab| *tab == app.config.replay_tab);
+			let dir = match tab {
+				Some(_) => PathBuf::from(&app.config.replay_tab),
+				None => dirs::audio_dir().or_else(dirs::home_dir).unwrap_or_default(),
+			};
+			let time = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+			(dir.join(format!("{}-replay-{}.wav", APP_NAME, time)).to_string_lossy().to_string(), tab)
+		}
+	};
+
+	let mut writer = WavWriter::create(&path, spec).map_err(Error::other)?;
+	for sample in samples {
+		writer.write_sample(sample).map_err(Error::other)?;
+	}
+	writer.finalize().map_err(Error::other)?;
+
+	let Some(tab) = tab else { return Ok((path, None)) };
+	let mut app = acquire();
+	let id = (1..).find(|id| !app.file_ids.contains_key(id)).unwrap();
+	app.config.insert_file_entry(path.clone(), FileEntry { id: Some(id), ..FileEntry::default() });
+	app.file_ids.insert(id, path.clone());
+	drop(app);
+	scan(Scanning::One(tab));
+	notify_redraw();
+	Ok((path, Some(id)))
+}
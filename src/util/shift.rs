@@ -0,0 +1,234 @@
+use std::{collections::VecDeque, f32::consts::PI};
+
+use crate::util::fade::ms_to_frames;
+
+// Length of the grains overlapped when time-stretching
+const GRAIN_MS: u32 = 40;
+
+// Changes the speed and pitch of a voice as it plays.
+// Resampling changes both together (varispeed). With time-stretching on, only the pitch is resampled,
+// and grains of the resampled audio are overlapped to bring the tempo to the speed.
+pub struct Shifter {
+	// Source frames consumed per resampled frame
+	step: f64,
+	// Interleaved source frames waiting to be resampled
+	input: Vec<f32>,
+	// Read position into `input`, in frames
+	phase: f64,
+	stretcher: Option<Stretcher>,
+	// Whether the last call ran out of source audio
+	dry: bool,
+}
+
+impl Shifter {
+	// `speed` multiplies the tempo, and `pitch` is in semitones.
+	// Returns None if the voice plays as is.
+	pub fn new(speed: f32, pitch: i32, stretch: bool, sample_rate: u32) -> Option<Self> {
+		let speed = speed as f64;
+		let pitch = 2_f64.powf(pitch as f64 / 12.0);
+		if speed == 1.0 && pitch == 1.0 {
+			return None;
+		}
+		let (step, stretcher) = if stretch {
+			let factor = speed / pitch;
+			(pitch, (factor != 1.0).then(|| Stretcher::new(factor, sample_rate)))
+		} else {
+			(speed * pitch, None)
+		};
+		Some(Self {
+			step,
+			input: vec![],
+			phase: 0.0,
+			stretcher,
+			dry: false,
+		})
+	}
+
+	// Source frames played per output frame
+	pub fn tempo(&self) -> f64 {
+		self.step * self.stretcher.as_ref().map_or(1.0, |stretcher| stretcher.factor)
+	}
+
+	// Drops buffered audio, for when the source position jumps
+	pub fn reset(&mut self) {
+		self.input.clear();
+		self.phase = 0.0;
+		self.dry = false;
+		if let Some(stretcher) = self.stretcher.as_mut() {
+			stretcher.reset();
+		}
+	}
+
+	// Fills `out` with interleaved stereo, pulling source samples through `read`.
+	// Returns the number of samples written, which is less than requested when the source runs dry.
+	pub fn process(&mut self, out: &mut [f32], mut read: impl FnMut(&mut [f32]) -> usize) -> usize {
+		let written = match self.stretcher.take() {
+			Some(mut stretcher) => {
+				let written = stretcher.process(out, |buf| self.resample(buf, &mut read));
+				self.stretcher = Some(stretcher);
+				written
+			},
+			None => self.resample(out, &mut read),
+		};
+		self.dry = written < out.len();
+		written
+	}
+
+	// Whether everything buffered has been played, once the source has ended
+	pub fn is_dry(&self) -> bool {
+		self.dry
+	}
+
+	// Cubic interpolation through the source at `step` frames per output frame
+	fn resample(&mut self, out: &mut [f32], read: &mut impl FnMut(&mut [f32]) -> usize) -> usize {
+		let frames = out.len() / 2;
+		// Interpolation looks up to two frames ahead
+		let needed = (self.phase + frames as f64 * self.step).ceil() as usize + 3;
+		let buffered = self.input.len();
+		if needed * 2 > buffered {
+			self.input.resize(needed * 2, 0.0);
+			let read = read(&mut self.input[buffered..]);
+			self.input.truncate(buffered + read - read % 2);
+		}
+		let available = self.input.len() / 2;
+		let mut written = 0;
+		while written < frames {
+			let index = self.phase as usize;
+			if index + 2 >= available {
+				break;
+			}
+			let t = (self.phase - index as f64) as f32;
+			for channel in 0..2 {
+				let sample = |frame: usize| self.input[frame * 2 + channel];
+				out[written * 2 + channel] = cubic(sample(index.saturating_sub(1)), sample(index), sample(index + 1), sample(index + 2), t);
+			}
+			written += 1;
+			self.phase += self.step;
+		}
+		// Keep one frame behind the position for the next interpolation
+		let consumed = (self.phase as usize).saturating_sub(1).min(available);
+		self.input.drain(..consumed * 2);
+		self.phase -= consumed as f64;
+		written * 2
+	}
+}
+
+fn cubic(y0: f32, y1: f32, y2: f32, y3: f32, t: f32) -> f32 {
+	let a = -0.5 * y0 + 1.5 * y1 - 1.5 * y2 + 0.5 * y3;
+	let b = y0 - 2.5 * y1 + 2.0 * y2 - 0.5 * y3;
+	let c = -0.5 * y0 + 0.5 * y2;
+	((a * t + b) * t + c) * t + y1
+}
+
+// Changes the tempo without the pitch by overlapping windowed grains (WSOLA).
+// Each grain starts where it best lines up with how the previous one continues, within a tolerance.
+struct Stretcher {
+	// Input frames consumed per output frame
+	factor: f64,
+	grain: usize,
+	hop: usize,
+	tolerance: usize,
+	window: Vec<f32>,
+	// Interleaved input, starting at the oldest frame still needed
+	input: Vec<f32>,
+	// Where the next grain would start without alignment, in frames into `input`
+	nominal: f64,
+	// Where the previous grain would have continued, in frames into `input`
+	continuation: Option<usize>,
+	// Silent frames at the end of `input`, added once the source ran dry
+	padding: usize,
+	// Output still receiving grains
+	overlap: Vec<f32>,
+	ready: VecDeque<f32>,
+}
+
+impl Stretcher {
+	fn new(factor: f64, sample_rate: u32) -> Self {
+		let grain = ms_to_frames(GRAIN_MS, sample_rate).max(16) & !1;
+		let hop = grain / 2;
+		Self {
+			factor,
+			grain,
+			hop,
+			tolerance: grain / 8,
+			// Periodic Hann, which sums to 1 at half overlap
+			window: (0..grain).map(|ii| 0.5 - 0.5 * (2.0 * PI * ii as f32 / grain as f32).cos()).collect(),
+			input: vec![],
+			nominal: 0.0,
+			continuation: None,
+			padding: 0,
+			overlap: vec![0.0; grain * 2],
+			ready: VecDeque::new(),
+		}
+	}
+
+	fn reset(&mut self) {
+		self.input.clear();
+		self.nominal = 0.0;
+		self.continuation = None;
+		self.padding = 0;
+		self.overlap.fill(0.0);
+		self.ready.clear();
+	}
+
+	fn process(&mut self, out: &mut [f32], mut read: impl FnMut(&mut [f32]) -> usize) -> usize {
+		while self.ready.len() < out.len() {
+			let nominal = self.nominal.round() as usize;
+			let needed = (nominal + self.tolerance).max(self.continuation.unwrap_or(0)) + self.grain;
+			while self.input.len() < needed * 2 {
+				let buffered = self.input.len();
+				self.input.resize(needed * 2, 0.0);
+				let read = read(&mut self.input[buffered..]);
+				self.input.truncate(buffered + read - read % 2);
+				if read == 0 {
+					// Finish the grains still covering audio over silence
+					if nominal + self.padding < self.input.len() / 2 {
+						self.padding += needed - self.input.len() / 2;
+						self.input.resize(needed * 2, 0.0);
+					}
+					break;
+				}
+			}
+			if self.input.len() < needed * 2 {
+				break;
+			}
+
+			let start = match self.continuation {
+				Some(continuation) => self.align(nominal, continuation),
+				None => nominal,
+			};
+			for ii in 0..self.grain {
+				let gain = self.window[ii];
+				self.overlap[ii * 2] += self.input[(start + ii) * 2] * gain;
+				self.overlap[ii * 2 + 1] += self.input[(start + ii) * 2 + 1] * gain;
+			}
+			// Nothing else overlaps the first hop, so it is final
+			self.ready.extend(self.overlap.drain(..self.hop * 2));
+			self.overlap.resize(self.grain * 2, 0.0);
+
+			self.nominal += self.hop as f64 * self.factor;
+			let consumed = (self.nominal as usize).saturating_sub(self.tolerance).min(start + self.hop);
+			self.input.drain(..consumed * 2);
+			self.padding = self.padding.min(self.input.len() / 2);
+			self.nominal -= consumed as f64;
+			self.continuation = Some(start + self.hop - consumed);
+		}
+		let written = out.len().min(self.ready.len());
+		for (out, sample) in out.iter_mut().zip(self.ready.drain(..written)) {
+			*out = sample;
+		}
+		written
+	}
+
+	// Finds the start near `nominal` that best matches the audio at `target`
+	fn align(&self, nominal: usize, target: usize) -> usize {
+		let mono = |frame: usize| self.input[frame * 2] + self.input[frame * 2 + 1];
+		let length = self.grain - self.hop;
+		// Every other frame is enough to line up the waveforms
+		let score = |start: usize| (0..length).step_by(2).map(|ii| mono(start + ii) * mono(target + ii)).sum::<f32>();
+		(nominal.saturating_sub(self.tolerance)..=nominal + self.tolerance)
+			.map(|start| (start, score(start)))
+			.max_by(|(_, a), (_, b)| a.total_cmp(b))
+			.map_or(nominal, |(start, _)| start)
+	}
+}
@@ -0,0 +1,248 @@
+use std::{collections::VecDeque, io::Read, num::NonZeroUsize, process::{Child, Command, Stdio}, sync::{Arc, Condvar, Mutex}, thread, time::Duration};
+
+use symphonium::{ProbedAudioSource, ResampleQuality, resample::fixed_resample::{FixedResampler, LastPacketInfo}, symphonia::{core::{audio::Channels, codecs::DecoderOptions, errors::Error as SymphoniaError}, default::get_codecs}};
+
+use crate::{component::block::log, constant::ENDIANESS, util::channels::Downmix};
+
+// How many seconds of decoded audio are kept ahead of the mixer
+const BUFFER_SECONDS: usize = 2;
+
+struct StreamQueue {
+	samples: VecDeque<f32>,
+	capacity: usize,
+	// Samples still to be dropped before buffering, for trimmed starts
+	skip: usize,
+	finished: bool,
+	cancelled: bool,
+}
+
+// A ring buffer of interleaved stereo samples filled by a decoder thread
+pub struct StreamBuffer {
+	queue: Mutex<StreamQueue>,
+	cvar: Condvar,
+	// Expected number of samples, if the source reports its length
+	pub length: Option<usize>,
+}
+
+impl StreamBuffer {
+	fn new(sample_rate: u32, length: Option<usize>, skip: usize) -> Self {
+		let capacity = sample_rate as usize * 2 * BUFFER_SECONDS;
+		Self {
+			queue: Mutex::new(StreamQueue {
+				samples: VecDeque::with_capacity(capacity),
+				capacity,
+				skip,
+				finished: false,
+				cancelled: false,
+			}),
+			cvar: Condvar::new(),
+			length,
+		}
+	}
+
+	// Blocks the decoder while the buffer is full. Returns false if the stream was cancelled.
+	fn push(&self, samples: &[f32]) -> bool {
+		let mut offset = {
+			let mut queue = self.queue.lock().unwrap();
+			if queue.cancelled {
+				return false;
+			}
+			let skipped = queue.skip.min(samples.len());
+			queue.skip -= skipped;
+			skipped
+		};
+		while offset < samples.len() {
+			let mut queue = self.queue.lock().unwrap();
+			while !queue.cancelled && queue.samples.len() >= queue.capacity {
+				queue = self.cvar.wait(queue).unwrap();
+			}
+			if queue.cancelled {
+				return false;
+			}
+			let count = (queue.capacity - queue.samples.len()).min(samples.len() - offset);
+			queue.samples.extend(&samples[offset..offset + count]);
+			offset += count;
+			self.cvar.notify_all();
+		}
+		true
+	}
+
+	fn finish(&self) {
+		let mut queue = self.queue.lock().unwrap();
+		queue.finished = true;
+		self.cvar.notify_all();
+	}
+
+	pub fn cancel(&self) {
+		let mut queue = self.queue.lock().unwrap();
+		queue.cancelled = true;
+		self.cvar.notify_all();
+	}
+
+	// Waits until the first chunk is decoded, or the timeout is reached
+	pub fn wait_ready(&self, timeout: Duration) {
+		let queue = self.queue.lock().unwrap();
+		drop(self.cvar.wait_timeout_while(queue, timeout, |queue| queue.samples.is_empty() && !queue.finished).unwrap());
+	}
+
+	// Moves buffered samples into `buf`. Never blocks on the decoder.
+	pub fn read_into(&self, buf: &mut [f32]) -> usize {
+		let mut queue = self.queue.lock().unwrap();
+		let count = buf.len().min(queue.samples.len());
+		for (sample, decoded) in buf.iter_mut().zip(queue.samples.drain(..count)) {
+			*sample = decoded;
+		}
+		self.cvar.notify_all();
+		count
+	}
+
+	// Drops the next `count` samples, including ones not decoded yet
+	pub fn skip(&self, count: usize) {
+		let mut queue = self.queue.lock().unwrap();
+		let buffered = count.min(queue.samples.len());
+		queue.samples.drain(..buffered);
+		queue.skip += count - buffered;
+		self.cvar.notify_all();
+	}
+
+	pub fn is_drained(&self) -> bool {
+		let queue = self.queue.lock().unwrap();
+		queue.finished && queue.samples.is_empty()
+	}
+}
+
+// `skip` is the number of samples to drop from the start
+pub fn stream_symphonia(path: &str, mut probed: ProbedAudioSource, sample_rate: u32, quality: ResampleQuality, skip: usize) -> Arc<StreamBuffer> {
+	let frames = probed.probe_result().format.default_track().and_then(|track| track.codec_params.n_frames);
+	let length = match (frames, probed.sample_rate()) {
+		(Some(frames), Some(rate)) => Some((frames * sample_rate as u64 / rate.get() as u64) as usize * 2),
+		_ => None
+	};
+	let stream = Arc::new(StreamBuffer::new(sample_rate, length, skip));
+	let writer = stream.clone();
+	let path = path.to_string();
+	thread::spawn(move || {
+		if let Err(err) = decode_symphonia(&mut probed, Some((sample_rate, quality)), |samples| writer.push(samples)) {
+			log::error(format!("Failed to stream {}", path).as_str());
+			log::error(format!("{:?}", err).as_str());
+		}
+		writer.finish();
+	});
+	stream
+}
+
+// Decodes packet by packet into interleaved stereo, resampled if `resample` is given.
+// Sources with more channels are mixed down.
+// Stops early when `push` returns false.
+pub fn decode_symphonia(probed: &mut ProbedAudioSource, resample: Option<(u32, ResampleQuality)>, mut push: impl FnMut(&[f32]) -> bool) -> Result<(), SymphoniaError> {
+	let source_rate = probed.sample_rate().map(|rate| rate.get());
+	let format = &mut probed.probe_result_mut().format;
+	let Some(track) = format.default_track() else { return Ok(()) };
+	let track_id = track.id;
+	let mut decoder = get_codecs().make(&track.codec_params, &DecoderOptions::default())?;
+	let mut resampler = match (source_rate, resample) {
+		(Some(source_rate), Some((sample_rate, quality))) if source_rate != sample_rate => {
+			Some(FixedResampler::<f32, 2>::new(NonZeroUsize::new(2).unwrap(), source_rate, sample_rate, quality, false))
+		},
+		_ => None
+	};
+	let mut interleaved = vec![];
+	// Rebuilt if the layout changes between packets
+	let mut downmix: Option<(Channels, Downmix)> = None;
+	let (mut left, mut right) = (vec![], vec![]);
+
+	loop {
+		let packet = match format.next_packet() {
+			Ok(packet) => packet,
+			Err(SymphoniaError::IoError(err)) if err.kind() == std::io::ErrorKind::UnexpectedEof => break,
+			Err(SymphoniaError::ResetRequired) => break,
+			Err(err) => return Err(err),
+		};
+		if packet.track_id() != track_id {
+			continue;
+		}
+		let decoded = match decoder.decode(&packet) {
+			Ok(decoded) => decoded,
+			Err(SymphoniaError::DecodeError(_)) => continue,
+			Err(err) => return Err(err),
+		};
+		let mut planar = decoded.make_equivalent::<f32>();
+		decoded.convert(&mut planar);
+		let channels = planar.spec().channels;
+		if downmix.as_ref().is_none_or(|(layout, _)| *layout != channels) {
+			downmix = Some((channels, Downmix::new(Some(channels), channels.count())));
+		}
+		if let Some((_, downmix)) = downmix.as_ref() {
+			downmix.apply(planar.planes().planes(), &mut left, &mut right);
+		}
+
+		interleaved.clear();
+		if let Some(resampler) = resampler.as_mut() {
+			resampler.process(&[&left, &right], 0..left.len(), |packet| {
+				interleaved.extend(packet[0].iter().zip(packet[1].iter()).flat_map(|(l, r)| [*l, *r]));
+			}, None, false);
+		} else {
+			interleaved.extend(left.iter().zip(right.iter()).flat_map(|(l, r)| [*l, *r]));
+		}
+		if !push(&interleaved) {
+			return Ok(());
+		}
+	}
+
+	if let Some(resampler) = resampler.as_mut() {
+		interleaved.clear();
+		let empty: [&[f32]; 2] = [&[], &[]];
+		resampler.process(&empty, 0..0, |packet| {
+			interleaved.extend(packet[0].iter().zip(packet[1].iter()).flat_map(|(l, r)| [*l, *r]));
+		}, Some(LastPacketInfo { desired_output_frames: None }), false);
+		push(&interleaved);
+	}
+	Ok(())
+}
+
+fn spawn_ffmpeg(path: &str, sample_rate: u32) -> Result<Child, std::io::Error> {
+	Command::new("ffmpeg").args([
+		"-loglevel", "-8",
+		"-i", path,
+		"-f", format!("f32{}", ENDIANESS).as_str(),
+		"-ac", "2",
+		"-ar", sample_rate.to_string().as_str(),
+		"-"
+	]).stdout(Stdio::piped()).spawn()
+}
+
+// Reads ffmpeg's output in chunks until it ends, or until `push` returns false
+fn read_ffmpeg(mut child: Child, mut push: impl FnMut(&[f32]) -> bool) {
+	let mut stdout = child.stdout.take().unwrap();
+	let mut bytes = vec![0_u8; 16384];
+	let mut pending = 0;
+	while let Ok(read) = stdout.read(&mut bytes[pending..]) && read > 0 {
+		let available = pending + read;
+		let usable = available - available % 4;
+		let samples = bytes[..usable].chunks(4).map(|chunk| f32::from_ne_bytes(chunk.try_into().unwrap())).collect::<Vec<_>>();
+		bytes.copy_within(usable..available, 0);
+		pending = available - usable;
+		if !push(&samples) {
+			child.kill().ok();
+			break;
+		}
+	}
+	child.wait().ok();
+}
+
+pub fn decode_ffmpeg(path: &str, sample_rate: u32, push: impl FnMut(&[f32]) -> bool) -> Result<(), std::io::Error> {
+	read_ffmpeg(spawn_ffmpeg(path, sample_rate)?, push);
+	Ok(())
+}
+
+pub fn stream_ffmpeg(path: &str, sample_rate: u32, duration: Option<Duration>, skip: usize) -> Result<Arc<StreamBuffer>, std::io::Error> {
+	let child = spawn_ffmpeg(path, sample_rate)?;
+	let length = duration.map(|duration| (duration.as_secs_f64() * sample_rate as f64) as usize * 2);
+	let stream = Arc::new(StreamBuffer::new(sample_rate, length, skip));
+	let writer = stream.clone();
+	thread::spawn(move || {
+		read_ffmpeg(child, |samples| writer.push(samples));
+		writer.finish();
+	});
+	Ok(stream)
+}
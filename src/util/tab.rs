@@ -5,7 +5,7 @@ use mime_guess::mime;
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 use symphonium::{ResampleQuality, SymphoniumLoader};
 
-use crate::{component::block::{BlockSingleton, files::FilesBlock, log, tabs::TabsBlock}, state::{Scanning, acquire, notify_redraw}, util::file::read_file_ffmpeg};
+use crate::{component::block::{BlockSingleton, files::FilesBlock, log, tabs::TabsBlock}, state::{Scanning, acquire, notify_redraw}, util::{file::read_file_ffmpeg, loudness::{measure_loudness, save_loudness_cache}}};
 
 fn ffprobe_duration(path: &str) -> Option<u128> {
 	let Ok(info) = ffprobe::ffprobe(path) else { return None };
@@ -29,15 +29,22 @@ fn add_duration(tab: String) {
 		}
 		let files = files.unwrap().clone();
 		let sample_rate = app.sample_rate;
+		let normalize = app.config.normalize;
 		drop(app);
 		let mut loader = SymphoniumLoader::new();
 		let mut new_files = vec![];
+		let mut measured = false;
 		for (filename, _) in &files {
 			let longpath = Path::new(&tab).join(filename);
 			let filepath = longpath.into_os_string().into_string().unwrap();
 
+			if normalize {
+				measured |= measure_loudness(&filepath, &loader, sample_rate);
+			}
+
 			let result = ffprobe_duration(&filepath);
 			let millis: u128 = if result.is_none() {
+				// Probed at the source rate, so the cheap resampler is enough
 				let result = loader.load(&filepath, None, ResampleQuality::Low, None);
 				if result.is_err() {
 					let result = read_file_ffmpeg(&filepath, sample_rate);
@@ -45,7 +52,7 @@ fn add_duration(tab: String) {
 						new_files.push((filename.clone(), String::new()));
 						continue;
 					}
-					result.unwrap().len() as u128 / 48
+					result.unwrap().len() as u128 * 500 / sample_rate as u128
 				} else {
 					let audio_data = result.unwrap();
 					audio_data.frames() as u128 * 1000 / audio_data.sample_rate().get() as u128
@@ -83,6 +90,9 @@ fn add_duration(tab: String) {
 		}
 		acquire().files.insert(tab, new_files);
 		notify_redraw();
+		if measured {
+			save_loudness_cache();
+		}
 	});
 }
 
@@ -1,11 +1,11 @@
-use std::{collections::{HashMap, HashSet}, sync::{Arc, LazyLock, Mutex, MutexGuard}, thread, time::Duration};
+use std::{collections::{HashMap, HashSet}, f32::consts::PI, sync::{Arc, LazyLock, Mutex, MutexGuard}, thread, time::Duration};
 
 use mki::Keyboard;
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::{config::WaveformEntry, state::{acquire, notify_redraw}, util::keyboard::keyboard_to_string};
+use crate::{config::WaveformEntry, state::{acquire, notify_redraw}, util::{audio::linear_to_logarithmic, choke::{Choker, choke_group}, fade::Fade, keyboard::keyboard_to_string, pan::pan_gains}};
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Default, Clone, Copy)]
 #[serde(rename_all = "lowercase")]
@@ -44,6 +44,10 @@ pub struct Waveform {
 	pub keys: Vec<Keyboard>,
 	pub waves: Vec<Wave>,
 	pub volume: u32,
+	pub fade_in: u32,
+	pub fade_out: u32,
+	pub group: Option<String>,
+	pub pan: i32,
 	pub playing: Arc<Mutex<(bool, bool)>>,
 }
 
@@ -56,6 +60,10 @@ impl Default for Waveform {
 			keys: vec![],
 			waves: vec![Wave::default()],
 			volume: 100,
+			fade_in: 0,
+			fade_out: 0,
+			group: Option::None,
+			pan: 0,
 			playing: Arc::new(Mutex::new((false, false)))
 		}
 	}
@@ -68,7 +76,11 @@ impl Waveform {
 			id: self.id,
 			keys: self.keys.par_iter().map(|key| { keyboard_to_string(*key) }).collect::<HashSet<String>>(),
 			waves: self.waves.clone(),
-			volume: self.volume
+			volume: self.volume,
+			fade_in: self.fade_in,
+			fade_out: self.fade_out,
+			group: self.group.clone(),
+			pan: self.pan,
 		}
 	}
 
@@ -80,6 +92,24 @@ impl Waveform {
 		}
 	}
 
+	pub fn playable(&self, sample_rate: u32) -> PlayableWaveform {
+		let waves = self.waves.par_iter().map(|w| {
+			PlayableWave {
+				wave_type: w.wave_type,
+				period: 1.0 / w.frequency,
+				phase: w.phase / w.frequency,
+				amplitude: w.amplitude,
+				volume: self.volume as f32 / 100.0
+			}
+		}).collect::<Vec<PlayableWave>>();
+		PlayableWaveform {
+			waves,
+			fade: Fade::new(self.fade_in, self.fade_out, sample_rate),
+			group: self.group.clone(),
+			pan: pan_gains(self.pan)
+		}
+	}
+
 	pub fn play(&self, auto_stop: bool) {
 		let wave = self.clone();
 		thread::spawn(move || {
@@ -101,32 +131,36 @@ impl Waveform {
 			playing.0 = true;
 			drop(playing);
 
-			let playable = wave.waves.par_iter().map(|w| {
-				PlayableWave {
-					wave_type: w.wave_type,
-					period: 1.0 / w.frequency,
-					phase: w.phase / w.frequency,
-					amplitude: w.amplitude,
-					volume: wave.volume as f32 / 100.0
-				}
-			}).collect::<Vec<PlayableWave>>();
+			let sample_rate = app.sample_rate;
 			app.playing_wave.insert(uuid, format!("{} ({})", wave.label, wave.details()));
-			acquire_playing_waves().insert(uuid, playable);
 			drop(app);
+			if let Some(group) = &wave.group {
+				choke_group(group, Some(Choker::Wave(wave.uuid)), sample_rate);
+			}
+			acquire_playing_waves().insert(uuid, wave.playable(sample_rate));
 			notify_redraw();
 
 			if auto_stop {
 				thread::sleep(Duration::from_secs(1));
 			} else {
-				while {
+				// Stop early if another sound in the group choked this one
+				while !acquire_playing_waves().get(&uuid).is_some_and(|playable| playable.fade.is_stopping()) && ({
 					let (playing, force) = *wave.playing.lock().unwrap();
 					playing && force
-				} || wave.keys.par_iter().all(|key| { key.is_pressed() }) {
+				} || wave.keys.par_iter().all(|key| { key.is_pressed() })) {
 					thread::sleep(Duration::from_millis(100));
 				}
 			}
 			wave.playing.lock().unwrap().0 = false;
 
+			// Let the fade out finish before removing the wave, unless a global stop already started one
+			if let Some(playable) = acquire_playing_waves().get_mut(&uuid) && !playable.fade.is_stopping() {
+				playable.fade.stop(wave.fade_out, sample_rate);
+			}
+			while acquire_playing_waves().get(&uuid).is_some_and(|playable| !playable.fade.is_stopped()) {
+				thread::sleep(Duration::from_millis(10));
+			}
+
 			acquire().playing_wave.remove(&uuid);
 			acquire_playing_waves().remove(&uuid);
 			notify_redraw();
@@ -142,15 +176,65 @@ pub struct PlayableWave {
 	pub volume: f32,
 }
 
-static PLAYING_WAVES: LazyLock<Mutex<HashMap<Uuid, Vec<PlayableWave>>>> = LazyLock::new(|| { Mutex::new(HashMap::new()) });
+pub struct PlayableWaveform {
+	pub waves: Vec<PlayableWave>,
+	pub fade: Fade,
+	pub group: Option<String>,
+	// Left and right gains
+	pub pan: (f32, f32),
+}
 
-pub fn acquire_playing_waves() -> MutexGuard<'static, HashMap<Uuid, Vec<PlayableWave>>> {
+impl PlayableWave {
+	// Sample at the current phase, then steps the phase by a frame
+	fn next(&mut self, sample_rate: u32) -> f32 {
+		let sample = match self.wave_type {
+			WaveType::Sine => (PI * 2.0 * self.phase).sin(),
+			WaveType::Square => if self.phase > 0.5 { 1.0 } else { -1.0 },
+			WaveType::Triangle => {
+				let portion = self.phase;
+				if portion > 0.5 {
+					-1.0 + (portion - 0.5) * 4.0
+				} else {
+					1.0 - portion * 4.0
+				}
+			},
+			WaveType::Saw => -1.0 + self.phase * 2.0,
+		} * self.amplitude;
+		self.phase += (1.0 / sample_rate as f32) / self.period;
+		if self.phase >= 1.0 {
+			self.phase -= 1.0;
+		}
+		sample
+	}
+}
+
+impl PlayableWaveform {
+	// Adds the next frames of every wave to `buf`, with `volume` being the master volume
+	pub fn mix_into(&mut self, buf: &mut [f32], volume: f32, sample_rate: u32) {
+		let len = self.waves.len() as f32;
+		let (left, right) = self.pan;
+		for frame in buf.chunks_exact_mut(2) {
+			let sample = self.waves.iter_mut().map(|wave| wave.next(sample_rate) * linear_to_logarithmic(wave.volume * volume)).sum::<f32>();
+			let gain = self.fade.next(None) / len;
+			frame[0] += sample * gain * left;
+			frame[1] += sample * gain * right;
+		}
+	}
+}
+
+static PLAYING_WAVES: LazyLock<Mutex<HashMap<Uuid, PlayableWaveform>>> = LazyLock::new(|| { Mutex::new(HashMap::new()) });
+
+pub fn acquire_playing_waves() -> MutexGuard<'static, HashMap<Uuid, PlayableWaveform>> {
 	PLAYING_WAVES.lock().unwrap()
 }
 
-pub fn stop_all_waves() {
+pub fn stop_all_waves(fade: u32) {
 	// Defer to avoid deadlock
 	thread::spawn(move || {
+		let sample_rate = { acquire().sample_rate };
+		for playable in acquire_playing_waves().values_mut() {
+			playable.fade.stop(fade, sample_rate);
+		}
 		let app = acquire();
 		app.waves.par_iter().for_each(|wave| {
 			let mut playing = wave.playing.lock().expect("Failed to lock mutex");